@@ -46,7 +46,7 @@ pub(crate) fn new() -> Item {
             fn insert "si" {
                 /// Key of the entry.
                 ///
-                /// This must be smaller than 4096.
+                /// This must be smaller than 256.
                 key: usize,
 
                 /// Value of the entry.
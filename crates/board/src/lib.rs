@@ -57,6 +57,16 @@ pub trait Api {
     /// available, this function blocks and enters a power-saving state until an event triggers.
     fn wait_event() -> Event<Self>;
 
+    /// Returns the public key verifying applets, if any.
+    ///
+    /// The key is a P-256 public key encoded as its big-endian affine coordinates x then y. When
+    /// the board provides a key, applets must be signed with it and the board must support P-256
+    /// and SHA-256. Otherwise, the scheduler refuses all applets unless explicitly configured to
+    /// run unsigned applets.
+    fn applet_key() -> Option<[u8; 64]> {
+        None
+    }
+
     type Button: button::Api;
     type Crypto: crypto::Api;
     type Debug: debug::Api;
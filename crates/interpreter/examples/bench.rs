@@ -0,0 +1,121 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compares the execution time of the tagged and untagged value representations.
+//!
+//! Run this example once with and once without the `untagged` feature:
+//!
+//!     cargo run --release --example=bench
+//!     cargo run --release --example=bench --features=untagged
+//!
+//! The spec workloads can be compared the same way by timing the `spec` test:
+//!
+//!     cargo test --release --test=spec --features=float-types,vector-types[,untagged]
+
+use std::time::{Duration, Instant};
+
+use wasefire_interpreter::*;
+
+/// Computes the sum of the integers below its argument in a loop.
+const LOOP: &str = r#"(module
+  (func (export "main") (param $n i32) (result i64)
+    (local $s i64)
+    (block $exit
+      (loop $loop
+        (br_if $exit (i32.eqz (local.get $n)))
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        (local.set $s (i64.add (local.get $s) (i64.extend_i32_u (local.get $n))))
+        (br $loop)))
+    (local.get $s)))"#;
+
+/// Computes the Fibonacci number of its argument recursively.
+const FIB: &str = r#"(module
+  (func $fib (export "main") (param $n i32) (result i64)
+    (if (result i64) (i32.lt_u (local.get $n) (i32.const 2))
+      (then (i64.extend_i32_u (local.get $n)))
+      (else (i64.add (call $fib (i32.sub (local.get $n) (i32.const 1)))
+                     (call $fib (i32.sub (local.get $n) (i32.const 2))))))))"#;
+
+/// Fills the memory with its argument and sums it back byte by byte.
+const MEMORY: &str = r#"(module
+  (memory 1)
+  (func (export "main") (param $x i32) (result i64)
+    (local $i i32) (local $s i64)
+    (memory.fill (i32.const 0) (local.get $x) (i32.const 0x10000))
+    (block $exit
+      (loop $loop
+        (br_if $exit (i32.eq (local.get $i) (i32.const 0x10000)))
+        (local.set $s (i64.add (local.get $s) (i64.load8_u (local.get $i))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $loop)))
+    (local.get $s)))"#;
+
+#[repr(align(16))]
+struct Memory([u8; 0x10000]);
+
+fn main() {
+    println!("untagged: {}", cfg!(feature = "untagged"));
+    bench_hello(10000);
+    bench_wat("loop", LOOP, 1000000, 499999500000);
+    bench_wat("fib", FIB, 25, 75025);
+    bench_wat("memory", MEMORY, 3, 3 * 0x10000);
+}
+
+fn bench_hello(count: usize) {
+    let mut linker = Linker::default();
+    linker.link_func("env", "println", 2, 0).unwrap();
+    let mut memory = Box::new(Memory([0; 0x10000]));
+    let start = Instant::now();
+    for _ in 0 .. count {
+        let module = Module::new(include_bytes!("hello.wasm")).unwrap();
+        let mut store = Store::new(&linker);
+        let inst = store.instantiate(module, vec![&mut memory.0[.. 5]]).unwrap();
+        let mut result = store.invoke(inst, "main", vec![]).unwrap();
+        while let RunResult::Host(call) = result {
+            result = call.resume(&[]).unwrap();
+        }
+    }
+    report("hello", start.elapsed());
+}
+
+fn bench_wat(name: &str, wat: &str, arg: u32, expected: u64) {
+    let wasm = wat_to_wasm(wat);
+    let module = Module::new(&wasm).unwrap();
+    let linker = Linker::default();
+    let mut store = Store::new(&linker);
+    let mut memory = Box::new(Memory([0; 0x10000]));
+    let memories = match wat.contains("(memory") {
+        true => vec![&mut memory.0[..]],
+        false => vec![],
+    };
+    let inst = store.instantiate(module, memories).unwrap();
+    let start = Instant::now();
+    let result = store.invoke(inst, "main", vec![Val::I32(arg)]).unwrap();
+    let elapsed = start.elapsed();
+    match result {
+        RunResult::Done(x) => assert_eq!(x, [Val::I64(expected)]),
+        _ => unreachable!(),
+    }
+    report(name, elapsed);
+}
+
+fn wat_to_wasm(wat: &str) -> Vec<u8> {
+    let buffer = wast::parser::ParseBuffer::new(wat).unwrap();
+    let mut wat = wast::parser::parse::<wast::Wat>(&buffer).unwrap();
+    wat.encode().unwrap()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!("{name:>8}: {:>10.3}ms", elapsed.as_secs_f64() * 1000.);
+}
@@ -15,13 +15,14 @@
 use wasefire_interpreter::*;
 
 fn main() {
-    // Create an empty store.
-    let mut store = Store::default();
+    // Link a "println" function in module "env". This function takes 2 arguments and doesn't
+    // return anything. The 2 arguments are the pointer and length of a buffer in the calling module
+    // memory. This buffer is printed as a string.
+    let mut linker = Linker::default();
+    linker.link_func("env", "println", 2, 0).unwrap();
 
-    // Link a "println" function in module "env" in the store. This function takes 2 arguments and
-    // doesn't return anything. The 2 arguments are the pointer and length of a buffer in the
-    // calling module memory. This buffer is printed as a string.
-    store.link_func("env", "println", 2, 0).unwrap();
+    // Create an empty store with the host functions of the linker.
+    let mut store = Store::new(&linker);
 
     // Validate a module. Here, we have `wasm2wat hello.wasm` print:
     //
@@ -43,7 +44,7 @@ fn main() {
     let mut memory = [0; 5];
 
     // Instantiate the module in the store.
-    let inst = store.instantiate(module, &mut memory).unwrap();
+    let inst = store.instantiate(module, vec![&mut memory]).unwrap();
 
     // Call the "main" function exported by the instance.
     let mut result = store.invoke(inst, "main", vec![]).unwrap();
@@ -57,6 +58,12 @@ fn main() {
                 assert!(results.is_empty());
                 break;
             }
+            // We didn't set any fuel, so execution never runs out of it.
+            RunResult::Yield(_) => unreachable!(),
+            // We didn't set any breakpoint, so execution never pauses.
+            RunResult::Breakpoint(_) => unreachable!(),
+            // The module doesn't use atomic instructions.
+            RunResult::Atomic(_) => unreachable!(),
         };
 
         // We only linked one function, which has thus index zero.
@@ -0,0 +1,240 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Differential fuzzing library for the interpreter.
+//!
+//! The fuzzer input is used to generate a valid module with wasm-smith, which is then validated,
+//! instantiated, and its exported functions invoked with arbitrary arguments, both by the
+//! interpreter and by wasmi (used as a reference). Any divergence in validation, results, or traps
+//! panics, such that it is reported as a crash.
+//!
+//! Executions that run out of fuel or exhaust the stack in either interpreter are not compared,
+//! since both interpreters account for those resources differently.
+//!
+//! The `float-types`, `toctou`, and `untagged` features are forwarded to the interpreter, such
+//! that each value representation is compared. Type confusions in the `untagged` representation
+//! would otherwise go unnoticed, since they don't trap.
+
+use arbitrary::{Arbitrary, Unstructured};
+use wasefire_interpreter::{Error, Module, RunResult, Store, TrapReason, Val};
+use wasm_smith::SwarmConfig;
+use wasmi::core::{TrapCode, ValueType, F32, F64};
+use wasmi::errors::{InstantiationError, MemoryError};
+use wasmi::{Engine, ExternRef, FuncRef, Linker, Value};
+
+/// Maximum number of pages of the generated memory.
+const MAX_PAGES: u64 = 4;
+
+/// Fuel given to the interpreter for each invocation.
+const FUEL: usize = 10_000;
+
+/// Runs one differential fuzzing iteration.
+pub fn fuzz(data: &[u8]) {
+    let mut u = Unstructured::new(data);
+    let _ = fuzz_(&mut u);
+}
+
+fn fuzz_(u: &mut Unstructured) -> arbitrary::Result<()> {
+    let wasm = wasm_smith::Module::new(config(u)?, u)?.to_bytes();
+    match wasefire_interpreter::validate(&wasm) {
+        Ok(()) => (),
+        // Some features (like floats without the float-types feature) are not supported.
+        Err(Error::Unsupported) => return Ok(()),
+        Err(error) => panic!("validation failed: {error:?}"),
+    }
+    let module = Module::new(&wasm).unwrap();
+
+    let mut config = wasmi::Config::default();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+    let mut reference = wasmi::Store::new(&engine, ());
+    let reference_module = wasmi::Module::new(&engine, &wasm[..]).unwrap();
+    reference.add_fuel(10 * FUEL as u64).unwrap();
+    let reference_inst = Linker::<()>::new(&engine)
+        .instantiate(&mut reference, &reference_module)
+        .and_then(|x| x.start(&mut reference));
+
+    let mut memory = Box::new(Memory([0; MAX_PAGES as usize * 0x10000]));
+    let mut store = Store::default();
+    let inst = store.instantiate(module, vec![&mut memory.0]);
+    let (inst, reference_inst) = match (inst, reference_inst) {
+        (Ok(x), Ok(y)) => (x, y),
+        (Err(Error::Unsupported), _) => return Ok(()),
+        (Err(Error::Trap(x)), Err(y)) => {
+            if let Some(y) = reference_trap(y) {
+                assert_eq!(x, y);
+            }
+            return Ok(());
+        }
+        (x, y) => panic!("instantiation mismatch: {x:?} vs {y:?}"),
+    };
+
+    let mut funcs: Vec<_> = reference_inst
+        .exports(&reference)
+        .filter_map(|x| Some((x.name().to_string(), x.into_func()?)))
+        .collect();
+    funcs.sort_by(|x, y| x.0.cmp(&y.0));
+    for (name, func) in funcs {
+        let type_ = func.ty(&reference);
+        #[cfg(not(feature = "float-types"))]
+        if type_
+            .params()
+            .iter()
+            .chain(type_.results())
+            .any(|x| matches!(x, ValueType::F32 | ValueType::F64))
+        {
+            continue;
+        }
+        let mut args = Vec::new();
+        for &param in type_.params() {
+            args.push(arbitrary_value(u, param)?);
+        }
+        let mut results: Vec<_> = type_.results().iter().map(|&x| Value::default(x)).collect();
+        let expected = match func.call(&mut reference, &args, &mut results) {
+            Ok(()) => Ok(results.iter().map(normalize_value).collect::<Vec<_>>()),
+            Err(error) => match reference_trap(error) {
+                Some(x) => Err(x),
+                None => return Ok(()),
+            },
+        };
+        store.set_fuel(Some(FUEL));
+        let actual = store.invoke(inst, &name, args.iter().map(convert_value).collect());
+        let actual = match actual {
+            Ok(RunResult::Done(x)) => Ok(x.iter().map(normalize_val).collect::<Vec<_>>()),
+            Ok(RunResult::Yield(_)) => return Ok(()),
+            Ok(_) => panic!("unexpected run result"),
+            Err(Error::Trap(TrapReason::StackExhaustion)) => return Ok(()),
+            Err(Error::Trap(x)) => Err(x),
+            Err(Error::Unsupported) => return Ok(()),
+            Err(error) => panic!("unexpected error: {error:?}"),
+        };
+        assert_eq!(actual, expected, "invoking {name:?} with {args:?}");
+    }
+    Ok(())
+}
+
+fn config(u: &mut Unstructured) -> arbitrary::Result<SwarmConfig> {
+    let mut config = SwarmConfig::arbitrary(u)?;
+    // The interpreter only links host functions, which wasmi would need to mirror.
+    config.max_imports = 0;
+    config.available_imports = None;
+    config.simd_enabled = false;
+    config.relaxed_simd_enabled = false;
+    config.exceptions_enabled = false;
+    config.threads_enabled = false;
+    config.memory64_enabled = false;
+    config.tail_call_enabled = false;
+    config.max_tags = 0;
+    // The start function runs without fuel in the interpreter.
+    config.allow_start_export = false;
+    // Exactly one memory with a bounded maximum, such that it fits the provided buffer.
+    config.min_memories = 1;
+    config.max_memories = 1;
+    config.max_memory_pages = MAX_PAGES;
+    config.memory_max_size_required = true;
+    // NaN bit patterns are non-deterministic.
+    config.canonicalize_nans = true;
+    Ok(config)
+}
+
+/// Memory buffer satisfying the interpreter alignment.
+#[repr(align(16))]
+struct Memory([u8; MAX_PAGES as usize * 0x10000]);
+
+fn arbitrary_value(u: &mut Unstructured, type_: ValueType) -> arbitrary::Result<Value> {
+    Ok(match type_ {
+        ValueType::I32 => Value::I32(u.arbitrary()?),
+        ValueType::I64 => Value::I64(u.arbitrary()?),
+        ValueType::F32 => Value::F32(F32::from_bits(u.arbitrary()?)),
+        ValueType::F64 => Value::F64(F64::from_bits(u.arbitrary()?)),
+        ValueType::FuncRef => Value::FuncRef(FuncRef::null()),
+        ValueType::ExternRef => Value::ExternRef(ExternRef::null()),
+    })
+}
+
+fn convert_value(x: &Value) -> Val {
+    use wasefire_interpreter::RefType;
+    match *x {
+        Value::I32(x) => Val::I32(x as u32),
+        Value::I64(x) => Val::I64(x as u64),
+        #[cfg(feature = "float-types")]
+        Value::F32(x) => Val::F32(x.to_bits()),
+        #[cfg(feature = "float-types")]
+        Value::F64(x) => Val::F64(x.to_bits()),
+        #[cfg(not(feature = "float-types"))]
+        Value::F32(_) | Value::F64(_) => unreachable!(),
+        Value::FuncRef(_) => Val::Null(RefType::FuncRef),
+        Value::ExternRef(_) => Val::Null(RefType::ExternRef),
+    }
+}
+
+/// Values of both interpreters in a comparable form.
+#[derive(Debug, PartialEq, Eq)]
+enum Normal {
+    I32(u32),
+    I64(u64),
+    F32(u32),
+    F64(u64),
+    /// Function and external references (only nullness is compared).
+    Ref(bool),
+}
+
+fn normalize_value(x: &Value) -> Normal {
+    match x {
+        Value::I32(x) => Normal::I32(*x as u32),
+        Value::I64(x) => Normal::I64(*x as u64),
+        Value::F32(x) => Normal::F32(x.to_bits()),
+        Value::F64(x) => Normal::F64(x.to_bits()),
+        Value::FuncRef(x) => Normal::Ref(x.is_null()),
+        Value::ExternRef(x) => Normal::Ref(x.is_null()),
+    }
+}
+
+fn normalize_val(x: &Val) -> Normal {
+    match *x {
+        Val::I32(x) => Normal::I32(x),
+        Val::I64(x) => Normal::I64(x),
+        #[cfg(feature = "float-types")]
+        Val::F32(x) => Normal::F32(x),
+        #[cfg(feature = "float-types")]
+        Val::F64(x) => Normal::F64(x),
+        Val::Null(_) => Normal::Ref(true),
+        Val::Ref(_) | Val::RefExtern(_) => Normal::Ref(false),
+    }
+}
+
+/// Returns the trap reason of a reference error, or `None` if it is not comparable.
+fn reference_trap(error: wasmi::Error) -> Option<TrapReason> {
+    Some(match error {
+        // Active segments are initialized outside execution.
+        wasmi::Error::Memory(MemoryError::OutOfBoundsAccess) => TrapReason::MemoryOutOfBounds,
+        wasmi::Error::Table(_) => TrapReason::TableOutOfBounds,
+        wasmi::Error::Instantiation(InstantiationError::ElementSegmentDoesNotFit { .. }) => {
+            TrapReason::TableOutOfBounds
+        }
+        wasmi::Error::Trap(ref x) => match x.trap_code() {
+            Some(TrapCode::UnreachableCodeReached) => TrapReason::Unreachable,
+            Some(TrapCode::MemoryOutOfBounds) => TrapReason::MemoryOutOfBounds,
+            Some(TrapCode::TableOutOfBounds) => TrapReason::TableOutOfBounds,
+            Some(TrapCode::IndirectCallToNull) => TrapReason::UninitializedElement,
+            Some(TrapCode::IntegerDivisionByZero) => TrapReason::DivisionByZero,
+            Some(TrapCode::IntegerOverflow) => TrapReason::IntegerOverflow,
+            Some(TrapCode::BadConversionToInteger) => TrapReason::InvalidConversion,
+            Some(TrapCode::BadSignature) => TrapReason::IndirectCallMismatch,
+            Some(TrapCode::StackOverflow | TrapCode::OutOfFuel) => return None,
+            None => panic!("unexpected reference trap: {x:?}"),
+        },
+        error => panic!("unexpected reference error: {error:?}"),
+    })
+}
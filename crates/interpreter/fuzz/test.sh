@@ -0,0 +1,27 @@
+#!/bin/sh
+# Copyright 2023 Google LLC
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+set -ex
+
+cargo check
+cargo check --features=float-types
+cargo check --features=toctou
+cargo check --features=float-types,toctou
+cargo check --features=untagged
+cargo check --features=float-types,untagged
+cargo fmt -- --check
+cargo clippy -- --deny=warnings
+cargo clippy --features=float-types,toctou -- --deny=warnings
+cargo clippy --features=float-types,untagged -- --deny=warnings
@@ -25,8 +25,50 @@ pub enum Error {
     Unsupported,
 
     /// Execution trapped.
-    // TODO: In debug mode, trap could describe the reason (like resource exhaustion, etc).
-    Trap,
+    Trap(TrapReason),
+}
+
+/// Reasons for execution to trap.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TrapReason {
+    /// An `unreachable` instruction was executed.
+    Unreachable,
+
+    /// A memory access is out of bounds.
+    MemoryOutOfBounds,
+
+    /// A table access is out of bounds.
+    TableOutOfBounds,
+
+    /// An indirect call targets an uninitialized table element.
+    UninitializedElement,
+
+    /// An indirect call targets a function of the wrong type.
+    IndirectCallMismatch,
+
+    /// An integer division or remainder is by zero.
+    DivisionByZero,
+
+    /// An integer operation or conversion overflows.
+    IntegerOverflow,
+
+    /// A conversion to integer is from NaN.
+    InvalidConversion,
+
+    /// The call stack is exhausted.
+    StackExhaustion,
+
+    /// The host rejected a call.
+    HostRejected,
+
+    /// An atomic memory access is not naturally aligned.
+    UnalignedAtomic,
+
+    /// A wait instruction is executed on a memory that is not shared.
+    UnsharedMemory,
+
+    /// A memory access is within the memory size but beyond the data provided by the host.
+    MemoryExhausted,
 }
 
 #[cfg(feature = "debug")]
@@ -55,10 +97,10 @@ pub fn unsupported() -> Error {
     Error::Unsupported
 }
 
-pub fn trap() -> Error {
+pub fn trap(reason: TrapReason) -> Error {
     #[cfg(feature = "debug")]
     print_backtrace();
-    Error::Trap
+    Error::Trap(reason)
 }
 
 pub fn check(cond: bool) -> Result<(), Error> {
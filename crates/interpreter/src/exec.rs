@@ -15,6 +15,7 @@
 // TODO: Some toctou could be used instead of panic.
 use alloc::vec;
 use alloc::vec::Vec;
+use core::mem::size_of;
 
 use crate::error::*;
 use crate::module::*;
@@ -22,10 +23,11 @@ use crate::syntax::*;
 use crate::toctou::*;
 use crate::*;
 
+mod snapshot;
+
 pub const MEMORY_ALIGN: usize = 16;
 
 /// Runtime values.
-// TODO: Introduce untyped values? (to save the tag)
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum Val {
     I32(u32),
@@ -49,10 +51,68 @@ static STORE_ID: id::UniqueId = id::UniqueId::new();
 pub struct Store<'m> {
     id: usize,
     insts: Vec<Instance<'m>>,
-    // TODO: This should be an Linker struct that can be shared between stores. The FuncType can be
-    // reconstructed on demand (only counts can be stored).
-    funcs: Vec<(HostName<'m>, FuncType<'m>)>,
+    linker: &'m Linker<'m>,
     threads: Vec<Continuation<'m>>,
+    fuel: Option<usize>,
+    stack_limit: usize,
+    instance_limits: InstanceLimits,
+    core_dump: Option<CoreDump<'m>>,
+    host: HostItems<'m>,
+    /// Instructions (as instance and module offset) before which execution pauses.
+    breakpoints: Vec<(usize, usize)>,
+}
+
+/// Default stack limit in bytes (see [`Store::set_stack_limit()`]).
+pub const DEFAULT_STACK_LIMIT: usize = 0x10000;
+
+/// Resource limits of the next instances of a store (see [`Store::set_instance_limits()`]).
+///
+/// The default limits are those of the WebAssembly specification.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InstanceLimits {
+    /// Maximum number of pages of each memory.
+    pub max_pages: u32,
+
+    /// Maximum number of elements of each table.
+    pub max_table_elements: u32,
+
+    /// Maximum number of globals (excluding imported ones).
+    pub max_globals: usize,
+}
+
+impl Default for InstanceLimits {
+    fn default() -> Self {
+        InstanceLimits {
+            max_pages: 0x10000,
+            max_table_elements: TABLE_MAX,
+            max_globals: usize::MAX,
+        }
+    }
+}
+
+/// Host tables, memories, and globals that modules may import.
+///
+/// Contrary to host functions, those items hold mutable state and are thus owned by the store.
+#[derive(Debug, Default)]
+struct HostItems<'m> {
+    tables: Vec<(HostName<'m>, TableType, Table)>,
+    mems: Vec<(HostName<'m>, Memory<'m>)>,
+    globals: Vec<(HostName<'m>, GlobalType, Global)>,
+}
+
+/// Host functions that modules may import.
+///
+/// A linker can be shared between stores.
+#[derive(Debug, Default)]
+pub struct Linker<'m> {
+    funcs: Vec<HostFunc<'m>>,
+}
+
+#[derive(Debug)]
+struct HostFunc<'m> {
+    name: HostName<'m>,
+    params: usize,
+    results: usize,
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -61,6 +121,8 @@ struct HostName<'m> {
     name: &'m str,
 }
 
+static EMPTY_LINKER: Linker<'static> = Linker { funcs: Vec::new() };
+
 /// Identifies a store.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct StoreId(usize);
@@ -81,18 +143,139 @@ impl InstId {
 
 /// Store wrapper when calling into the host.
 #[derive(Debug)]
-// Invariant that there is at least one thread.
+// Invariant that the last thread is calling into the host.
 pub struct Call<'a, 'm> {
     store: &'a mut Store<'m>,
 }
 
+/// Store wrapper when execution ran out of fuel.
+#[derive(Debug)]
+// Invariant that the last thread ran out of fuel.
+pub struct Yield<'a, 'm> {
+    store: &'a mut Store<'m>,
+}
+
+/// Store wrapper when execution is paused for debugging.
+#[derive(Debug)]
+// Invariant that the last thread is paused for debugging.
+pub struct Breakpoint<'a, 'm> {
+    store: &'a mut Store<'m>,
+}
+
+/// Store wrapper when execution waits on or notifies a shared memory location.
+#[derive(Debug)]
+// Invariant that the last thread is executing a wait or notify instruction.
+pub struct Atomic<'a, 'm> {
+    store: &'a mut Store<'m>,
+}
+
+/// Wait or notify instruction on a shared memory (threads proposal).
+///
+/// Memory locations are identified by the index of the memory in the instance (including imported
+/// memories) and the effective address in that memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AtomicOp {
+    /// The thread waits until notified or until the timeout (in nanoseconds) expires.
+    ///
+    /// The memory location is known to hold the expected value.
+    Wait { mem: u32, addr: u32, timeout: Option<u64> },
+
+    /// The thread notifies at most `count` threads waiting on the memory location.
+    Notify { mem: u32, addr: u32, count: u32 },
+}
+
 impl<'m> Default for Store<'m> {
     fn default() -> Self {
-        Self { id: STORE_ID.next(), insts: vec![], funcs: vec![], threads: vec![] }
+        Self::new(&EMPTY_LINKER)
     }
 }
 
+impl<'m> Linker<'m> {
+    /// Links a host function provided its signature.
+    ///
+    /// Currently, only functions which take and return `i32` are supported. So the `params` and
+    /// `results` parameters describe how many `i32` are taken as parameters and returned as
+    /// results respectively.
+    ///
+    /// Note that the order in which functions are linked defines their index. The first linked
+    /// function has index 0, the second has index 1, etc. This index is used when a module calls in
+    /// the host to identify the function.
+    pub fn link_func(
+        &mut self, module: &'m str, name: &'m str, params: usize, results: usize,
+    ) -> Result<(), Error> {
+        let name = HostName { module, name };
+        check(self.funcs.last().map_or(true, |x| x.name < name))?;
+        check(params <= HOST_TYPES.len() && results <= HOST_TYPES.len())?;
+        self.funcs.push(HostFunc { name, params, results });
+        Ok(())
+    }
+
+    fn resolve(&self, name: &HostName) -> Option<usize> {
+        self.funcs.binary_search_by(|x| x.name.cmp(name)).ok()
+    }
+
+    fn func_type(&self, x: usize) -> FuncType<'static> {
+        let HostFunc { params, results, .. } = self.funcs[x];
+        FuncType { params: HOST_TYPES[.. params].into(), results: HOST_TYPES[.. results].into() }
+    }
+}
+
+static HOST_TYPES: &[ValType] = &[ValType::I32; 8];
+
 impl<'m> Store<'m> {
+    /// Creates an empty store with host functions from a linker.
+    pub fn new(linker: &'m Linker<'m>) -> Self {
+        Self {
+            id: STORE_ID.next(),
+            insts: vec![],
+            linker,
+            threads: vec![],
+            fuel: None,
+            stack_limit: DEFAULT_STACK_LIMIT,
+            instance_limits: InstanceLimits::default(),
+            core_dump: None,
+            host: HostItems::default(),
+            breakpoints: Vec::new(),
+        }
+    }
+
+    /// Links a host global.
+    ///
+    /// The type of the global is the type of its initial value.
+    pub fn link_global(
+        &mut self, module: &'m str, name: &'m str, mutable: Mut, value: Val,
+    ) -> Result<(), Error> {
+        let name = self.host_name(module, name)?;
+        let type_ = GlobalType { mutable, value: value.type_() };
+        self.host.globals.push((name, type_, Global::new(value)));
+        Ok(())
+    }
+
+    /// Links a host table.
+    ///
+    /// The table is initialized with `type_.limits.min` null elements.
+    pub fn link_table(
+        &mut self, module: &'m str, name: &'m str, type_: TableType,
+    ) -> Result<(), Error> {
+        let name = self.host_name(module, name)?;
+        check(type_.limits.valid(TABLE_MAX))?;
+        self.host.tables.push((name, type_, Table::new(type_)));
+        Ok(())
+    }
+
+    /// Links a host memory.
+    ///
+    /// The same constraints as for [`Self::instantiate()`] apply to the memory. However, its
+    /// content is preserved such that the host can share existing data with modules.
+    pub fn link_memory(
+        &mut self, module: &'m str, name: &'m str, memory: &'m mut [u8], limits: Limits,
+    ) -> Result<(), Error> {
+        let name = self.host_name(module, name)?;
+        check(limits.valid(MEM_MAX))?;
+        self.host.mems.push((name, Memory::new(memory, limits)?));
+        Ok(())
+    }
+
     /// Returns the identifier of this store.
     pub fn id(&self) -> StoreId {
         StoreId(self.id)
@@ -100,15 +283,23 @@ impl<'m> Store<'m> {
 
     /// Instantiates a valid module in this store.
     ///
-    /// The memory is not dynamically allocated and must thus be provided. It is not necessary for
-    /// the memory length to be a multiple of 64kB. Execution will trap if the module tries to
-    /// access part of the memory that does not exist.
+    /// The memories are not dynamically allocated and must thus be provided. There is one buffer
+    /// per memory defined by the module (imported memories are not counted) in order of
+    /// definition. Missing buffers are treated as empty and extra buffers are invalid. It is not
+    /// necessary for the buffer length to be a multiple of 64kB. Execution will trap if the module
+    /// tries to access part of the memory that does not exist.
     pub fn instantiate(
-        &mut self, module: Module<'m>, memory: &'m mut [u8],
+        &mut self, module: Module<'m>, memories: Vec<&'m mut [u8]>,
     ) -> Result<InstId, Error> {
+        self.check_instance_limits(&module)?;
         let inst_id = self.insts.len();
         self.insts.push(Instance::default());
         self.last_inst().module = module;
+        #[cfg(feature = "profile")]
+        {
+            let count = self.last_inst().module.func_count();
+            self.last_inst().profile = vec![Counts::default(); count];
+        }
         for import in self.last_inst().module.imports() {
             let type_ = import.type_(&self.last_inst().module);
             let id = self.resolve(&import, type_)?;
@@ -121,23 +312,28 @@ impl<'m> Store<'m> {
         }
         if let Some(mut parser) = self.last_inst().module.section(SectionId::Table) {
             for _ in 0 .. parser.parse_vec().into_ok() {
-                (self.last_inst().tables.int).push(Table::new(parser.parse_tabletype().into_ok()));
+                let mut type_ = parser.parse_tabletype().into_ok();
+                type_.limits.max =
+                    core::cmp::min(type_.limits.max, self.instance_limits.max_table_elements);
+                (self.last_inst().tables.int).push(Table::new(type_));
             }
         }
+        let mut memories = memories.into_iter();
         if let Some(mut parser) = self.last_inst().module.section(SectionId::Memory) {
-            match parser.parse_vec().into_ok() {
-                0 => (),
-                1 => {
-                    let limits = parser.parse_memtype().into_ok();
-                    self.last_inst().mems.int.init(memory, limits)?;
-                }
-                _ => unimplemented!(),
+            for _ in 0 .. parser.parse_vec().into_ok() {
+                let MemType { mut limits, shared } = parser.parse_memtype().into_ok();
+                limits.max = core::cmp::min(limits.max, self.instance_limits.max_pages);
+                let mut memory = Memory::default();
+                memory.init(memories.next().unwrap_or_default(), limits)?;
+                memory.shared = shared;
+                self.last_inst().mems.int.push(memory);
             }
         }
+        check(memories.next().is_none())?;
         if let Some(mut parser) = self.last_inst().module.section(SectionId::Global) {
             for _ in 0 .. parser.parse_vec().into_ok() {
-                parser.parse_globaltype().into_ok();
-                let value = Thread::const_expr(self, inst_id, &mut parser);
+                let type_ = parser.parse_globaltype().into_ok();
+                let value = Thread::const_expr(self, inst_id, &mut parser, type_.value);
                 self.last_inst().globals.int.push(Global::new(value));
             }
         }
@@ -184,9 +380,10 @@ impl<'m> Store<'m> {
             let mut parser = self.insts[inst_id].module.func(ptr.index());
             let mut locals = Vec::new();
             append_locals(&mut parser, &mut locals);
-            let thread = Thread::new(parser, vec![Frame::new(inst_id, 0, &[], locals)]);
-            let result = thread.run(self)?;
-            assert!(matches!(result, RunResult::Done(x) if x.is_empty()));
+            let frames = vec![Frame::new(inst_id, ptr.index(), 0, Parser::default(), locals, 0)];
+            let thread = Thread::new(parser, frames, ().into());
+            let result = self.without_fuel(|store| thread.run(store).map(|x| x.forget()))?;
+            assert!(matches!(result, RunAnswer::Done(x) if x.is_empty()));
         }
         Ok(InstId { store_id: self.id, inst_id })
     }
@@ -211,10 +408,12 @@ impl<'m> Store<'m> {
         let t = inst.module.func_type(x);
         let mut parser = inst.module.func(x);
         check_types(&t.params, &args)?;
-        let mut locals = args;
+        let mut locals: Vec<Slot> = args.into_iter().map(Slot::from).collect();
         append_locals(&mut parser, &mut locals);
-        let frame = Frame::new(inst_id, t.results.len(), &[], locals);
-        Thread::new(parser, vec![frame]).run(self)
+        // Threads calling into the host are still using the stack.
+        let stack = self.threads.iter().map(|x| x.thread.stack()).sum();
+        let frame = Frame::new(inst_id, x, t.results.len(), Parser::default(), locals, stack);
+        Thread::new(parser, vec![frame], t.results).run(self)
     }
 
     /// Returns the value of a global of an instance.
@@ -225,9 +424,7 @@ impl<'m> Store<'m> {
             ExportDesc::Global(x) => inst.globals.ptr(inst_id, x),
             _ => return Err(Error::Invalid),
         };
-        let inst_id = ptr.instance().unwrap_wasm();
-        let x = ptr.index() as usize;
-        Ok(self.insts[inst_id].globals.int[x].value)
+        Ok(self.global_at(ptr).value)
     }
 
     /// Sets the name of an instance.
@@ -237,37 +434,151 @@ impl<'m> Store<'m> {
         Ok(())
     }
 
-    /// Links a host function provided its signature.
+    /// Returns the call in the host, if any.
     ///
-    /// Currently, only functions which take and return `i32` are supported. So the `params` and
-    /// `results` parameters describe how many `i32` are taken as parameters and returned as
-    /// results respectively.
+    /// This function returns `None` if nothing is running or if the last thread is not calling the
+    /// host.
+    // NOTE: This is like poll. Could be called next.
+    pub fn last_call(&mut self) -> Option<Call<'_, 'm>> {
+        match self.threads.last()?.pause {
+            Pause::Host(_) => Some(Call { store: self }),
+            _ => None,
+        }
+    }
+
+    /// Returns the thread that ran out of fuel, if any.
     ///
-    /// Note that the order in which functions are linked defines their index. The first linked
-    /// function has index 0, the second has index 1, etc. This index is used when a module calls in
-    /// the host to identify the function.
-    pub fn link_func(
-        &mut self, module: &'m str, name: &'m str, params: usize, results: usize,
+    /// This function returns `None` if nothing is running or if the last thread did not run out of
+    /// fuel.
+    pub fn last_yield(&mut self) -> Option<Yield<'_, 'm>> {
+        match self.threads.last()?.pause {
+            Pause::Yield => Some(Yield { store: self }),
+            _ => None,
+        }
+    }
+
+    /// Returns the thread paused for debugging, if any.
+    ///
+    /// This function returns `None` if nothing is running or if the last thread is not paused for
+    /// debugging.
+    pub fn last_breakpoint(&mut self) -> Option<Breakpoint<'_, 'm>> {
+        match self.threads.last()?.pause {
+            Pause::Breakpoint => Some(Breakpoint { store: self }),
+            _ => None,
+        }
+    }
+
+    /// Returns the thread waiting on or notifying a shared memory location, if any.
+    ///
+    /// This function returns `None` if nothing is running or if the last thread is not executing a
+    /// wait or notify instruction.
+    pub fn last_atomic(&mut self) -> Option<Atomic<'_, 'm>> {
+        match self.threads.last()?.pause {
+            Pause::Atomic(_) => Some(Atomic { store: self }),
+            _ => None,
+        }
+    }
+
+    /// Sets a breakpoint before an instruction.
+    ///
+    /// The instruction is identified by the index of its function in the module (including
+    /// imported functions) and its offset in the module binary, like in [`CoreFrame`]. Execution
+    /// pauses with [`RunResult::Breakpoint`] before executing the instruction.
+    pub fn set_breakpoint(&mut self, inst: InstId, func: u32, offset: usize) -> Result<(), Error> {
+        let breakpoint = self.breakpoint(inst, func, offset)?;
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+        Ok(())
+    }
+
+    /// Removes a breakpoint (see [`Self::set_breakpoint()`]).
+    pub fn clear_breakpoint(
+        &mut self, inst: InstId, func: u32, offset: usize,
     ) -> Result<(), Error> {
-        static TYPES: &[ValType] = &[ValType::I32; 8];
-        let name = HostName { module, name };
-        check(self.insts.is_empty())?;
-        check(self.funcs.last().map_or(true, |x| x.0 < name))?;
-        check(params <= TYPES.len() && results <= TYPES.len())?;
-        let type_ = FuncType { params: TYPES[.. params].into(), results: TYPES[.. results].into() };
-        self.funcs.push((name, type_));
+        let breakpoint = self.breakpoint(inst, func, offset)?;
+        let i = self.breakpoints.iter().position(|x| *x == breakpoint).ok_or_else(not_found)?;
+        self.breakpoints.swap_remove(i);
         Ok(())
     }
 
-    /// Returns the call in the host, if any.
+    /// Sets the remaining fuel of this store.
     ///
-    /// This function returns `None` if nothing is running.
-    // NOTE: This is like poll. Could be called next.
-    pub fn last_call(&mut self) -> Option<Call<'_, 'm>> {
-        if self.threads.is_empty() {
-            None
-        } else {
-            Some(Call { store: self })
+    /// Each executed instruction consumes one unit of fuel. When there is no fuel left, execution
+    /// pauses with [`RunResult::Yield`] and may be resumed once fuel is added. When `None` (the
+    /// default), fuel is not accounted and execution never yields.
+    ///
+    /// Constant expressions and start functions are not accounted.
+    pub fn set_fuel(&mut self, fuel: Option<usize>) {
+        self.fuel = fuel;
+    }
+
+    /// Returns the remaining fuel of this store, if accounted.
+    pub fn fuel(&self) -> Option<usize> {
+        self.fuel
+    }
+
+    /// Sets the stack limit of this store in bytes.
+    ///
+    /// The stack usage accounts for the frames, labels, locals, and values of all threads of the
+    /// store (including those calling into the host). It is computed from the size of the
+    /// interpreter data structures and checked each time a function is called, a block is
+    /// entered, or an instruction pushes values. Execution traps with
+    /// [`TrapReason::StackExhaustion`] when the limit is exceeded.
+    pub fn set_stack_limit(&mut self, limit: usize) {
+        self.stack_limit = limit;
+    }
+
+    /// Returns the stack limit of this store in bytes.
+    pub fn stack_limit(&self) -> usize {
+        self.stack_limit
+    }
+
+    /// Sets the resource limits of the next instances of this store.
+    ///
+    /// Instantiation fails with [`Error::Unsupported`] if the module requires more than those
+    /// limits, i.e. if a memory or table has a larger minimum size, or if there are more globals.
+    /// Otherwise, the maximum size of memories and tables is reduced to those limits, such that
+    /// growing beyond them fails as if the module declared them.
+    pub fn set_instance_limits(&mut self, limits: InstanceLimits) {
+        self.instance_limits = limits;
+    }
+
+    /// Returns the resource limits of the next instances of this store.
+    pub fn instance_limits(&self) -> InstanceLimits {
+        self.instance_limits
+    }
+
+    /// Takes the core dump of the last thread that trapped, if any.
+    ///
+    /// A core dump is recorded each time execution returns [`Error::Trap`], replacing the previous
+    /// one.
+    pub fn take_core_dump(&mut self) -> Option<CoreDump<'m>> {
+        self.core_dump.take()
+    }
+
+    /// Returns the execution profile of an instance.
+    ///
+    /// There is one entry per function defined by the module (imported functions are executed
+    /// elsewhere). Counts accumulate since instantiation, including across threads, and are not
+    /// part of snapshots.
+    #[cfg(feature = "profile")]
+    pub fn profile(&self, inst: InstId) -> Result<Vec<FuncProfile<'m>>, Error> {
+        let inst = &self.insts[self.inst_id(inst)?];
+        let imported = inst.funcs.ext.len();
+        let profile = inst.profile.iter().enumerate().map(|(i, counts)| {
+            let func = (imported + i) as FuncIdx;
+            let name = inst.module.func_name(func);
+            FuncProfile { func, name, instrs: counts.instrs, host_calls: counts.host_calls }
+        });
+        Ok(profile.collect())
+    }
+
+    /// Resets the execution profile of all instances.
+    #[cfg(feature = "profile")]
+    pub fn clear_profile(&mut self) {
+        for inst in &mut self.insts {
+            inst.profile.iter_mut().for_each(|x| *x = Counts::default());
         }
     }
 }
@@ -275,12 +586,12 @@ impl<'m> Store<'m> {
 impl<'a, 'm> Call<'a, 'm> {
     /// Returns the index of the host function being called.
     pub fn index(&self) -> usize {
-        self.cont().index
+        self.call().index
     }
 
     /// Returns the arguments to the host function being called.
     pub fn args(&self) -> &[Val] {
-        &self.cont().args
+        &self.call().args
     }
 
     /// Returns the identifier of the instance calling the host.
@@ -288,27 +599,159 @@ impl<'a, 'm> Call<'a, 'm> {
         self.cont().thread.inst(self.store)
     }
 
-    /// Returns the memory of the instance calling the host.
+    /// Returns the first memory of the instance calling the host.
     pub fn mem(self) -> &'a mut [u8] {
         self.store.mem(self.inst().inst_id, 0).data
     }
 
-    /// Returns the memory of the instance calling the host.
+    /// Returns the first memory of the instance calling the host.
     pub fn mem_mut(&mut self) -> &mut [u8] {
         self.store.mem(self.inst().inst_id, 0).data
     }
 
     /// Resumes execution with the results from the host.
     pub fn resume(self, results: &[Val]) -> Result<RunResult<'a, 'm>, Error> {
-        let Continuation { mut thread, arity, .. } = self.store.threads.pop().unwrap();
+        let Continuation { mut thread, pause } = self.store.threads.pop().unwrap();
+        let arity = match pause {
+            Pause::Host(call) => call.arity,
+            _ => unreachable!(),
+        };
         check(results.len() == arity)?;
-        thread.push_values(results);
+        for &x in results {
+            thread.push_value(x);
+        }
         thread.run(self.store)
     }
 
+    /// Rejects the call, which traps the thread calling the host.
+    ///
+    /// Returns the error that execution results in, like [`Call::resume()`] would.
+    pub fn reject(self) -> Error {
+        let Continuation { thread, .. } = self.store.threads.pop().unwrap();
+        let pos = thread.parser.save();
+        self.store.core_dump = Some(thread.core_dump(self.store, pos));
+        trap(TrapReason::HostRejected)
+    }
+
     fn cont(&self) -> &Continuation {
         self.store.threads.last().unwrap()
     }
+
+    fn call(&self) -> &HostCall {
+        match &self.cont().pause {
+            Pause::Host(call) => call,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<'a, 'm> Yield<'a, 'm> {
+    /// Returns the identifier of the instance that ran out of fuel.
+    pub fn inst(&self) -> InstId {
+        self.store.threads.last().unwrap().thread.inst(self.store)
+    }
+
+    /// Resumes execution.
+    ///
+    /// Execution yields again immediately if there is still no fuel.
+    pub fn resume(self) -> Result<RunResult<'a, 'm>, Error> {
+        let Continuation { thread, .. } = self.store.threads.pop().unwrap();
+        thread.run(self.store)
+    }
+}
+
+impl<'a, 'm> Breakpoint<'a, 'm> {
+    /// Returns the identifier of the paused instance.
+    pub fn inst(&self) -> InstId {
+        self.thread().inst(self.store)
+    }
+
+    /// Returns the index of the paused function in its module.
+    pub fn func(&self) -> u32 {
+        self.pos().0
+    }
+
+    /// Returns the offset of the next instruction in the module binary.
+    pub fn offset(&self) -> usize {
+        self.pos().1
+    }
+
+    /// Returns the parameters and locals of the paused function.
+    pub fn locals(&self) -> Vec<Val> {
+        let thread = self.thread();
+        let module = &self.store.insts[thread.inst_id()].module;
+        let types = module.local_types(&thread.parser);
+        vals(thread.frames.last().unwrap().locals.clone(), &types)
+    }
+
+    /// Returns the value stack of the paused function (from bottom to top).
+    ///
+    /// The value stack is not available with the `untagged` feature because the type of the
+    /// values is not stored.
+    #[cfg(not(feature = "untagged"))]
+    pub fn stack(&self) -> Vec<Val> {
+        let frame = self.thread().frames.last().unwrap();
+        frame.labels.iter().flat_map(|x| x.values.iter().cloned()).collect()
+    }
+
+    /// Returns the globals of the paused instance (including imported globals).
+    pub fn globals(&self) -> Vec<Val> {
+        let inst_id = self.thread().inst_id();
+        let globals = &self.store.insts[inst_id].globals;
+        let len = globals.ext.len() + globals.int.len();
+        (0 .. len as GlobalIdx)
+            .map(|x| self.store.global_val(self.store.global_ptr(inst_id, x)))
+            .collect()
+    }
+
+    /// Executes the next instruction and pauses again.
+    ///
+    /// Execution doesn't pause if the instruction returns from the function being invoked, calls
+    /// into the host, or runs out of fuel.
+    pub fn step(self) -> Result<RunResult<'a, 'm>, Error> {
+        let Continuation { thread, .. } = self.store.threads.pop().unwrap();
+        thread.resume_breakpoint(self.store, true)
+    }
+
+    /// Resumes execution until the next breakpoint.
+    pub fn resume(self) -> Result<RunResult<'a, 'm>, Error> {
+        let Continuation { thread, .. } = self.store.threads.pop().unwrap();
+        thread.resume_breakpoint(self.store, false)
+    }
+
+    fn thread(&self) -> &Thread<'m> {
+        &self.store.threads.last().unwrap().thread
+    }
+
+    fn pos(&self) -> (u32, usize) {
+        let thread = self.thread();
+        self.store.insts[thread.inst_id()].module.func_pos(&thread.parser)
+    }
+}
+
+impl<'a, 'm> Atomic<'a, 'm> {
+    /// Returns the identifier of the instance executing the instruction.
+    pub fn inst(&self) -> InstId {
+        self.store.threads.last().unwrap().thread.inst(self.store)
+    }
+
+    /// Returns the instruction being executed.
+    pub fn op(&self) -> AtomicOp {
+        match self.store.threads.last().unwrap().pause {
+            Pause::Atomic(op) => op,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Resumes execution with the result of the instruction.
+    ///
+    /// For [`AtomicOp::Wait`], the result is 0 if the thread was notified and 2 if the timeout
+    /// expired. For [`AtomicOp::Notify`], the result is the number of notified threads.
+    pub fn resume(self, result: u32) -> Result<RunResult<'a, 'm>, Error> {
+        let Continuation { mut thread, .. } = self.store.threads.pop().unwrap();
+        thread.push_value(Val::I32(result));
+        thread.run(self.store)
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -384,7 +827,7 @@ impl core::fmt::Debug for Val {
             #[cfg(feature = "float-types")]
             Val::F64(x) => write!(f, "{}", f64::from_bits(x)),
             #[cfg(feature = "vector-types")]
-            Val::V128(_) => todo!(),
+            Val::V128(x) => write!(f, "{x:#034x}"),
             Val::Null(_) => write!(f, "null"),
             Val::Ref(p) => write!(f, "ref@{:?}:{}", p.instance(), p.index()),
             Val::RefExtern(p) => write!(f, "ext@{p}"),
@@ -417,16 +860,46 @@ struct Instance<'m> {
     module: Module<'m>,
     funcs: Component<()>,
     tables: Component<Vec<Table>>,
-    mems: Component<Memory<'m>>,
+    mems: Component<Vec<Memory<'m>>>,
     globals: Component<Vec<Global>>,
     elems: Vec<bool>, // whether the elem segment is dropped
     datas: Vec<bool>, // whether the data segment is dropped
+    #[cfg(feature = "profile")]
+    profile: Vec<Counts>, // indexed by defined function (excluding imports)
+}
+
+/// Execution counts of a function.
+#[cfg(feature = "profile")]
+#[derive(Debug, Default, Copy, Clone)]
+struct Counts {
+    instrs: u64,
+    host_calls: u64,
+}
+
+/// Execution counts of a function (see [`Store::profile()`]).
+#[cfg(feature = "profile")]
+#[derive(Debug, Clone)]
+pub struct FuncProfile<'m> {
+    /// Index of the function in its module.
+    pub func: u32,
+
+    /// Name of the function according to the name section, if any.
+    pub name: Option<&'m str>,
+
+    /// Number of instructions executed by the function (excluding its callees).
+    pub instrs: u64,
+
+    /// Number of calls from the function into the host.
+    pub host_calls: u64,
 }
 
 #[derive(Debug)]
 struct Thread<'m> {
     parser: Parser<'m>,
     frames: Vec<Frame<'m>>,
+    results: ResultType<'m>,
+    /// Whether the thread resumed from a breakpoint and did not execute its instruction yet.
+    resumed: bool,
 }
 
 /// Runtime result.
@@ -437,6 +910,17 @@ pub enum RunResult<'a, 'm> {
 
     /// Execution is calling into the host.
     Host(Call<'a, 'm>),
+
+    /// Execution ran out of fuel.
+    Yield(Yield<'a, 'm>),
+
+    /// Execution is paused at a breakpoint or after a single step.
+    Breakpoint(Breakpoint<'a, 'm>),
+
+    /// Execution waits on or notifies a shared memory location.
+    ///
+    /// This only happens with the `threads` feature.
+    Atomic(Atomic<'a, 'm>),
 }
 
 /// Runtime result without host call information.
@@ -444,6 +928,9 @@ pub enum RunResult<'a, 'm> {
 pub enum RunAnswer {
     Done(Vec<Val>),
     Host,
+    Yield,
+    Breakpoint,
+    Atomic,
 }
 
 impl<'a, 'm> RunResult<'a, 'm> {
@@ -451,13 +938,83 @@ impl<'a, 'm> RunResult<'a, 'm> {
         match self {
             RunResult::Done(result) => RunAnswer::Done(result),
             RunResult::Host(_) => RunAnswer::Host,
+            RunResult::Yield(_) => RunAnswer::Yield,
+            RunResult::Breakpoint(_) => RunAnswer::Breakpoint,
+            RunResult::Atomic(_) => RunAnswer::Atomic,
+        }
+    }
+}
+
+/// Execution state of a thread when it trapped.
+#[derive(Debug)]
+pub struct CoreDump<'m> {
+    /// Frames from the most recent (where the trap occurred) to the oldest.
+    pub frames: Vec<CoreFrame<'m>>,
+}
+
+/// Function frame of a core dump.
+#[derive(Debug)]
+pub struct CoreFrame<'m> {
+    /// Instance of the function.
+    pub inst: InstId,
+
+    /// Index of the function in its module.
+    pub func: u32,
+
+    /// Name of the function according to the name section, if any.
+    pub name: Option<&'m str>,
+
+    /// Offset of the current instruction in the module binary.
+    ///
+    /// For all frames but the most recent one, this is the offset of the instruction following the
+    /// call.
+    pub offset: usize,
+
+    /// Parameters and locals of the function.
+    pub locals: Vec<Val>,
+}
+
+impl core::fmt::Display for CoreDump<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, frame) in self.frames.iter().enumerate() {
+            writeln!(f, "#{i} {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+impl core::fmt::Display for CoreFrame<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:#x} in ", self.offset)?;
+        match self.name {
+            Some(name) => write!(f, "{name}")?,
+            None => write!(f, "func[{}]", self.func)?,
         }
+        write!(f, " {:?}", self.locals)
     }
 }
 
 #[derive(Debug)]
 struct Continuation<'m> {
     thread: Thread<'m>,
+    pause: Pause,
+}
+
+/// Reason why a thread is suspended.
+#[derive(Debug)]
+enum Pause {
+    /// The thread is calling into the host.
+    Host(HostCall),
+    /// The thread ran out of fuel.
+    Yield,
+    /// The thread is paused for debugging.
+    Breakpoint,
+    /// The thread is executing a wait or notify instruction.
+    Atomic(AtomicOp),
+}
+
+#[derive(Debug)]
+struct HostCall {
     index: usize,
     args: Vec<Val>,
     arity: usize,
@@ -468,6 +1025,37 @@ impl<'m> Store<'m> {
         self.insts.last_mut().unwrap()
     }
 
+    fn check_instance_limits(&self, module: &Module<'m>) -> Result<(), Error> {
+        let limits = self.instance_limits;
+        if let Some(mut parser) = module.section(SectionId::Table) {
+            for _ in 0 .. parser.parse_vec().into_ok() {
+                if parser.parse_tabletype().into_ok().limits.min > limits.max_table_elements {
+                    return Err(unsupported());
+                }
+            }
+        }
+        if let Some(mut parser) = module.section(SectionId::Memory) {
+            for _ in 0 .. parser.parse_vec().into_ok() {
+                if parser.parse_memtype().into_ok().limits.min > limits.max_pages {
+                    return Err(unsupported());
+                }
+            }
+        }
+        if let Some(mut parser) = module.section(SectionId::Global) {
+            if parser.parse_vec().into_ok() > limits.max_globals {
+                return Err(unsupported());
+            }
+        }
+        Ok(())
+    }
+
+    fn without_fuel<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let fuel = self.fuel.take();
+        let result = f(self);
+        self.fuel = fuel;
+        result
+    }
+
     fn inst_id(&self, inst: InstId) -> Result<usize, Error> {
         check(self.id == inst.store_id)?;
         Ok(inst.inst_id)
@@ -479,7 +1067,7 @@ impl<'m> Store<'m> {
 
     fn func_type(&self, ptr: Ptr) -> FuncType<'m> {
         match ptr.instance() {
-            Side::Host => self.funcs[ptr.index() as usize].1,
+            Side::Host => self.linker.func_type(ptr.index() as usize),
             Side::Wasm(x) => self.insts[x].module.func_type(ptr.index()),
         }
     }
@@ -502,29 +1090,118 @@ impl<'m> Store<'m> {
 
     fn table(&mut self, inst_id: usize, x: TableIdx) -> &mut Table {
         let ptr = self.table_ptr(inst_id, x);
-        let x = ptr.instance().unwrap_wasm();
-        &mut self.insts[x].tables.int[ptr.index() as usize]
+        self.table_at(ptr)
     }
 
     fn mem(&mut self, inst_id: usize, x: MemIdx) -> &mut Memory<'m> {
         let ptr = self.mem_ptr(inst_id, x);
-        let x = ptr.instance().unwrap_wasm();
-        assert_eq!(ptr.index(), 0);
-        &mut self.insts[x].mems.int
+        self.mem_at(ptr)
     }
 
     fn global(&mut self, inst_id: usize, x: GlobalIdx) -> &mut Global {
         let ptr = self.global_ptr(inst_id, x);
-        let x = ptr.instance().unwrap_wasm();
-        &mut self.insts[x].globals.int[ptr.index() as usize]
+        self.global_at(ptr)
+    }
+
+    fn table_at(&mut self, ptr: Ptr) -> &mut Table {
+        let x = ptr.index() as usize;
+        match ptr.instance() {
+            Side::Host => &mut self.host.tables[x].2,
+            Side::Wasm(inst_id) => &mut self.insts[inst_id].tables.int[x],
+        }
+    }
+
+    fn mem_at(&mut self, ptr: Ptr) -> &mut Memory<'m> {
+        let x = ptr.index() as usize;
+        match ptr.instance() {
+            Side::Host => &mut self.host.mems[x].1,
+            Side::Wasm(inst_id) => &mut self.insts[inst_id].mems.int[x],
+        }
+    }
+
+    fn global_at(&mut self, ptr: Ptr) -> &mut Global {
+        let x = ptr.index() as usize;
+        match ptr.instance() {
+            Side::Host => &mut self.host.globals[x].2,
+            Side::Wasm(inst_id) => &mut self.insts[inst_id].globals.int[x],
+        }
+    }
+
+    fn table_type(&self, ptr: Ptr) -> TableType {
+        let x = ptr.index() as usize;
+        let (mut t, table) = match ptr.instance() {
+            Side::Host => (self.host.tables[x].1, &self.host.tables[x].2),
+            Side::Wasm(inst_id) => {
+                let inst = &self.insts[inst_id];
+                (inst.module.table_type(x as TableIdx), &inst.tables.int[x])
+            }
+        };
+        t.limits.min = table.size();
+        t
+    }
+
+    fn mem_type(&self, ptr: Ptr) -> MemType {
+        let x = ptr.index() as usize;
+        let mem = match ptr.instance() {
+            Side::Host => &self.host.mems[x].1,
+            Side::Wasm(inst_id) => &self.insts[inst_id].mems.int[x],
+        };
+        MemType { limits: Limits { min: mem.size(), max: mem.max() }, shared: mem.shared }
+    }
+
+    fn global_type(&self, ptr: Ptr) -> GlobalType {
+        let x = ptr.index() as usize;
+        match ptr.instance() {
+            Side::Host => self.host.globals[x].1,
+            Side::Wasm(inst_id) => self.insts[inst_id].module.global_type(x as GlobalIdx),
+        }
+    }
+
+    fn global_val(&self, ptr: Ptr) -> Val {
+        let x = ptr.index() as usize;
+        match ptr.instance() {
+            Side::Host => self.host.globals[x].2.value,
+            Side::Wasm(inst_id) => self.insts[inst_id].globals.int[x].value,
+        }
+    }
+
+    fn breakpoint(&self, inst: InstId, func: u32, offset: usize) -> Result<(usize, usize), Error> {
+        let inst_id = self.inst_id(inst)?;
+        let module = &self.insts.get(inst_id).ok_or_else(not_found)?.module;
+        let pos = module.func_instr(func, offset).ok_or_else(not_found)?;
+        Ok((inst_id, pos.offset()))
+    }
+
+    fn host_name(&self, module: &'m str, name: &'m str) -> Result<HostName<'m>, Error> {
+        let name = HostName { module, name };
+        check(self.linker.resolve(&name).is_none())?;
+        check(self.host.tables.iter().all(|x| x.0 != name))?;
+        check(self.host.mems.iter().all(|x| x.0 != name))?;
+        check(self.host.globals.iter().all(|x| x.0 != name))?;
+        Ok(name)
+    }
+
+    fn resolve_host(&self, import: &Import<'m>) -> Option<(Ptr, ExternType<'m>)> {
+        let name = HostName { module: import.module, name: import.name };
+        let x = match import.desc {
+            ImportDesc::Func(_) => self.linker.resolve(&name),
+            ImportDesc::Table(_) => self.host.tables.iter().position(|x| x.0 == name),
+            ImportDesc::Mem(_) => self.host.mems.iter().position(|x| x.0 == name),
+            ImportDesc::Global(_) => self.host.globals.iter().position(|x| x.0 == name),
+        }?;
+        let ptr = Ptr::new(Side::Host, x as u32);
+        let type_ = match import.desc {
+            ImportDesc::Func(_) => ExternType::Func(self.linker.func_type(x)),
+            ImportDesc::Table(_) => ExternType::Table(self.table_type(ptr)),
+            ImportDesc::Mem(_) => ExternType::Mem(self.mem_type(ptr)),
+            ImportDesc::Global(_) => ExternType::Global(self.global_type(ptr)),
+        };
+        Some((ptr, type_))
     }
 
     fn resolve(&self, import: &Import<'m>, imp_type_: ExternType<'m>) -> Result<Ptr, Error> {
-        let host_name = HostName { module: import.module, name: import.name };
-        let mut found = None;
-        if let Ok(x) = self.funcs.binary_search_by(|x| x.0.cmp(&host_name)) {
-            found = Some((Ptr::new(Side::Host, x as u32), ExternType::Func(self.funcs[x].1)));
-        } else {
+        let mut found = self.resolve_host(import);
+        if found.is_none() {
             let inst_id = self.resolve_inst(import.module)?;
             let inst = &self.insts[inst_id];
             if let Some(mut parser) = inst.module.section(SectionId::Export) {
@@ -541,23 +1218,15 @@ impl<'m> Store<'m> {
                         }
                         ExportDesc::Table(x) => {
                             let ptr = self.table_ptr(inst_id, x);
-                            let inst = &self.insts[ptr.instance().unwrap_wasm()];
-                            let mut t = inst.module.table_type(ptr.index());
-                            t.limits.min = inst.tables.int[ptr.index() as usize].size();
-                            (ptr, ExternType::Table(t))
+                            (ptr, ExternType::Table(self.table_type(ptr)))
                         }
                         ExportDesc::Mem(x) => {
                             let ptr = self.mem_ptr(inst_id, x);
-                            let inst = &self.insts[ptr.instance().unwrap_wasm()];
-                            let mut t = inst.module.mem_type(ptr.index());
-                            assert_eq!(ptr.index(), 0);
-                            t.min = inst.mems.int.size();
-                            (ptr, ExternType::Mem(t))
+                            (ptr, ExternType::Mem(self.mem_type(ptr)))
                         }
                         ExportDesc::Global(x) => {
                             let ptr = self.global_ptr(inst_id, x);
-                            let inst = &self.insts[ptr.instance().unwrap_wasm()];
-                            (ptr, ExternType::Global(inst.module.global_type(ptr.index())))
+                            (ptr, ExternType::Global(self.global_type(ptr)))
                         }
                     });
                     break;
@@ -584,12 +1253,14 @@ struct ComputeElem<'a, 'm> {
     store: &'a mut Store<'m>,
     inst_id: usize,
     mode: ElemMode,
+    type_: RefType,
     init: Vec<Val>,
 }
 
 impl<'a, 'm> ComputeElem<'a, 'm> {
     fn new(store: &'a mut Store<'m>, inst_id: usize) -> Self {
-        Self { store, inst_id, mode: ElemMode::Passive, init: Vec::new() }
+        let type_ = RefType::FuncRef;
+        Self { store, inst_id, mode: ElemMode::Passive, type_, init: Vec::new() }
     }
 }
 
@@ -599,7 +1270,8 @@ impl<'a, 'm> parser::ParseElem<'m, Use> for ComputeElem<'a, 'm> {
             parser::ElemMode::Passive => ElemMode::Passive,
             parser::ElemMode::Active { table, offset } => ElemMode::Active {
                 table,
-                offset: Thread::const_expr(self.store, self.inst_id, offset).unwrap_i32() as usize,
+                offset: Thread::const_expr(self.store, self.inst_id, offset, ValType::I32)
+                    .unwrap_i32() as usize,
             },
             parser::ElemMode::Declarative => ElemMode::Declarative,
         };
@@ -607,13 +1279,19 @@ impl<'a, 'm> parser::ParseElem<'m, Use> for ComputeElem<'a, 'm> {
         Ok(())
     }
 
+    fn type_(&mut self, type_: RefType) -> MResult<(), Use> {
+        self.type_ = type_;
+        Ok(())
+    }
+
     fn init_funcidx(&mut self, x: FuncIdx) -> MResult<(), Use> {
         self.init.push(Val::Ref(self.store.func_ptr(self.inst_id, x)));
         Ok(())
     }
 
     fn init_expr(&mut self, parser: &mut Parser<'m>) -> MResult<(), Use> {
-        self.init.push(Thread::const_expr(self.store, self.inst_id, parser));
+        let t = self.type_.into();
+        self.init.push(Thread::const_expr(self.store, self.inst_id, parser, t));
         Ok(())
     }
 }
@@ -628,12 +1306,12 @@ struct ComputeData<'a, 'm> {
     store: &'a mut Store<'m>,
     inst_id: usize,
     mode: DataMode,
-    init: &'m [u8],
+    init: Parser<'m>,
 }
 
 impl<'a, 'm> ComputeData<'a, 'm> {
     fn new(store: &'a mut Store<'m>, inst_id: usize) -> Self {
-        Self { store, inst_id, mode: DataMode::Passive, init: &[] }
+        Self { store, inst_id, mode: DataMode::Passive, init: Parser::default() }
     }
 }
 
@@ -643,14 +1321,15 @@ impl<'a, 'm> parser::ParseData<'m, Use> for ComputeData<'a, 'm> {
             parser::DataMode::Passive => DataMode::Passive,
             parser::DataMode::Active { memory, offset } => DataMode::Active {
                 memory,
-                offset: Thread::const_expr(self.store, self.inst_id, offset).unwrap_i32() as usize,
+                offset: Thread::const_expr(self.store, self.inst_id, offset, ValType::I32)
+                    .unwrap_i32() as usize,
             },
         };
         self.mode = mode;
         Ok(())
     }
 
-    fn init(&mut self, init: &'m [u8]) -> MResult<(), Use> {
+    fn init(&mut self, init: Parser<'m>) -> MResult<(), Use> {
         self.init = init;
         Ok(())
     }
@@ -659,6 +1338,7 @@ impl<'a, 'm> parser::ParseData<'m, Use> for ComputeData<'a, 'm> {
 #[derive(Debug)]
 struct Table {
     max: u32,
+    item: RefType,
     elems: Vec<Val>,
 }
 
@@ -667,92 +1347,201 @@ struct Global {
     value: Val,
 }
 
-enum ThreadResult<'m> {
-    Continue(Thread<'m>),
-    Done(Vec<Val>),
-    Host,
+enum ThreadResult {
+    Continue,
+    Done(Vec<Slot>),
+    Host(HostCall),
+    Yield,
+    #[cfg(feature = "threads")]
+    Atomic(AtomicOp),
 }
 
 impl<'m> Thread<'m> {
-    fn new(parser: Parser<'m>, frames: Vec<Frame<'m>>) -> Thread<'m> {
-        Thread { parser, frames }
+    fn new(parser: Parser<'m>, frames: Vec<Frame<'m>>, results: ResultType<'m>) -> Thread<'m> {
+        Thread { parser, frames, results, resumed: false }
     }
 
-    fn const_expr(store: &mut Store<'m>, inst_id: usize, mut_parser: &mut Parser<'m>) -> Val {
-        let frames = vec![Frame::new(inst_id, 1, &[], Vec::new())];
+    fn const_expr(
+        store: &mut Store<'m>, inst_id: usize, mut_parser: &mut Parser<'m>, t: ValType,
+    ) -> Val {
+        // Constant expressions don't have blocks and thus don't use the function side table.
+        let frames = vec![Frame::new(inst_id, 0, 1, Parser::default(), Vec::new(), 0)];
         let parser = mut_parser.clone();
-        let mut thread = Thread::new(parser, frames);
+        let mut thread = Thread::new(parser, frames, t.into());
         let (parser, results) = loop {
             let p = thread.parser.save();
-            match thread.step(store).unwrap() {
-                ThreadResult::Continue(x) => thread = x,
+            match store.without_fuel(|store| thread.step(store)).unwrap() {
+                ThreadResult::Continue => (),
                 ThreadResult::Done(x) => break (p, x),
-                ThreadResult::Host => unreachable!(),
+                _ => unreachable!(),
             }
         };
         unsafe { mut_parser.restore(parser) };
         let instr = mut_parser.parse_instr().into_ok();
         debug_assert_eq!(instr, Instr::End);
         debug_assert_eq!(results.len(), 1);
-        results[0]
+        results[0].val(t)
+    }
+
+    fn run<'a>(self, store: &'a mut Store<'m>) -> Result<RunResult<'a, 'm>, Error> {
+        self.execute(store, false)
+    }
+
+    fn resume_breakpoint<'a>(
+        mut self, store: &'a mut Store<'m>, single_step: bool,
+    ) -> Result<RunResult<'a, 'm>, Error> {
+        self.resumed = true;
+        self.execute(store, single_step)
     }
 
-    fn run<'a>(mut self, store: &'a mut Store<'m>) -> Result<RunResult<'a, 'm>, Error> {
+    /// Executes until the thread terminates or is suspended.
+    ///
+    /// When `single_step` is set, the thread pauses after the first instruction.
+    fn execute<'a>(
+        mut self, store: &'a mut Store<'m>, single_step: bool,
+    ) -> Result<RunResult<'a, 'm>, Error> {
         loop {
-            // TODO: When trapping, we could return some CoreDump<'m> that contains the Thread<'m>.
-            // This permits to dump the frames.
-            match self.step(store)? {
-                ThreadResult::Continue(x) => self = x,
-                ThreadResult::Done(x) => return Ok(RunResult::Done(x)),
-                ThreadResult::Host => return Ok(RunResult::Host(Call { store })),
+            let saved = self.parser.save();
+            if !self.resumed && self.is_breakpoint(store, &saved) {
+                return Ok(self.pause(store));
+            }
+            #[cfg(feature = "profile")]
+            let (inst_id, func) = (self.inst_id(), self.frame().func as usize);
+            let result = self.step(store);
+            if !matches!(result, Ok(ThreadResult::Yield)) {
+                self.resumed = false;
+            }
+            #[cfg(feature = "profile")]
+            if let Ok(result) = &result {
+                let counts = &mut store.insts[inst_id].profile[func];
+                match result {
+                    ThreadResult::Yield => (),
+                    ThreadResult::Host(_) => {
+                        counts.instrs += 1;
+                        counts.host_calls += 1;
+                    }
+                    _ => counts.instrs += 1,
+                }
+            }
+            match result {
+                Ok(ThreadResult::Continue) if single_step => return Ok(self.pause(store)),
+                Ok(ThreadResult::Continue) => (),
+                Ok(ThreadResult::Done(x)) => return Ok(RunResult::Done(vals(x, &self.results))),
+                Ok(ThreadResult::Host(call)) => {
+                    store.threads.push(Continuation { thread: self, pause: Pause::Host(call) });
+                    return Ok(RunResult::Host(Call { store }));
+                }
+                Ok(ThreadResult::Yield) => {
+                    store.threads.push(Continuation { thread: self, pause: Pause::Yield });
+                    return Ok(RunResult::Yield(Yield { store }));
+                }
+                #[cfg(feature = "threads")]
+                Ok(ThreadResult::Atomic(op)) => {
+                    store.threads.push(Continuation { thread: self, pause: Pause::Atomic(op) });
+                    return Ok(RunResult::Atomic(Atomic { store }));
+                }
+                Err(e @ Error::Trap(_)) => {
+                    store.core_dump = Some(self.core_dump(store, saved));
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
             }
         }
     }
 
-    fn step(mut self, store: &mut Store<'m>) -> Result<ThreadResult<'m>, Error> {
+    fn is_breakpoint(&self, store: &Store<'m>, pos: &Parser<'m>) -> bool {
+        let inst_id = self.inst_id();
+        store.breakpoints.iter().any(|&(i, x)| i == inst_id && x == pos.offset())
+    }
+
+    fn pause<'a>(self, store: &'a mut Store<'m>) -> RunResult<'a, 'm> {
+        store.threads.push(Continuation { thread: self, pause: Pause::Breakpoint });
+        RunResult::Breakpoint(Breakpoint { store })
+    }
+
+    /// Consumes a trapped thread into a core dump.
+    ///
+    /// The position of the trapping instruction must be provided since the parser may have moved.
+    fn core_dump(self, store: &Store<'m>, mut pos: Parser<'m>) -> CoreDump<'m> {
+        let mut frames = Vec::with_capacity(self.frames.len());
+        for frame in self.frames.into_iter().rev() {
+            let inst_id = frame.inst_id;
+            let module = &store.insts[inst_id].module;
+            let (func, offset) = module.func_pos(&pos);
+            let name = module.func_name(func);
+            let inst = InstId { store_id: store.id, inst_id };
+            let locals = vals(frame.locals, &module.local_types(&pos));
+            frames.push(CoreFrame { inst, func, name, offset, locals });
+            pos = frame.ret;
+        }
+        CoreDump { frames }
+    }
+
+    fn step(&mut self, store: &mut Store<'m>) -> Result<ThreadResult, Error> {
         use Instr::*;
+        if let Some(fuel) = &mut store.fuel {
+            if *fuel == 0 {
+                return Ok(ThreadResult::Yield);
+            }
+            *fuel -= 1;
+        }
         let saved = self.parser.save();
         let inst_id = self.frame().inst_id;
         let inst = &mut store.insts[inst_id];
         match self.parser.parse_instr().into_ok() {
-            Unreachable => return Err(trap()),
+            Unreachable => return Err(trap(TrapReason::Unreachable)),
             Nop => (),
-            Block(b) => self.push_label(self.blocktype(inst, &b), LabelKind::Block),
-            Loop(b) => self.push_label(self.blocktype(inst, &b), LabelKind::Loop(saved)),
-            If(b) => match self.pop_value().unwrap_i32() {
-                0 => {
-                    self.skip_to_else(inst);
-                    self.push_label(self.blocktype(inst, &b), LabelKind::Block);
+            Block(b) => {
+                let end = self.side_entry(inst, &saved).1;
+                self.push_label(store.stack_limit, self.blocktype(inst, &b), LabelKind::Block(end))?
+            }
+            Loop(b) => {
+                let start = Target { len: saved.len(), cursor: self.frame().cursor };
+                self.push_label(
+                    store.stack_limit,
+                    self.blocktype(inst, &b),
+                    LabelKind::Loop(start),
+                )?
+            }
+            If(b) => {
+                let (else_, end) = self.side_entry(inst, &saved);
+                if self.pop_value().unwrap_i32() == 0 {
+                    self.jump(else_);
                 }
-                _ => self.push_label(self.blocktype(inst, &b), LabelKind::If),
-            },
+                self.push_label(
+                    store.stack_limit,
+                    self.blocktype(inst, &b),
+                    LabelKind::Block(end),
+                )?;
+            }
             Else => {
-                self.skip_to_end(inst, 0);
+                match self.labels().last().unwrap().kind {
+                    LabelKind::Block(end) => self.jump(end),
+                    LabelKind::Loop(_) => unreachable!(),
+                }
                 return Ok(self.exit_label());
             }
             End => return Ok(self.exit_label()),
-            Br(l) => return Ok(self.pop_label(inst, l)),
+            Br(l) => return Ok(self.pop_label(l)),
             BrIf(l) => {
                 if self.pop_value().unwrap_i32() != 0 {
-                    return Ok(self.pop_label(inst, l));
+                    return Ok(self.pop_label(l));
                 }
             }
             BrTable(ls, ln) => {
                 let i = self.pop_value().unwrap_i32() as usize;
-                return Ok(self.pop_label(inst, ls.get(i).cloned().unwrap_or(ln)));
+                return Ok(self.pop_label(ls.get(i).cloned().unwrap_or(ln)));
             }
             Return => return Ok(self.exit_frame()),
             Call(x) => return self.invoke(store, store.func_ptr(inst_id, x)),
             CallIndirect(x, y) => {
-                let i = self.pop_value().unwrap_i32();
-                let x = match store.table(inst_id, x).elems.get(i as usize) {
-                    None | Some(Val::Null(_)) => return Err(trap()),
-                    Some(x) => x.unwrap_ref(),
-                };
-                if store.func_type(x) != store.insts[inst_id].module.types()[y as usize] {
-                    return Err(trap());
-                }
-                return self.invoke(store, x);
+                let ptr = self.indirect_ptr(store, inst_id, x, y)?;
+                return self.invoke(store, ptr);
+            }
+            ReturnCall(x) => return self.tail_invoke(store, store.func_ptr(inst_id, x)),
+            ReturnCallIndirect(x, y) => {
+                let ptr = self.indirect_ptr(store, inst_id, x, y)?;
+                return self.tail_invoke(store, ptr);
             }
             Drop => drop(self.pop_value()),
             Select(_) => {
@@ -777,34 +1566,118 @@ impl<'m> Thread<'m> {
                 self.frame().locals[x as usize] = v;
             }
             GlobalGet(x) => self.push_value(store.global(inst_id, x).value),
-            GlobalSet(x) => store.global(inst_id, x).value = self.pop_value(),
+            GlobalSet(x) => {
+                let v = self.pop_value();
+                let global = store.global(inst_id, x);
+                global.value = v.val(global.value.type_());
+            }
             TableGet(x) => {
                 let i = self.pop_value().unwrap_i32();
-                let v = *store.table(inst_id, x).elems.get(i as usize).ok_or_else(trap)?;
+                let v = *store
+                    .table(inst_id, x)
+                    .elems
+                    .get(i as usize)
+                    .ok_or_else(table_out_of_bounds)?;
                 self.push_value(v);
             }
-            TableSet(x) => {
-                let val = self.pop_value();
-                let i = self.pop_value().unwrap_i32();
-                let v = store.table(inst_id, x).elems.get_mut(i as usize).ok_or_else(trap)?;
-                *v = val;
+            TableSet(x) => {
+                let val = self.pop_value();
+                let i = self.pop_value().unwrap_i32();
+                let table = store.table(inst_id, x);
+                let val = val.val(table.item.into());
+                let v = table.elems.get_mut(i as usize).ok_or_else(table_out_of_bounds)?;
+                *v = val;
+            }
+            ILoad(n, m) => {
+                self.load(store.mem(inst_id, m.memory), NumType::i(n), n.into(), Sx::U, m)?
+            }
+            #[cfg(feature = "float-types")]
+            FLoad(n, m) => {
+                self.load(store.mem(inst_id, m.memory), NumType::f(n), n.into(), Sx::U, m)?
+            }
+            ILoad_(b, s, m) => {
+                self.load(store.mem(inst_id, m.memory), NumType::i(b.into()), b.into(), s, m)?
+            }
+            IStore(n, m) => self.store(store.mem(inst_id, m.memory), NumType::i(n), n.into(), m)?,
+            #[cfg(feature = "float-types")]
+            FStore(n, m) => self.store(store.mem(inst_id, m.memory), NumType::f(n), n.into(), m)?,
+            IStore_(b, m) => {
+                self.store(store.mem(inst_id, m.memory), NumType::i(b.into()), b.into(), m)?
+            }
+            MemorySize(x) => self.push_value(Val::I32(store.mem(inst_id, x).size())),
+            MemoryGrow(x) => {
+                let n = self.pop_value().unwrap_i32();
+                self.push_value(Val::I32(grow(store.mem(inst_id, x), n, ())));
+            }
+            #[cfg(feature = "threads")]
+            AtomicNotify(m) => {
+                let count = self.pop_value().unwrap_i32();
+                let i = self.pop_value().unwrap_i32();
+                let mem = store.mem(inst_id, m.memory);
+                self.atomic_slice(mem, m, i, 4)?;
+                // There are no waiters on unshared memories.
+                if !mem.shared {
+                    self.push_value(Val::I32(0));
+                } else {
+                    let (mem, addr) = (m.memory, i + m.offset);
+                    return Ok(ThreadResult::Atomic(AtomicOp::Notify { mem, addr, count }));
+                }
+            }
+            #[cfg(feature = "threads")]
+            AtomicWait(n, m) => {
+                let timeout = self.pop_value().unwrap_i64() as i64;
+                let expected = self.pop_atomic(NumType::i(n));
+                let i = self.pop_value().unwrap_i32();
+                let mem = store.mem(inst_id, m.memory);
+                let value = atomic_read(self.atomic_slice(mem, m, i, usize::from(n) / 8)?);
+                if !mem.shared {
+                    return Err(trap(TrapReason::UnsharedMemory));
+                }
+                if value != expected {
+                    self.push_value(Val::I32(1));
+                } else {
+                    let (mem, addr) = (m.memory, i + m.offset);
+                    let timeout = u64::try_from(timeout).ok();
+                    return Ok(ThreadResult::Atomic(AtomicOp::Wait { mem, addr, timeout }));
+                }
+            }
+            // Threads of a store are interleaved, so all accesses are sequentially consistent.
+            #[cfg(feature = "threads")]
+            AtomicFence => (),
+            #[cfg(feature = "threads")]
+            AtomicLoad(n, m) => {
+                self.atomic_load(store.mem(inst_id, m.memory), NumType::i(n), n.into(), m)?
+            }
+            #[cfg(feature = "threads")]
+            AtomicLoad_(b, m) => {
+                self.atomic_load(store.mem(inst_id, m.memory), NumType::i(b.into()), b.into(), m)?
+            }
+            #[cfg(feature = "threads")]
+            AtomicStore(n, m) => {
+                self.atomic_store(store.mem(inst_id, m.memory), NumType::i(n), n.into(), m)?
+            }
+            #[cfg(feature = "threads")]
+            AtomicStore_(b, m) => {
+                self.atomic_store(store.mem(inst_id, m.memory), NumType::i(b.into()), b.into(), m)?
+            }
+            #[cfg(feature = "threads")]
+            AtomicRmw(op, n, m) => {
+                let t = NumType::i(n);
+                self.atomic_rmw(store.mem(inst_id, m.memory), Some(op), t, n.into(), m)?
             }
-            ILoad(n, m) => self.load(store.mem(inst_id, 0), NumType::i(n), n.into(), Sx::U, m)?,
-            #[cfg(feature = "float-types")]
-            FLoad(n, m) => self.load(store.mem(inst_id, 0), NumType::f(n), n.into(), Sx::U, m)?,
-            ILoad_(b, s, m) => {
-                self.load(store.mem(inst_id, 0), NumType::i(b.into()), b.into(), s, m)?
+            #[cfg(feature = "threads")]
+            AtomicRmw_(op, b, m) => {
+                let t = NumType::i(b.into());
+                self.atomic_rmw(store.mem(inst_id, m.memory), Some(op), t, b.into(), m)?
             }
-            IStore(n, m) => self.store(store.mem(inst_id, 0), NumType::i(n), n.into(), m)?,
-            #[cfg(feature = "float-types")]
-            FStore(n, m) => self.store(store.mem(inst_id, 0), NumType::f(n), n.into(), m)?,
-            IStore_(b, m) => {
-                self.store(store.mem(inst_id, 0), NumType::i(b.into()), b.into(), m)?
+            #[cfg(feature = "threads")]
+            AtomicCmpxchg(n, m) => {
+                self.atomic_rmw(store.mem(inst_id, m.memory), None, NumType::i(n), n.into(), m)?
             }
-            MemorySize => self.push_value(Val::I32(store.mem(inst_id, 0).size())),
-            MemoryGrow => {
-                let n = self.pop_value().unwrap_i32();
-                self.push_value(Val::I32(grow(store.mem(inst_id, 0), n, ())));
+            #[cfg(feature = "threads")]
+            AtomicCmpxchg_(b, m) => {
+                let t = NumType::i(b.into());
+                self.atomic_rmw(store.mem(inst_id, m.memory), None, t, b.into(), m)?
             }
             I32Const(c) => self.push_value(Val::I32(c)),
             I64Const(c) => self.push_value(Val::I64(c)),
@@ -826,44 +1699,60 @@ impl<'m> Thread<'m> {
             IExtend(b) => self.extend(b),
             RefNull(t) => self.push_value(Val::Null(t)),
             RefIsNull => {
-                let c = matches!(self.pop_value(), Val::Null(_)) as u32;
+                let c = self.pop_value().is_null() as u32;
                 self.push_value(Val::I32(c));
             }
             RefFunc(x) => self.push_value(Val::Ref(store.func_ptr(inst_id, x))),
-            MemoryInit(x) => {
+            MemoryInit(x, y) => {
                 let n = self.pop_value().unwrap_i32() as usize;
                 let s = self.pop_value().unwrap_i32() as usize;
                 let d = self.pop_value().unwrap_i32() as usize;
-                let data = if inst.datas[x as usize] {
-                    &[]
+                let data = if inst.datas[y as usize] {
+                    Parser::default()
                 } else {
-                    let mut parser = inst.module.data(x);
+                    let mut parser = inst.module.data(y);
                     let mut data = ComputeData::new(store, inst_id);
                     parser.parse_data(&mut data).into_ok();
                     data.init
                 };
-                let mem = store.mem(inst_id, 0);
+                let mem = store.mem(inst_id, x);
                 memory_init(d, s, n, mem, data)?;
             }
             DataDrop(x) => inst.datas[x as usize] = true,
-            MemoryCopy => {
+            MemoryCopy(x, y) if inst.mems.ptr(inst_id, x) == inst.mems.ptr(inst_id, y) => {
                 let n = self.pop_value().unwrap_i32() as usize;
                 let s = self.pop_value().unwrap_i32() as usize;
                 let d = self.pop_value().unwrap_i32() as usize;
-                let mem = store.mem(inst_id, 0);
-                if core::cmp::max(s, d).checked_add(n).map_or(true, |x| x > mem.len() as usize) {
-                    return Err(trap());
+                let mem = store.mem(inst_id, x);
+                let x = core::cmp::max(s, d);
+                if x.checked_add(n).map_or(true, |x| x > mem.len() as usize) {
+                    return Err(memory_too_small(x, n, mem));
                 }
                 mem.data.copy_within(s .. s + n, d);
             }
-            MemoryFill => {
+            MemoryCopy(x, y) => {
+                let n = self.pop_value().unwrap_i32() as usize;
+                let s = self.pop_value().unwrap_i32() as usize;
+                let d = self.pop_value().unwrap_i32() as usize;
+                // TODO: This is not efficient.
+                let src = store.mem(inst_id, y);
+                if s.checked_add(n).map_or(true, |x| x > src.len() as usize) {
+                    return Err(memory_too_small(s, n, src));
+                }
+                let data = src.data[s ..][.. n].to_vec();
+                let dst = store.mem(inst_id, x);
+                if d.checked_add(n).map_or(true, |x| x > dst.len() as usize) {
+                    return Err(memory_too_small(d, n, dst));
+                }
+                dst.data[d ..][.. n].copy_from_slice(&data);
+            }
+            MemoryFill(x) => {
                 let n = self.pop_value().unwrap_i32() as usize;
                 let val = self.pop_value().unwrap_i32() as u8;
                 let d = self.pop_value().unwrap_i32() as usize;
-                let mem = store.mem(inst_id, 0);
+                let mem = store.mem(inst_id, x);
                 if d.checked_add(n).map_or(true, |x| x > mem.len() as usize) {
-                    memory_too_small(d, n, mem);
-                    return Err(trap());
+                    return Err(memory_too_small(d, n, mem));
                 }
                 mem.data[d ..][.. n].fill(val);
             }
@@ -887,17 +1776,27 @@ impl<'m> Thread<'m> {
                 let n = self.pop_value().unwrap_i32() as usize;
                 let s = self.pop_value().unwrap_i32() as usize;
                 let d = self.pop_value().unwrap_i32() as usize;
-                let sn = s.checked_add(n).ok_or_else(trap)?;
-                let dn = d.checked_add(n).ok_or_else(trap)?;
+                let sn = s.checked_add(n).ok_or_else(table_out_of_bounds)?;
+                let dn = d.checked_add(n).ok_or_else(table_out_of_bounds)?;
                 // TODO: This is not efficient.
-                let ys = store.table(inst_id, y).elems.get(s .. sn).ok_or_else(trap)?.to_vec();
-                let xs = store.table(inst_id, x).elems.get_mut(d .. dn).ok_or_else(trap)?;
+                let ys = store
+                    .table(inst_id, y)
+                    .elems
+                    .get(s .. sn)
+                    .ok_or_else(table_out_of_bounds)?
+                    .to_vec();
+                let xs = store
+                    .table(inst_id, x)
+                    .elems
+                    .get_mut(d .. dn)
+                    .ok_or_else(table_out_of_bounds)?;
                 xs.copy_from_slice(&ys);
             }
             TableGrow(x) => {
                 let n = self.pop_value().unwrap_i32();
                 let val = self.pop_value();
                 let table = store.table(inst_id, x);
+                let val = val.val(table.item.into());
                 self.push_value(Val::I32(grow(table, n, val)));
             }
             TableSize(x) => self.push_value(Val::I32(store.table(inst_id, x).size())),
@@ -906,13 +1805,128 @@ impl<'m> Thread<'m> {
                 let val = self.pop_value();
                 let i = self.pop_value().unwrap_i32() as usize;
                 let table = store.table(inst_id, x);
+                let val = val.val(table.item.into());
                 if i.checked_add(n).map_or(true, |x| x > table.elems.len()) {
-                    return Err(trap());
+                    return Err(table_out_of_bounds());
                 }
                 table.elems[i ..][.. n].fill(val);
             }
+            #[cfg(feature = "vector-types")]
+            V128Load(m) => {
+                let x = self.vload(store.mem(inst_id, m.memory), m, 16)?;
+                self.push_value(Val::V128(x));
+            }
+            #[cfg(feature = "vector-types")]
+            V128LoadExtend(s, sx, m) => {
+                let x = self.vload(store.mem(inst_id, m.memory), m, 8)?;
+                self.push_value(Val::V128(s.extend(Half::Low, sx, x)));
+            }
+            #[cfg(feature = "vector-types")]
+            V128LoadSplat(s, m) => {
+                let x = self.vload(store.mem(inst_id, m.memory), m, s.width() as usize / 8)?;
+                self.push_value(Val::V128(s.splat(x as u64)));
+            }
+            #[cfg(feature = "vector-types")]
+            V128LoadZero(n, m) => {
+                let x = self.vload(store.mem(inst_id, m.memory), m, usize::from(n) / 8)?;
+                self.push_value(Val::V128(x));
+            }
+            #[cfg(feature = "vector-types")]
+            V128LoadLane(s, m, i) => {
+                let x = self.pop_value().unwrap_v128();
+                let v = self.vload(store.mem(inst_id, m.memory), m, s.width() as usize / 8)?;
+                self.push_value(Val::V128(s.replace(x, i as usize, v as u64)));
+            }
+            #[cfg(feature = "vector-types")]
+            V128Store(m) => {
+                let x = self.pop_value().unwrap_v128();
+                self.vstore(store.mem(inst_id, m.memory), m, &x.to_le_bytes())?;
+            }
+            #[cfg(feature = "vector-types")]
+            V128StoreLane(s, m, i) => {
+                let x = self.pop_value().unwrap_v128();
+                let v = s.lane(x, i as usize).to_le_bytes();
+                self.vstore(store.mem(inst_id, m.memory), m, &v[.. s.width() as usize / 8])?;
+            }
+            #[cfg(feature = "vector-types")]
+            V128Const(c) => self.push_value(Val::V128(c)),
+            #[cfg(feature = "vector-types")]
+            I8x16Shuffle(ls) => self.vbinop(|x, y| simd::shuffle(&ls, x, y)),
+            #[cfg(feature = "vector-types")]
+            I8x16Swizzle => self.vbinop(simd::swizzle),
+            #[cfg(feature = "vector-types")]
+            VSplat(s) => {
+                let v = self.pop_value().unwrap_lane();
+                self.push_value(Val::V128(s.splat(v)));
+            }
+            #[cfg(feature = "vector-types")]
+            VExtractLane(s, sx, i) => {
+                let x = self.pop_value().unwrap_v128();
+                let v = s.extract(x, sx, i as usize);
+                self.push_value(match s.unpacked() {
+                    NumType::I32 => Val::I32(v as u32),
+                    NumType::I64 => Val::I64(v),
+                    NumType::F32 => {
+                        support_if!("float-types"[], Val::F32(v as u32), unreachable!())
+                    }
+                    NumType::F64 => support_if!("float-types"[], Val::F64(v), unreachable!()),
+                });
+            }
+            #[cfg(feature = "vector-types")]
+            VReplaceLane(s, i) => {
+                let v = self.pop_value().unwrap_lane();
+                let x = self.pop_value().unwrap_v128();
+                self.push_value(Val::V128(s.replace(x, i as usize, v)));
+            }
+            #[cfg(feature = "vector-types")]
+            VIRelOp(s, op) => self.vbinop(|x, y| op.vector(s, x, y)),
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VFRelOp(s, op) => self.vbinop(|x, y| op.vector(s, x, y)),
+            #[cfg(feature = "vector-types")]
+            V128Not => self.vunop(|x| !x),
+            #[cfg(feature = "vector-types")]
+            VBinOp(op) => self.vbinop(|x, y| op.eval(x, y)),
+            #[cfg(feature = "vector-types")]
+            V128Bitselect => {
+                let c = self.pop_value().unwrap_v128();
+                self.vbinop(|x, y| simd::bitselect(x, y, c));
+            }
+            #[cfg(feature = "vector-types")]
+            V128AnyTrue => self.vtestop(|x| (x != 0) as u32),
+            #[cfg(feature = "vector-types")]
+            VIUnOp(s, op) => self.vunop(|x| op.eval(s, x)),
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VFUnOp(s, op) => self.vunop(|x| op.vector(s, x)),
+            #[cfg(feature = "vector-types")]
+            VIBinOp(s, op) => self.vbinop(|x, y| op.eval(s, x, y)),
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VFBinOp(s, op) => self.vbinop(|x, y| op.eval(s, x, y)),
+            #[cfg(feature = "vector-types")]
+            VShiftOp(s, op) => {
+                let n = self.pop_value().unwrap_i32();
+                self.vunop(|x| op.eval(s, x, n));
+            }
+            #[cfg(feature = "vector-types")]
+            VAllTrue(s) => self.vtestop(|x| s.all_true(x) as u32),
+            #[cfg(feature = "vector-types")]
+            VBitmask(s) => self.vtestop(|x| s.bitmask(x)),
+            #[cfg(feature = "vector-types")]
+            VNarrow(s, sx) => self.vbinop(|x, y| s.narrow(sx, x, y)),
+            #[cfg(feature = "vector-types")]
+            VExtend(s, h, sx) => self.vunop(|x| s.extend(h, sx, x)),
+            #[cfg(feature = "vector-types")]
+            VExtMul(s, h, sx) => self.vbinop(|x, y| s.ext_mul(h, sx, x, y)),
+            #[cfg(feature = "vector-types")]
+            VExtAddPairwise(s, sx) => self.vunop(|x| s.ext_add_pairwise(sx, x)),
+            #[cfg(feature = "vector-types")]
+            I32x4DotI16x8S => self.vbinop(simd::dot),
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VCvtOp(op) => self.vunop(|x| op.eval(x)),
         }
-        Ok(ThreadResult::Continue(self))
+        // Values pushed by the instruction are accounted here. The stack exceeds the limit by at
+        // most the values pushed by a single instruction.
+        check_stack(store.stack_limit, self.stack())?;
+        Ok(ThreadResult::Continue)
     }
 
     fn inst_id(&self) -> usize {
@@ -927,44 +1941,44 @@ impl<'m> Thread<'m> {
         self.frames.last_mut().unwrap()
     }
 
-    fn labels(&mut self) -> &mut Vec<Label<'m>> {
+    fn labels(&mut self) -> &mut Vec<Label> {
         &mut self.frame().labels
     }
 
-    fn label(&mut self) -> &mut Label<'m> {
+    fn label(&mut self) -> &mut Label {
         self.labels().last_mut().unwrap()
     }
 
-    fn values(&mut self) -> &mut Vec<Val> {
+    fn values(&mut self) -> &mut Vec<Slot> {
         &mut self.label().values
     }
 
-    fn peek_value(&mut self) -> Val {
+    fn peek_value(&mut self) -> Slot {
         *self.values().last().unwrap()
     }
 
-    fn push_value(&mut self, value: Val) {
-        self.values().push(value);
+    fn push_value(&mut self, value: impl Into<Slot>) {
+        self.values().push(value.into());
     }
 
-    fn push_value_or_trap(&mut self, value: Option<Val>) -> Result<(), Error> {
+    fn push_value_or_trap(&mut self, value: Option<Val>, reason: TrapReason) -> Result<(), Error> {
         if let Some(x) = value {
             self.push_value(x);
             Ok(())
         } else {
-            Err(trap())
+            Err(trap(reason))
         }
     }
 
-    fn push_values(&mut self, values: &[Val]) {
+    fn push_values(&mut self, values: &[Slot]) {
         self.values().extend_from_slice(values);
     }
 
-    fn pop_value(&mut self) -> Val {
+    fn pop_value(&mut self) -> Slot {
         self.values().pop().unwrap()
     }
 
-    fn pop_values(&mut self, n: usize) -> Vec<Val> {
+    fn pop_values(&mut self, n: usize) -> Vec<Slot> {
         let mut values = Vec::new();
         for _ in 0 .. n {
             values.push(self.pop_value());
@@ -973,17 +1987,33 @@ impl<'m> Thread<'m> {
         values
     }
 
-    fn push_label(&mut self, type_: FuncType<'m>, kind: LabelKind<'m>) {
+    fn push_label(
+        &mut self, limit: usize, type_: FuncType<'m>, kind: LabelKind,
+    ) -> Result<(), Error> {
         let values = self.pop_values(type_.params.len());
         let arity = match kind {
-            LabelKind::Block | LabelKind::If => type_.results.len(),
+            LabelKind::Block(_) => type_.results.len(),
             LabelKind::Loop(_) => type_.params.len(),
         };
-        let label = Label { arity, kind, values };
+        let stack = self.stack() + size_of::<Label>();
+        check_stack(limit, stack + values.len() * size_of::<Slot>())?;
+        let label = Label { arity, kind, values, stack };
         self.labels().push(label);
+        Ok(())
+    }
+
+    /// Returns the stack usage of this thread in bytes.
+    fn stack(&self) -> usize {
+        match self.frames.last() {
+            None => 0,
+            Some(frame) => {
+                let label = frame.labels.last().unwrap();
+                label.stack + label.values.len() * size_of::<Slot>()
+            }
+        }
     }
 
-    fn pop_label(mut self, inst: &mut Instance<'m>, l: LabelIdx) -> ThreadResult<'m> {
+    fn pop_label(&mut self, l: LabelIdx) -> ThreadResult {
         let i = self.labels().len() - l as usize - 1;
         if i == 0 {
             return self.exit_frame();
@@ -993,13 +2023,12 @@ impl<'m> Thread<'m> {
         let Label { arity, kind, .. } = frame.labels.drain(i ..).next().unwrap();
         self.values().extend_from_slice(&values[values.len() - arity ..]);
         match kind {
-            LabelKind::Loop(pos) => unsafe { self.parser.restore(pos) },
-            LabelKind::Block | LabelKind::If => self.skip_to_end(inst, l),
+            LabelKind::Block(target) | LabelKind::Loop(target) => self.jump(target),
         }
-        ThreadResult::Continue(self)
+        ThreadResult::Continue
     }
 
-    fn exit_label(mut self) -> ThreadResult<'m> {
+    fn exit_label(&mut self) -> ThreadResult {
         let frame = self.frame();
         let label = frame.labels.pop().unwrap();
         if frame.labels.is_empty() {
@@ -1011,10 +2040,10 @@ impl<'m> Thread<'m> {
             unsafe { self.parser.restore(frame.ret) };
         }
         self.values().extend_from_slice(&label.values);
-        ThreadResult::Continue(self)
+        ThreadResult::Continue
     }
 
-    fn exit_frame(mut self) -> ThreadResult<'m> {
+    fn exit_frame(&mut self) -> ThreadResult {
         let mut values = core::mem::take(self.values());
         let frame = self.frames.pop().unwrap();
         let mid = values.len() - frame.arity;
@@ -1024,15 +2053,27 @@ impl<'m> Thread<'m> {
         }
         unsafe { self.parser.restore(frame.ret) };
         self.values().extend_from_slice(&values[mid ..]);
-        ThreadResult::Continue(self)
+        ThreadResult::Continue
     }
 
-    fn skip_to_else(&mut self, inst: &mut Instance<'m>) {
-        inst.module.skip_to_else(&mut self.parser);
+    /// Returns the else and end targets of the `block` or `if` at a given position.
+    ///
+    /// This advances the side table cursor past the entry of the instruction.
+    fn side_entry(&mut self, inst: &Instance<'m>, pos: &Parser<'m>) -> (Target, Target) {
+        let frame = self.frame();
+        let entry = inst.module.side_entry(frame.func, frame.cursor);
+        frame.cursor += 1;
+        let len = pos.len();
+        let else_ =
+            Target { len: len - entry.else_pos as usize, cursor: entry.else_cursor as usize };
+        let end = Target { len: len - entry.end_pos as usize, cursor: entry.end_cursor as usize };
+        (else_, end)
     }
 
-    fn skip_to_end(&mut self, inst: &mut Instance<'m>, l: LabelIdx) {
-        inst.module.skip_to_end(&mut self.parser, l);
+    fn jump(&mut self, target: Target) {
+        // SAFETY: Targets are positions in the function body of the current frame.
+        unsafe { self.parser.seek(target.len) };
+        self.frame().cursor = target.cursor;
     }
 
     fn blocktype(&self, inst: &Instance<'m>, b: &BlockType) -> FuncType<'m> {
@@ -1045,23 +2086,100 @@ impl<'m> Thread<'m> {
 
     fn mem_slice<'a>(
         &mut self, mem: &'a mut Memory<'m>, m: MemArg, i: u32, len: usize,
-    ) -> Option<&'a mut [u8]> {
-        let ea = i.checked_add(m.offset)?;
-        if ea.checked_add(len as u32)? > mem.len() {
-            memory_too_small(ea as usize, len, mem);
-            return None;
+    ) -> Result<&'a mut [u8], Error> {
+        let ea = i.checked_add(m.offset).ok_or_else(memory_out_of_bounds)? as usize;
+        if ea.checked_add(len).map_or(true, |x| x > mem.len() as usize) {
+            return Err(memory_too_small(ea, len, mem));
+        }
+        Ok(&mut mem.data[ea ..][.. len])
+    }
+
+    /// Returns the memory accessed by an atomic instruction.
+    #[cfg(feature = "threads")]
+    fn atomic_slice<'a>(
+        &mut self, mem: &'a mut Memory<'m>, m: MemArg, i: u32, len: usize,
+    ) -> Result<&'a mut [u8], Error> {
+        let mem = self.mem_slice(mem, m, i, len)?;
+        // The effective address doesn't overflow since the access is in bounds.
+        if (i + m.offset) % len as u32 != 0 {
+            return Err(trap(TrapReason::UnalignedAtomic));
+        }
+        Ok(mem)
+    }
+
+    #[cfg(feature = "threads")]
+    fn pop_atomic(&mut self, t: NumType) -> u64 {
+        let x = self.pop_value();
+        match t {
+            NumType::I32 => x.unwrap_i32() as u64,
+            NumType::I64 => x.unwrap_i64(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[cfg(feature = "threads")]
+    fn push_atomic(&mut self, t: NumType, x: u64) {
+        match t {
+            NumType::I32 => self.push_value(Val::I32(x as u32)),
+            NumType::I64 => self.push_value(Val::I64(x)),
+            _ => unreachable!(),
         }
-        Some(&mut mem.data[ea as usize ..][.. len])
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_load(
+        &mut self, mem: &mut Memory<'m>, t: NumType, n: usize, m: MemArg,
+    ) -> Result<(), Error> {
+        let i = self.pop_value().unwrap_i32();
+        let x = atomic_read(self.atomic_slice(mem, m, i, n / 8)?);
+        self.push_atomic(t, x);
+        Ok(())
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_store(
+        &mut self, mem: &mut Memory<'m>, t: NumType, n: usize, m: MemArg,
+    ) -> Result<(), Error> {
+        let x = self.pop_atomic(t);
+        let i = self.pop_value().unwrap_i32();
+        atomic_write(self.atomic_slice(mem, m, i, n / 8)?, x);
+        Ok(())
+    }
+
+    /// Executes a read-modify-write instruction (or compare-exchange if there is no operation).
+    #[cfg(feature = "threads")]
+    fn atomic_rmw(
+        &mut self, mem: &mut Memory<'m>, op: Option<RmwOp>, t: NumType, n: usize, m: MemArg,
+    ) -> Result<(), Error> {
+        let x = self.pop_atomic(t);
+        let expected = match op {
+            None => Some(self.pop_atomic(t)),
+            Some(_) => None,
+        };
+        let i = self.pop_value().unwrap_i32();
+        let mem = self.atomic_slice(mem, m, i, n / 8)?;
+        let old = atomic_read(mem);
+        let new = match op {
+            Some(RmwOp::Add) => old.wrapping_add(x),
+            Some(RmwOp::Sub) => old.wrapping_sub(x),
+            Some(RmwOp::And) => old & x,
+            Some(RmwOp::Or) => old | x,
+            Some(RmwOp::Xor) => old ^ x,
+            Some(RmwOp::Xchg) => x,
+            // The expected value is wrapped to the access width.
+            None if Some(old) == expected.map(|y| y & (u64::MAX >> (64 - n))) => x,
+            None => old,
+        };
+        atomic_write(mem, new);
+        self.push_atomic(t, old);
+        Ok(())
     }
 
     fn load(
         &mut self, mem: &mut Memory<'m>, t: NumType, n: usize, s: Sx, m: MemArg,
     ) -> Result<(), Error> {
         let i = self.pop_value().unwrap_i32();
-        let mem = match self.mem_slice(mem, m, i, n / 8) {
-            None => return Err(trap()),
-            Some(x) => x,
-        };
+        let mem = self.mem_slice(mem, m, i, n / 8)?;
         macro_rules! convert {
             ($T:ident, $t:ident, $s:ident) => {
                 Val::$T($s::from_le_bytes(mem.try_into().unwrap()) as $t)
@@ -1095,10 +2213,7 @@ impl<'m> Thread<'m> {
     ) -> Result<(), Error> {
         let c = self.pop_value();
         let i = self.pop_value().unwrap_i32();
-        let mem = match self.mem_slice(mem, m, i, n / 8) {
-            None => return Err(trap()),
-            Some(x) => x,
-        };
+        let mem = self.mem_slice(mem, m, i, n / 8)?;
         macro_rules! convert {
             ($s:ident, $t:ident) => {
                 paste::paste! {
@@ -1123,6 +2238,43 @@ impl<'m> Thread<'m> {
         Ok(())
     }
 
+    /// Loads a vector (or part of it) zero-extended.
+    #[cfg(feature = "vector-types")]
+    fn vload(&mut self, mem: &mut Memory<'m>, m: MemArg, n: usize) -> Result<u128, Error> {
+        let i = self.pop_value().unwrap_i32();
+        let mem = self.mem_slice(mem, m, i, n)?;
+        let mut bytes = [0; 16];
+        bytes[.. n].copy_from_slice(mem);
+        Ok(u128::from_le_bytes(bytes))
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn vstore(&mut self, mem: &mut Memory<'m>, m: MemArg, bytes: &[u8]) -> Result<(), Error> {
+        let i = self.pop_value().unwrap_i32();
+        let mem = self.mem_slice(mem, m, i, bytes.len())?;
+        mem.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn vtestop(&mut self, op: impl FnOnce(u128) -> u32) {
+        let x = self.pop_value().unwrap_v128();
+        self.push_value(Val::I32(op(x)));
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn vunop(&mut self, op: impl FnOnce(u128) -> u128) {
+        let x = self.pop_value().unwrap_v128();
+        self.push_value(Val::V128(op(x)));
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn vbinop(&mut self, op: impl FnOnce(u128, u128) -> u128) {
+        let y = self.pop_value().unwrap_v128();
+        let x = self.pop_value().unwrap_v128();
+        self.push_value(Val::V128(op(x, y)));
+    }
+
     fn itestop(&mut self, n: Nx, op: ITestOp) {
         let x = self.pop_value();
         let z = match n {
@@ -1150,7 +2302,7 @@ impl<'m> Thread<'m> {
                 Nx::N64 => Val::I64(op.n64(x.unwrap_i64())?),
             }
         };
-        self.push_value_or_trap(z)
+        self.push_value_or_trap(z, TrapReason::IntegerOverflow)
     }
 
     fn ibinop(&mut self, n: Nx, op: IBinOp) -> Result<(), Error> {
@@ -1162,7 +2314,15 @@ impl<'m> Thread<'m> {
                 Nx::N64 => Val::I64(op.n64(x.unwrap_i64(), y.unwrap_i64())?),
             }
         };
-        self.push_value_or_trap(z)
+        let zero = match n {
+            Nx::N32 => y.unwrap_i32() == 0,
+            Nx::N64 => y.unwrap_i64() == 0,
+        };
+        let reason = match zero {
+            true => TrapReason::DivisionByZero,
+            false => TrapReason::IntegerOverflow,
+        };
+        self.push_value_or_trap(z, reason)
     }
 
     #[cfg(feature = "float-types")]
@@ -1275,7 +2435,18 @@ impl<'m> Thread<'m> {
                 },
             }
         };
-        self.push_value_or_trap(z)
+        let nan = match op {
+            #[cfg(feature = "float-types")]
+            Trunc(_, Nx::N32, _) => f32::from_bits(x.unwrap_f32()).is_nan(),
+            #[cfg(feature = "float-types")]
+            Trunc(_, Nx::N64, _) => f64::from_bits(x.unwrap_f64()).is_nan(),
+            _ => false,
+        };
+        let reason = match nan {
+            true => TrapReason::InvalidConversion,
+            false => TrapReason::IntegerOverflow,
+        };
+        self.push_value_or_trap(z, reason)
     }
 
     fn extend(&mut self, n: Bx) {
@@ -1290,21 +2461,52 @@ impl<'m> Thread<'m> {
         self.push_value(z)
     }
 
-    fn invoke(mut self, store: &mut Store<'m>, ptr: Ptr) -> Result<ThreadResult<'m>, Error> {
-        // TODO: This should be based on actual size in RAM.
-        const MAX_FRAMES: usize = 1000;
-        if self.frames.len() >= MAX_FRAMES {
-            return Err(trap());
+    fn indirect_ptr(
+        &mut self, store: &mut Store<'m>, inst_id: usize, x: TableIdx, y: TypeIdx,
+    ) -> Result<Ptr, Error> {
+        let i = self.pop_value().unwrap_i32();
+        let ptr = match store.table(inst_id, x).elems.get(i as usize) {
+            None => return Err(trap(TrapReason::TableOutOfBounds)),
+            Some(Val::Null(_)) => return Err(trap(TrapReason::UninitializedElement)),
+            Some(x) => x.unwrap_ref(),
+        };
+        if store.func_type(ptr) != store.insts[inst_id].module.types()[y as usize] {
+            return Err(trap(TrapReason::IndirectCallMismatch));
+        }
+        Ok(ptr)
+    }
+
+    /// Invokes a function in place of the current frame.
+    fn tail_invoke(&mut self, store: &mut Store<'m>, ptr: Ptr) -> Result<ThreadResult, Error> {
+        let t = store.func_type(ptr);
+        let args = self.pop_values(t.params.len());
+        let frame = self.frames.pop().unwrap();
+        if self.frames.is_empty() {
+            // There is no caller to return to. We replace the frame with an empty frame positioned
+            // at the end of the current function, such that the callee results are returned when
+            // it returns (this is also needed when the callee is a host function).
+            // SAFETY: Function bodies end with the end instruction.
+            unsafe { self.parser.seek(1) };
+            let stack = self.stack();
+            let arity = t.results.len();
+            let ret = Parser::default();
+            self.frames.push(Frame::new(frame.inst_id, frame.func, arity, ret, Vec::new(), stack));
+        } else {
+            // SAFETY: The return position was saved when the frame was created.
+            unsafe { self.parser.restore(frame.ret) };
         }
+        self.push_values(&args);
+        self.invoke(store, ptr)
+    }
+
+    fn invoke(&mut self, store: &mut Store<'m>, ptr: Ptr) -> Result<ThreadResult, Error> {
         let t = store.func_type(ptr);
         let inst_id = match ptr.instance() {
             Side::Host => {
                 let index = ptr.index() as usize;
-                let t = store.funcs[index].1;
                 let arity = t.results.len();
-                let args = self.pop_values(t.params.len());
-                store.threads.push(Continuation { thread: self, arity, index, args });
-                return Ok(ThreadResult::Host);
+                let args = vals(self.pop_values(t.params.len()), &t.params);
+                return Ok(ThreadResult::Host(HostCall { index, args, arity }));
             }
             Side::Wasm(x) => x,
         };
@@ -1312,9 +2514,18 @@ impl<'m> Thread<'m> {
         let mut locals = self.pop_values(t.params.len());
         append_locals(&mut parser, &mut locals);
         let ret = self.parser.save();
+        let frame = Frame::new(inst_id, ptr.index(), t.results.len(), ret, locals, self.stack());
+        check_stack(store.stack_limit, frame.labels[0].stack)?;
         self.parser = parser;
-        self.frames.push(Frame::new(inst_id, t.results.len(), ret, locals));
-        Ok(ThreadResult::Continue(self))
+        self.frames.push(frame);
+        Ok(ThreadResult::Continue)
+    }
+}
+
+fn check_stack(limit: usize, stack: usize) -> Result<(), Error> {
+    match stack <= limit {
+        true => Ok(()),
+        false => Err(trap(TrapReason::StackExhaustion)),
     }
 }
 
@@ -1322,63 +2533,94 @@ fn table_init(d: usize, s: usize, n: usize, table: &mut Table, elems: &[Val]) ->
     if s.checked_add(n).map_or(true, |x| x > elems.len())
         || d.checked_add(n).map_or(true, |x| x > table.elems.len())
     {
-        Err(trap())
+        Err(table_out_of_bounds())
     } else {
         table.elems[d ..][.. n].copy_from_slice(&elems[s ..][.. n]);
         Ok(())
     }
 }
 
-fn memory_init(d: usize, s: usize, n: usize, mem: &mut Memory, data: &[u8]) -> Result<(), Error> {
-    if s.checked_add(n).map_or(true, |x| x > data.len())
-        || d.checked_add(n).map_or(true, |x| x > mem.len() as usize)
-    {
-        memory_too_small(d, n, mem);
-        Err(trap())
+fn memory_init(
+    d: usize, s: usize, n: usize, mem: &mut Memory, mut data: Parser,
+) -> Result<(), Error> {
+    if s.checked_add(n).map_or(true, |x| x > data.len()) {
+        Err(memory_out_of_bounds())
+    } else if d.checked_add(n).map_or(true, |x| x > mem.len() as usize) {
+        Err(memory_too_small(d, n, mem))
     } else {
-        mem.data[d ..][.. n].copy_from_slice(&data[s ..][.. n]);
+        // The data segment may cross page boundaries.
+        data.skip(s);
+        data.copy_into(&mut mem.data[d ..][.. n]);
         Ok(())
     }
 }
 
+fn memory_out_of_bounds() -> Error {
+    trap(TrapReason::MemoryOutOfBounds)
+}
+
+fn table_out_of_bounds() -> Error {
+    trap(TrapReason::TableOutOfBounds)
+}
+
 #[derive(Debug)]
 struct Frame<'m> {
     inst_id: usize,
+    /// Index of the function in its instance (excluding imported functions).
+    func: FuncIdx,
+    /// Side table entry of the next `block` or `if` in the function.
+    cursor: usize,
     arity: usize,
-    ret: &'m [u8],
-    locals: Vec<Val>,
-    labels: Vec<Label<'m>>,
+    ret: Parser<'m>,
+    locals: Vec<Slot>,
+    labels: Vec<Label>,
 }
 
 impl<'m> Frame<'m> {
-    fn new(inst_id: usize, arity: usize, ret: &'m [u8], locals: Vec<Val>) -> Self {
-        let label = Label { arity, kind: LabelKind::Block, values: vec![] };
-        Frame { inst_id, arity, ret, locals, labels: vec![label] }
+    /// Creates a frame given the stack usage of the thread before the frame.
+    fn new(
+        inst_id: usize, func: FuncIdx, arity: usize, ret: Parser<'m>, locals: Vec<Slot>,
+        stack: usize,
+    ) -> Self {
+        let size = size_of::<Frame>() + size_of::<Label>() + locals.len() * size_of::<Slot>();
+        // Branches to the function label return from the function and don't use the target.
+        let kind = LabelKind::Block(Target { len: 0, cursor: 0 });
+        let label = Label { arity, kind, values: vec![], stack: stack + size };
+        Frame { inst_id, func, cursor: 0, arity, ret, locals, labels: vec![label] }
     }
 }
 
 #[derive(Debug)]
-struct Label<'m> {
+struct Label {
     arity: usize,
-    kind: LabelKind<'m>,
-    values: Vec<Val>,
+    kind: LabelKind,
+    values: Vec<Slot>,
+    // Stack usage of the thread up to this label (excluding its values).
+    stack: usize,
 }
 
-#[derive(Debug)]
-enum LabelKind<'m> {
-    // TODO: If and Block can be merged and then we just have Option<NonNull<u8>> which is
-    // optimized.
-    Block,
-    // TODO: Could be just NonNull<u8> since we can reuse the end of current parser since it
-    // never changes.
-    Loop(&'m [u8]),
-    If,
+#[derive(Debug, Copy, Clone)]
+enum LabelKind {
+    /// Branches to the end of the block (or if).
+    Block(Target),
+    /// Branches to the start of the loop.
+    Loop(Target),
+}
+
+/// Branch target within a function body.
+#[derive(Debug, Copy, Clone)]
+struct Target {
+    /// Position as remaining length of the function body.
+    len: usize,
+    /// Side table cursor at that position.
+    cursor: usize,
 }
 
 impl Table {
     fn new(type_: TableType) -> Self {
         Table {
             max: type_.limits.max,
+            item: type_.item,
             elems: vec![Val::Null(type_.item); type_.limits.min as usize],
         }
     }
@@ -1391,23 +2633,26 @@ struct Memory<'m> {
     // The size currently available to the module. May be larger than the actual data.
     size: u32,
     max: u32,
+    shared: bool,
 }
 
 impl<'m> Memory<'m> {
-    fn init(&mut self, mut data: &'m mut [u8], limits: Limits) -> Result<(), Error> {
-        if !data.as_ptr().is_aligned_to(MEMORY_ALIGN) {
+    fn new(mut data: &'m mut [u8], limits: Limits) -> Result<Self, Error> {
+        if !data.is_empty() && !data.as_ptr().is_aligned_to(MEMORY_ALIGN) {
             return Err(invalid());
         }
         if limits.max < 0x10000 {
             let max = core::cmp::min(limits.max as usize * 0x10000, data.len());
             data = &mut data[.. max];
         }
-        self.data = data;
+        Ok(Memory { data, size: limits.min, max: limits.max, shared: false })
+    }
+
+    fn init(&mut self, data: &'m mut [u8], limits: Limits) -> Result<(), Error> {
+        *self = Memory::new(data, limits)?;
         // TODO: Figure out if we need to do this or whether we can rely on the caller to provide a
         // zeroed-out slice.
         self.data.fill(0);
-        self.size = limits.min;
-        self.max = limits.max;
         Ok(())
     }
 
@@ -1499,8 +2744,150 @@ impl_val_unwrap!(unwrap_i64, I64, u64);
 impl_val_unwrap!(unwrap_f32, F32, u32);
 #[cfg(feature = "float-types")]
 impl_val_unwrap!(unwrap_f64, F64, u64);
+#[cfg(feature = "vector-types")]
+impl_val_unwrap!(unwrap_v128, V128, u128);
 impl_val_unwrap!(unwrap_ref, Ref, Ptr);
 
+#[cfg(all(feature = "vector-types", not(feature = "untagged")))]
+impl Val {
+    /// Returns the bits of a value that may be splat in a vector.
+    fn unwrap_lane(self) -> u64 {
+        match self {
+            Val::I32(x) => x as u64,
+            Val::I64(x) => x,
+            #[cfg(feature = "float-types")]
+            Val::F32(x) => x as u64,
+            #[cfg(feature = "float-types")]
+            Val::F64(x) => x,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Values of the operand stack and locals.
+///
+/// Validation guarantees that those values are used according to their type. They are thus stored
+/// without tag when the `untagged` feature is enabled, and converted from and to [`Val`] at the
+/// boundaries (host calls, globals, tables, results, and core dumps).
+#[cfg(not(feature = "untagged"))]
+type Slot = Val;
+
+#[cfg(not(feature = "untagged"))]
+impl Val {
+    fn val(self, t: ValType) -> Val {
+        debug_assert_eq!(self.type_(), t);
+        self
+    }
+
+    fn is_null(self) -> bool {
+        matches!(self, Val::Null(_))
+    }
+}
+
+#[cfg(feature = "untagged")]
+#[derive(Copy, Clone, Debug)]
+struct Slot(SlotBits);
+
+#[cfg(all(feature = "untagged", not(feature = "vector-types")))]
+type SlotBits = u64;
+#[cfg(all(feature = "untagged", feature = "vector-types"))]
+type SlotBits = u128;
+
+// Null references are represented with a value that is neither a valid pointer nor a (reasonable)
+// external reference.
+#[cfg(feature = "untagged")]
+const SLOT_NULL: SlotBits = u64::MAX as SlotBits;
+
+#[cfg(feature = "untagged")]
+#[allow(clippy::unnecessary_cast)] // SlotBits depends on features
+impl From<Val> for Slot {
+    fn from(x: Val) -> Self {
+        Slot(match x {
+            Val::I32(x) => x as SlotBits,
+            Val::I64(x) => x as SlotBits,
+            #[cfg(feature = "float-types")]
+            Val::F32(x) => x as SlotBits,
+            #[cfg(feature = "float-types")]
+            Val::F64(x) => x as SlotBits,
+            #[cfg(feature = "vector-types")]
+            Val::V128(x) => x,
+            Val::Null(_) => SLOT_NULL,
+            Val::Ref(x) => x.0 as SlotBits,
+            Val::RefExtern(x) => {
+                debug_assert_ne!(x as SlotBits, SLOT_NULL);
+                x as SlotBits
+            }
+        })
+    }
+}
+
+#[cfg(feature = "untagged")]
+#[allow(clippy::unnecessary_cast)] // SlotBits depends on features
+impl Slot {
+    fn val(self, t: ValType) -> Val {
+        match t {
+            ValType::I32 => Val::I32(self.unwrap_i32()),
+            ValType::I64 => Val::I64(self.unwrap_i64()),
+            ValType::F32 => {
+                support_if!("float-types"[], Val::F32(self.unwrap_f32()), unreachable!())
+            }
+            ValType::F64 => {
+                support_if!("float-types"[], Val::F64(self.unwrap_f64()), unreachable!())
+            }
+            ValType::V128 => {
+                support_if!("vector-types"[], Val::V128(self.unwrap_v128()), unreachable!())
+            }
+            ValType::FuncRef if self.is_null() => Val::Null(RefType::FuncRef),
+            ValType::FuncRef => Val::Ref(self.unwrap_ref()),
+            ValType::ExternRef if self.is_null() => Val::Null(RefType::ExternRef),
+            ValType::ExternRef => Val::RefExtern(self.0 as usize),
+        }
+    }
+
+    fn is_null(self) -> bool {
+        self.0 == SLOT_NULL
+    }
+
+    fn unwrap_i32(self) -> u32 {
+        self.0 as u32
+    }
+
+    fn unwrap_i64(self) -> u64 {
+        self.0 as u64
+    }
+
+    #[cfg(feature = "float-types")]
+    fn unwrap_f32(self) -> u32 {
+        self.0 as u32
+    }
+
+    #[cfg(feature = "float-types")]
+    fn unwrap_f64(self) -> u64 {
+        self.0 as u64
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn unwrap_v128(self) -> u128 {
+        self.0
+    }
+
+    /// Returns the bits of a value that may be splat in a vector.
+    #[cfg(feature = "vector-types")]
+    fn unwrap_lane(self) -> u64 {
+        self.0 as u64
+    }
+
+    fn unwrap_ref(self) -> Ptr {
+        Ptr(self.0 as u32)
+    }
+}
+
+/// Converts slots to values given their types.
+fn vals(slots: Vec<Slot>, types: &[ValType]) -> Vec<Val> {
+    debug_assert_eq!(slots.len(), types.len());
+    slots.into_iter().zip(types).map(|(x, &t)| x.val(t)).collect()
+}
+
 impl ValType {
     fn default(self) -> Val {
         match self {
@@ -1529,11 +2916,11 @@ impl ValType {
     }
 }
 
-fn append_locals(parser: &mut Parser, locals: &mut Vec<Val>) {
+fn append_locals(parser: &mut Parser, locals: &mut Vec<Slot>) {
     for _ in 0 .. parser.parse_vec().into_ok() {
         let len = parser.parse_u32().into_ok() as usize;
         let val = parser.parse_valtype().into_ok().default();
-        locals.extend(core::iter::repeat(val).take(len));
+        locals.extend(core::iter::repeat(Slot::from(val)).take(len));
     }
 }
 
@@ -1542,9 +2929,560 @@ fn check_types(types: &[ValType], values: &[Val]) -> Result<(), Error> {
     check(types.iter().zip(values.iter()).all(|(t, x)| t.contains(*x)))
 }
 
-fn memory_too_small(x: usize, n: usize, mem: &Memory) {
-    #[cfg(not(feature = "debug"))]
-    let _ = (x, n, mem);
+/// Reads a little-endian unsigned integer of at most 64 bits.
+#[cfg(feature = "threads")]
+fn atomic_read(mem: &[u8]) -> u64 {
+    let mut bytes = [0; 8];
+    bytes[.. mem.len()].copy_from_slice(mem);
+    u64::from_le_bytes(bytes)
+}
+
+/// Writes a little-endian unsigned integer of at most 64 bits, truncating it if needed.
+#[cfg(feature = "threads")]
+fn atomic_write(mem: &mut [u8], x: u64) {
+    let n = mem.len();
+    mem.copy_from_slice(&x.to_le_bytes()[.. n]);
+}
+
+/// Returns the trap of an access beyond the memory.
+///
+/// The access is only out of bounds if it's beyond the size of the memory. Otherwise, the memory is
+/// exhausted because its size is larger than the data provided by the host.
+fn memory_too_small(x: usize, n: usize, mem: &Memory) -> Error {
     #[cfg(feature = "debug")]
     eprintln!("Memory too small: {x} + {n} > {}", mem.len());
+    match x.checked_add(n) {
+        Some(x) if x as u64 <= mem.size as u64 * 0x10000 => trap(TrapReason::MemoryExhausted),
+        _ => memory_out_of_bounds(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wat(source: &str) -> Vec<u8> {
+        let buffer = wast::parser::ParseBuffer::new(source).unwrap();
+        let mut wat: wast::Wat = wast::parser::parse(&buffer).unwrap();
+        wat.encode().unwrap()
+    }
+
+    #[test]
+    fn core_dump_is_symbolized() {
+        let wasm = wat(r#"(module
+          (func $fail (param i32) unreachable)
+          (func (export "main") (local i64) i32.const 42 call $fail))"#);
+        let mut store = Store::default();
+        let inst = store.instantiate(Module::new(&wasm).unwrap(), vec![]).unwrap();
+        let error = Some(Error::Trap(TrapReason::Unreachable));
+        assert_eq!(store.invoke(inst, "main", vec![]).err(), error);
+        let dump = store.take_core_dump().unwrap();
+        assert_eq!(dump.frames.len(), 2);
+        let frame = &dump.frames[0];
+        assert_eq!(
+            (frame.func, frame.name, &frame.locals[..]),
+            (0, Some("fail"), &[Val::I32(42)][..])
+        );
+        assert_eq!(wasm[frame.offset], 0x00); // unreachable
+        let frame = &dump.frames[1];
+        assert_eq!((frame.func, frame.name, &frame.locals[..]), (1, None, &[Val::I64(0)][..]));
+        assert_eq!(wasm[frame.offset - 2], 0x10); // call
+        assert!(store.take_core_dump().is_none());
+    }
+
+    #[cfg(feature = "profile")]
+    #[test]
+    fn profile_counts_functions() {
+        let wasm = wat(r#"(module
+          (import "env" "foo" (func $foo))
+          (func $leaf (param i32) (result i32) local.get 0 i32.const 1 i32.add)
+          (func (export "main") (result i32)
+            call $foo i32.const 1 call $leaf call $leaf))"#);
+        let mut linker = Linker::default();
+        linker.link_func("env", "foo", 0, 0).unwrap();
+        let mut store = Store::new(&linker);
+        let inst = store.instantiate(Module::new(&wasm).unwrap(), vec![]).unwrap();
+        let mut result = store.invoke(inst, "main", vec![]).unwrap();
+        while let RunResult::Host(call) = result {
+            result = call.resume(&[]).unwrap();
+        }
+        assert!(matches!(result, RunResult::Done(x) if x == [Val::I32(3)]));
+        fn counts<'m>(store: &Store<'m>, inst: InstId) -> Vec<(u32, Option<&'m str>, u64, u64)> {
+            let profile = store.profile(inst).unwrap();
+            profile.iter().map(|x| (x.func, x.name, x.instrs, x.host_calls)).collect()
+        }
+        // The leaf function executes 4 instructions (including the implicit return) twice. The
+        // main function executes 5 instructions including one host call.
+        assert_eq!(counts(&store, inst), [(1, Some("leaf"), 8, 0), (2, None, 5, 1)]);
+        store.clear_profile();
+        assert_eq!(counts(&store, inst), [(1, Some("leaf"), 0, 0), (2, None, 0, 0)]);
+    }
+
+    #[test]
+    fn names_are_parsed() {
+        let wasm = wat(r#"(module $mod
+          (import "env" "foo" (func $foo (param i32)))
+          (func $bar (param $x i32) (local i64) (local $y i64))
+          (func))"#);
+        let module = Module::new(&wasm).unwrap();
+        assert_eq!(module.name(), Some("mod"));
+        let funcs: Vec<_> = (0 .. 4).map(|x| module.func_name(x)).collect();
+        assert_eq!(funcs, [Some("foo"), Some("bar"), None, None]);
+        let locals: Vec<_> = (0 .. 4).map(|x| module.local_name(1, x)).collect();
+        assert_eq!(locals, [Some("x"), None, Some("y"), None]);
+        assert_eq!(module.local_name(2, 0), None);
+        let wasm = wat("(module)");
+        let module = Module::new(&wasm).unwrap();
+        assert_eq!((module.name(), module.func_name(0)), (None, None));
+    }
+
+    #[test]
+    fn custom_sections_are_returned() {
+        let mut wasm = wat("(module)");
+        wasm.extend_from_slice(b"\x00\x08\x03foodata");
+        let module = Module::new(&wasm).unwrap();
+        assert_eq!(module.custom_section_data("foo"), Ok(Some(&b"data"[..])));
+        assert_eq!(module.custom_section_data("bar"), Ok(None));
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn wait_and_notify_call_into_host() {
+        let wasm = wat(r#"(module
+          (memory 1 1 shared)
+          (func (export "main") (result i32 i32 i32)
+            (i32.atomic.store (i32.const 8) (i32.const 7))
+            (memory.atomic.wait32 (i32.const 8) (i32.const 7) (i64.const -1))
+            (memory.atomic.wait64 offset=8 (i32.const 0) (i64.const 0) (i64.const 1000))
+            (memory.atomic.notify (i32.const 8) (i32.const 3))))"#);
+        #[repr(align(16))]
+        struct Aligned([u8; 0x10000]);
+        let mut memory = Aligned([0; 0x10000]);
+        let mut store = Store::default();
+        let module = Module::new(&wasm).unwrap();
+        let inst = store.instantiate(module, vec![&mut memory.0]).unwrap();
+        let mut result = store.invoke(inst, "main", vec![]).unwrap();
+        let mut ops = Vec::new();
+        let results = loop {
+            let atomic = match result {
+                RunResult::Atomic(x) => x,
+                RunResult::Done(x) => break x,
+                _ => unreachable!(),
+            };
+            ops.push(atomic.op());
+            result = atomic.resume(ops.len() as u32 - 1).unwrap();
+        };
+        // The second wait doesn't call into the host because the value differs.
+        assert_eq!(
+            ops,
+            [
+                AtomicOp::Wait { mem: 0, addr: 8, timeout: None },
+                AtomicOp::Notify { mem: 0, addr: 8, count: 3 },
+            ]
+        );
+        assert_eq!(results, [Val::I32(0), Val::I32(1), Val::I32(1)]);
+    }
+
+    #[test]
+    fn paged_modules_execute_in_place() {
+        // Pages of the same size, except the last one which may be shorter.
+        struct Chunks<'a>(Vec<&'a [u8]>);
+        impl<'a> Pages for Chunks<'a> {
+            fn len(&self) -> usize {
+                self.0.iter().map(|x| x.len()).sum()
+            }
+            fn page(&self, offset: usize) -> &[u8] {
+                let size = self.0[0].len();
+                &self.0[offset / size][offset % size ..]
+            }
+        }
+        // Sums the bytes of the data segment.
+        let wasm = wat(r#"(module
+          (memory 1 1)
+          (data (i32.const 0) "0123456789abcdefghijklmnopqrstuvwxyz")
+          (func (export "f") (result i32) (local i32 i32)
+            (block
+              (loop
+                (br_if 1 (i32.eq (local.get 0) (i32.const 36)))
+                (local.set 1 (i32.add (local.get 1) (i32.load8_u (local.get 0))))
+                (local.set 0 (i32.add (local.get 0) (i32.const 1)))
+                (br 0)))
+            (local.get 1)))"#);
+        let sum = b"0123456789abcdefghijklmnopqrstuvwxyz".iter().map(|&x| x as u32).sum();
+        #[repr(align(16))]
+        struct Aligned([u8; 0x10000]);
+        let mut memory = Aligned([0; 0x10000]);
+        for size in 1 ..= wasm.len() {
+            let pages = Chunks(wasm.chunks(size).collect());
+            let module = Module::new_paged(&pages).unwrap();
+            let mut store = Store::default();
+            let inst = store.instantiate(module, vec![&mut memory.0]).unwrap();
+            match store.invoke(inst, "f", vec![]).unwrap() {
+                RunResult::Done(x) => assert_eq!(x, [Val::I32(sum)]),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn paged_modules_follow_layout() {
+        // Pages starting at the given offsets.
+        struct Layout<'a>(&'a [u8], Vec<usize>);
+        impl<'a> Pages for Layout<'a> {
+            fn len(&self) -> usize {
+                self.0.len()
+            }
+            fn page(&self, offset: usize) -> &[u8] {
+                let i = self.1.partition_point(|&x| x <= offset);
+                &self.0[offset .. self.1.get(i).copied().unwrap_or(self.0.len())]
+            }
+        }
+        let wasm = wat(r#"(module $paged_module_with_long_names
+          (import "environment" "increment_by_one" (func $increment (param i32) (result i32)))
+          (func $add_then_increment (export "add_then_increment")
+            (param $first i32) (param $second i32) (result i32)
+            (call $increment (i32.add (local.get $first) (local.get $second)))))"#);
+        let mut linker = Linker::default();
+        linker.link_func("environment", "increment_by_one", 1, 1).unwrap();
+        let longest = "paged_module_with_long_names".len();
+        for size in 1 .. longest {
+            assert_eq!(Module::page_layout(&wasm, size).err(), Some(Error::Unsupported));
+        }
+        for size in longest ..= wasm.len() {
+            let starts = Module::page_layout(&wasm, size).unwrap();
+            assert!(starts.windows(2).all(|x| x[0] < x[1] && x[1] - x[0] <= size));
+            let pages = Layout(&wasm, starts);
+            let module = Module::new_paged(&pages).unwrap();
+            assert_eq!(module.name(), Some("paged_module_with_long_names"));
+            assert_eq!(module.func_name(1), Some("add_then_increment"));
+            let mut store = Store::new(&linker);
+            let inst = store.instantiate(module, vec![]).unwrap();
+            let call =
+                match store.invoke(inst, "add_then_increment", vec![Val::I32(2), Val::I32(3)]) {
+                    Ok(RunResult::Host(x)) => x,
+                    _ => unreachable!(),
+                };
+            assert_eq!(call.args(), [Val::I32(5)]);
+            match call.resume(&[Val::I32(6)]).unwrap() {
+                RunResult::Done(x) => assert_eq!(x, [Val::I32(6)]),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn linker_is_shared() {
+        let wasm = wat(r#"(module
+          (import "env" "foo" (func (param i32) (result i32)))
+          (import "env" "bar" (func))
+          (func (export "main") (result i32) call 1 i32.const 13 call 0))"#);
+        let mut linker = Linker::default();
+        linker.link_func("env", "bar", 0, 0).unwrap();
+        assert_eq!(linker.link_func("env", "abc", 0, 0), Err(Error::Invalid));
+        linker.link_func("env", "foo", 1, 1).unwrap();
+        let mut stores = [Store::new(&linker), Store::new(&linker)];
+        for store in &mut stores {
+            let inst = store.instantiate(Module::new(&wasm).unwrap(), vec![]).unwrap();
+            let call = match store.invoke(inst, "main", vec![]).unwrap() {
+                RunResult::Host(x) => x,
+                _ => unreachable!(),
+            };
+            assert_eq!((call.index(), call.args()), (0, &[][..]));
+            let call = match call.resume(&[]).unwrap() {
+                RunResult::Host(x) => x,
+                _ => unreachable!(),
+            };
+            assert_eq!((call.index(), call.args()), (1, &[Val::I32(13)][..]));
+            match call.resume(&[Val::I32(42)]).unwrap() {
+                RunResult::Done(x) => assert_eq!(x, [Val::I32(42)]),
+                _ => unreachable!(),
+            }
+        }
+        let wasm = wat(r#"(module (import "env" "baz" (func)))"#);
+        let module = Module::new(&wasm).unwrap();
+        assert_eq!(stores[0].instantiate(module, vec![]).err(), Some(Error::NotFound));
+    }
+
+    #[test]
+    fn host_items_are_linked() {
+        #[repr(align(16))]
+        struct Aligned([u8; 32]);
+        let wasm = wat(r#"(module
+          (import "env" "version" (global i32))
+          (import "env" "counter" (global (mut i64)))
+          (import "env" "table" (table 2 funcref))
+          (import "env" "window" (memory 1 1))
+          (func (export "main") (result i32 i32)
+            global.get 1 i64.const 1 i64.add global.set 1
+            i32.const 4 i32.const 7 i32.store8
+            global.get 0 i32.const 1 i32.load8_u i32.add
+            table.size))"#);
+        let mut memory = Aligned([0; 32]);
+        memory.0[1] = 3;
+        let mut store = Store::default();
+        store.link_global("env", "version", Mut::Const, Val::I32(2)).unwrap();
+        store.link_global("env", "counter", Mut::Var, Val::I64(41)).unwrap();
+        let limits = Limits { min: 3, max: 5 };
+        store.link_table("env", "table", TableType { limits, item: RefType::FuncRef }).unwrap();
+        let limits = Limits { min: 1, max: 1 };
+        store.link_memory("env", "window", &mut memory.0, limits).unwrap();
+        let limits = Limits { min: 0, max: 0 };
+        let type_ = TableType { limits, item: RefType::FuncRef };
+        assert_eq!(store.link_table("env", "version", type_), Err(Error::Invalid));
+        let inst = store.instantiate(Module::new(&wasm).unwrap(), vec![]).unwrap();
+        match store.invoke(inst, "main", vec![]).unwrap() {
+            RunResult::Done(x) => assert_eq!(x, [Val::I32(5), Val::I32(3)]),
+            _ => unreachable!(),
+        }
+        assert_eq!(store.host.globals[1].2.value, Val::I64(42));
+        assert_eq!(store.host.mems[0].1.data[4], 7);
+        let wasm = wat(r#"(module (import "env" "version" (global (mut i32))))"#);
+        let module = Module::new(&wasm).unwrap();
+        assert_eq!(store.instantiate(module, vec![]).err(), Some(Error::NotFound));
+    }
+
+    #[test]
+    fn memories_use_their_buffer() {
+        #[repr(align(16))]
+        struct Aligned([u8; 16]);
+        let wasm = wat(r#"(module
+          (memory $public 1)
+          (memory $secret 1)
+          (data (memory $public) (i32.const 0) "\01\02\03\04")
+          (func (export "main")
+            i32.const 0 i32.const 0 i32.const 4 memory.copy $secret $public
+            i32.const 1 i32.const 7 i32.store8 $public
+            i32.const 2 i32.const 0 i32.const 1 memory.fill $secret))"#);
+        let mut public = Aligned([0; 16]);
+        let mut secret = Aligned([0; 16]);
+        let mut store = Store::default();
+        let memories = vec![&mut public.0[..], &mut secret.0[..]];
+        let inst = store.instantiate(Module::new(&wasm).unwrap(), memories).unwrap();
+        assert!(matches!(store.invoke(inst, "main", vec![]), Ok(RunResult::Done(_))));
+        let inst = &store.insts[inst.inst_id];
+        assert_eq!(inst.mems.int[0].data[.. 4], [1, 7, 3, 4]);
+        assert_eq!(inst.mems.int[1].data[.. 4], [1, 2, 0, 4]);
+        let wasm = wat(r#"(module (memory 0))"#);
+        let module = Module::new(&wasm).unwrap();
+        let memories = vec![&mut [][..], &mut [][..]];
+        assert_eq!(Store::default().instantiate(module, memories).err(), Some(Error::Invalid));
+    }
+
+    #[test]
+    fn tail_call_host() {
+        let wasm = wat(r#"(module
+          (import "env" "foo" (func $foo (param i32) (result i32)))
+          (func (export "main") (result i32) i32.const 13 return_call $foo))"#);
+        let mut linker = Linker::default();
+        linker.link_func("env", "foo", 1, 1).unwrap();
+        let mut store = Store::new(&linker);
+        let inst = store.instantiate(Module::new(&wasm).unwrap(), vec![]).unwrap();
+        let call = match store.invoke(inst, "main", vec![]).unwrap() {
+            RunResult::Host(x) => x,
+            _ => unreachable!(),
+        };
+        assert_eq!((call.index(), call.args()), (0, &[Val::I32(13)][..]));
+        match call.resume(&[Val::I32(42)]).unwrap() {
+            RunResult::Done(x) => assert_eq!(x, [Val::I32(42)]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn stack_limit_traps() {
+        let wasm = wat(r#"(module
+          (func $f (export "f") (param i32) (result i32)
+            local.get 0 i32.eqz
+            if (result i32) i32.const 0
+            else local.get 0 i32.const 1 i32.sub call $f end))"#);
+        let mut store = Store::default();
+        let inst = store.instantiate(Module::new(&wasm).unwrap(), vec![]).unwrap();
+        assert!(matches!(store.invoke(inst, "f", vec![Val::I32(100)]), Ok(RunResult::Done(_))));
+        store.set_stack_limit(0x1000);
+        let error = Some(Error::Trap(TrapReason::StackExhaustion));
+        assert_eq!(store.invoke(inst, "f", vec![Val::I32(100)]).err(), error);
+        assert!(store.take_core_dump().unwrap().frames.len() < 100);
+        assert!(matches!(store.invoke(inst, "f", vec![Val::I32(10)]), Ok(RunResult::Done(_))));
+    }
+
+    #[test]
+    fn stack_limit_counts_values() {
+        let values = "i32.const 0 ".repeat(1000) + &"drop ".repeat(1000);
+        let wasm = wat(&alloc::format!(r#"(module (func (export "f") {values}))"#));
+        let mut store = Store::default();
+        let inst = store.instantiate(Module::new(&wasm).unwrap(), vec![]).unwrap();
+        store.set_stack_limit(0x1000);
+        let error = Some(Error::Trap(TrapReason::StackExhaustion));
+        assert_eq!(store.invoke(inst, "f", vec![]).err(), error);
+        store.set_stack_limit(0x10000);
+        assert!(matches!(store.invoke(inst, "f", vec![]), Ok(RunResult::Done(_))));
+    }
+
+    #[test]
+    fn side_tables_match() {
+        // Counts the odd numbers below the argument in a convoluted way.
+        let wasm = wat(r#"(module
+          (func (export "f") (param i32) (result i32) (local i32)
+            (block $exit
+              (loop $loop
+                (br_if $exit (i32.eqz (local.get 0)))
+                (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                (block $odd (block $even
+                  (br_table $even $odd (i32.rem_u (local.get 0) (i32.const 2))))
+                  (br $loop))
+                (if (i32.const 1)
+                  (then (local.set 1 (i32.add (local.get 1) (i32.const 1))))
+                  (else unreachable))
+                (if (i32.const 0) (then unreachable))
+                (br $loop)))
+            (local.get 1)))"#);
+        let checked = Module::new(&wasm).unwrap();
+        let unchecked = unsafe { Module::new_unchecked(&wasm) };
+        assert_eq!(alloc::format!("{checked:?}"), alloc::format!("{unchecked:?}"));
+        let mut store = Store::default();
+        let inst = store.instantiate(unchecked, vec![]).unwrap();
+        match store.invoke(inst, "f", vec![Val::I32(10)]).unwrap() {
+            RunResult::Done(x) => assert_eq!(x, [Val::I32(5)]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn snapshot_restores_execution() {
+        #[repr(align(16))]
+        struct Aligned([u8; 16]);
+        let wasm = wat(r#"(module
+          (import "env" "tick" (func $tick (param i32) (result i32)))
+          (memory 1)
+          (global $g (mut i32) (i32.const 0))
+          (func (export "main") (param $n i32) (result i32)
+            (block $exit
+              (loop $loop
+                (br_if $exit (i32.eqz (local.get $n)))
+                (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+                (global.set $g (i32.add (global.get $g) (call $tick (local.get $n))))
+                (i32.store (i32.const 0) (global.get $g))
+                (br $loop)))
+            (i32.add (global.get $g) (i32.load (i32.const 0)))))"#);
+        let mut linker = Linker::default();
+        linker.link_func("env", "tick", 1, 1).unwrap();
+        fn resume(mut result: RunResult, steps: usize) -> Option<Vec<Val>> {
+            for _ in 0 .. steps {
+                let call = match result {
+                    RunResult::Host(x) => x,
+                    RunResult::Done(x) => return Some(x),
+                    _ => unreachable!(),
+                };
+                let x = call.args()[0].unwrap_i32();
+                result = call.resume(&[Val::I32(x + 1)]).unwrap();
+            }
+            None
+        }
+        let mut memory = Aligned([0; 16]);
+        let mut store = Store::new(&linker);
+        let inst = store.instantiate(Module::new(&wasm).unwrap(), vec![&mut memory.0]).unwrap();
+        let result = store.invoke(inst, "main", vec![Val::I32(5)]).unwrap();
+        assert_eq!(resume(result, 2), None);
+        let snapshot = store.snapshot();
+        let stack = store.threads[0].thread.stack();
+        let result = RunResult::Host(store.last_call().unwrap());
+        assert_eq!(resume(result, 10), Some(vec![Val::I32(30)]));
+
+        let mut memory = Aligned([0; 16]);
+        let mut store = Store::new(&linker);
+        store.instantiate(Module::new(&wasm).unwrap(), vec![&mut memory.0]).unwrap();
+        assert_eq!(store.restore(&snapshot[.. snapshot.len() - 1]), Err(Error::Invalid));
+        assert!(store.last_call().is_none());
+        store.restore(&snapshot).unwrap();
+        assert_eq!(store.threads[0].thread.stack(), stack);
+        let result = RunResult::Host(store.last_call().unwrap());
+        assert_eq!(resume(result, 10), Some(vec![Val::I32(30)]));
+        assert_eq!(memory.0[.. 4], [15, 0, 0, 0]);
+
+        let other = wat(r#"(module
+          (import "env" "tick" (func $tick (param i32) (result i32)))
+          (memory 1)
+          (global $g (mut i32) (i32.const 1)))"#);
+        let mut memory = Aligned([0; 16]);
+        let mut store = Store::new(&linker);
+        store.instantiate(Module::new(&other).unwrap(), vec![&mut memory.0]).unwrap();
+        assert_eq!(store.restore(&snapshot), Err(Error::Invalid));
+    }
+
+    #[test]
+    fn breakpoints_pause_execution() {
+        let wasm = wat(r#"(module
+          (global $g (mut i32) (i32.const 7))
+          (func (export "main") (param i32) (result i32) (local i64)
+            local.get 0 i32.const 42 i32.add global.set $g global.get $g))"#);
+        let offset = wasm.windows(2).position(|x| x == [0x41, 42]).unwrap(); // i32.const 42
+        let mut store = Store::default();
+        let inst = store.instantiate(Module::new(&wasm).unwrap(), vec![]).unwrap();
+        assert_eq!(store.set_breakpoint(inst, 0, offset + 1), Err(Error::NotFound));
+        assert_eq!(store.set_breakpoint(inst, 1, offset), Err(Error::NotFound));
+        store.set_breakpoint(inst, 0, offset).unwrap();
+        let mut result = store.invoke(inst, "main", vec![Val::I32(1)]).unwrap();
+        let mut offsets = Vec::new();
+        let mut stacks: Vec<Vec<Val>> = Vec::new();
+        while let RunResult::Breakpoint(breakpoint) = result {
+            assert_eq!(breakpoint.func(), 0);
+            assert_eq!(breakpoint.locals(), [Val::I32(1), Val::I64(0)]);
+            offsets.push(breakpoint.offset() - offset);
+            #[cfg(not(feature = "untagged"))]
+            stacks.push(breakpoint.stack());
+            #[cfg(feature = "untagged")]
+            stacks.push(Vec::new());
+            if offsets.len() == 4 {
+                assert_eq!(breakpoint.globals(), [Val::I32(43)]);
+                result = breakpoint.resume().unwrap();
+            } else {
+                assert_eq!(breakpoint.globals(), [Val::I32(7)]);
+                result = breakpoint.step().unwrap();
+            }
+        }
+        assert!(matches!(result, RunResult::Done(x) if x == [Val::I32(43)]));
+        assert_eq!(offsets, [0, 2, 3, 5]);
+        #[cfg(not(feature = "untagged"))]
+        assert_eq!(
+            stacks,
+            [vec![Val::I32(1)], vec![Val::I32(1), Val::I32(42)], vec![Val::I32(43)], vec![]]
+        );
+        store.clear_breakpoint(inst, 0, offset).unwrap();
+        assert_eq!(store.clear_breakpoint(inst, 0, offset), Err(Error::NotFound));
+        let result = store.invoke(inst, "main", vec![Val::I32(2)]).unwrap();
+        assert!(matches!(result, RunResult::Done(x) if x == [Val::I32(44)]));
+    }
+
+    #[test]
+    fn instance_limits_bound_growth() {
+        let wasm = wat(r#"(module
+          (memory 1 3)
+          (table 2 funcref)
+          (global i32 (i32.const 0))
+          (func (export "grow") (param i32) (result i32) (memory.grow (local.get 0)))
+          (func (export "table") (result i32) (table.grow (ref.null func) (i32.const 1)))
+          (func (export "load") (param i32) (result i32) (i32.load (local.get 0))))"#);
+        #[repr(align(16))]
+        struct Aligned([u8; 0x10000]);
+        let mut memory = Aligned([0; 0x10000]);
+        let mut store = Store::default();
+        let limits = InstanceLimits { max_pages: 2, max_table_elements: 1, max_globals: 1 };
+        store.set_instance_limits(limits);
+        let module = || Module::new(&wasm).unwrap();
+        assert_eq!(store.instantiate(module(), vec![]), Err(Error::Unsupported));
+        store.set_instance_limits(InstanceLimits { max_table_elements: 2, ..limits });
+        let inst = store.instantiate(module(), vec![&mut memory.0]).unwrap();
+        let mut invoke = |name, args| match store.invoke(inst, name, args) {
+            Ok(RunResult::Done(x)) => Ok(x),
+            Ok(_) => unreachable!(),
+            Err(x) => Err(x),
+        };
+        assert_eq!(invoke("table", vec![]), Ok(vec![Val::I32(u32::MAX)]));
+        assert_eq!(invoke("grow", vec![Val::I32(2)]), Ok(vec![Val::I32(u32::MAX)]));
+        assert_eq!(invoke("grow", vec![Val::I32(1)]), Ok(vec![Val::I32(1)]));
+        let exhausted = Err(Error::Trap(TrapReason::MemoryExhausted));
+        assert_eq!(invoke("load", vec![Val::I32(0x10000)]), exhausted);
+        let out_of_bounds = Err(Error::Trap(TrapReason::MemoryOutOfBounds));
+        assert_eq!(invoke("load", vec![Val::I32(0x20000)]), out_of_bounds);
+    }
 }
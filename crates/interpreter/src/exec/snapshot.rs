@@ -0,0 +1,555 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Serialization of the execution state of a store.
+//!
+//! Positions in function bodies are serialized as their remaining length in the body. They are
+//! checked to be instruction boundaries when restored, and the side table cursor of each frame is
+//! recomputed from its position.
+
+use super::*;
+use crate::parser::Binary;
+
+const MAGIC: &[u8; 4] = b"snap";
+
+/// Features that change the serialization format.
+const FLAGS: u8 = cfg!(feature = "untagged") as u8 | (cfg!(feature = "vector-types") as u8) << 1;
+
+impl<'m> Store<'m> {
+    /// Serializes the execution state of this store.
+    ///
+    /// The snapshot contains the state that execution may modify: the tables, memories, and
+    /// globals (of the host and of instances), the dropped segments, and the suspended threads
+    /// (calling into the host, out of fuel, or paused for debugging). It does not contain the
+    /// fuel, stack limit, breakpoints, or core dump. See [`Self::restore()`] to restore a
+    /// snapshot.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut writer = Writer(MAGIC.to_vec());
+        writer.u8(FLAGS);
+        let host = &self.host;
+        let tables = host.tables.iter().map(|x| &x.2);
+        let mems = host.mems.iter().map(|x| &x.1);
+        let globals = host.globals.iter().map(|x| &x.2);
+        writer.state(tables, mems, globals, &[], &[]);
+        writer.len(self.insts.len());
+        for inst in &self.insts {
+            writer.u64(checksum(inst.module.binary()));
+            let tables = inst.tables.int.iter();
+            let mems = inst.mems.int.iter();
+            let globals = inst.globals.int.iter();
+            writer.state(tables, mems, globals, &inst.elems, &inst.datas);
+        }
+        writer.len(self.threads.len());
+        for Continuation { thread, pause } in &self.threads {
+            writer.thread(thread);
+            match pause {
+                Pause::Yield => writer.u8(0),
+                Pause::Host(HostCall { index, args, arity }) => {
+                    writer.u8(1);
+                    writer.len(*index);
+                    writer.len(*arity);
+                    writer.len(args.len());
+                    args.iter().for_each(|&x| writer.val(x));
+                }
+                Pause::Breakpoint => writer.u8(2),
+                // A timeout is at most `i64::MAX` so the maximum encodes its absence.
+                &Pause::Atomic(AtomicOp::Wait { mem, addr, timeout }) => {
+                    writer.u8(3);
+                    writer.u32(mem);
+                    writer.u32(addr);
+                    writer.u64(timeout.unwrap_or(u64::MAX));
+                }
+                &Pause::Atomic(AtomicOp::Notify { mem, addr, count }) => {
+                    writer.u8(4);
+                    writer.u32(mem);
+                    writer.u32(addr);
+                    writer.u32(count);
+                }
+            }
+        }
+        writer.0
+    }
+
+    /// Restores the execution state of this store from a snapshot.
+    ///
+    /// The store must be set up like the store the snapshot was taken from: same host functions,
+    /// same host tables, memories, and globals, and same modules (with the same bytes) instantiated
+    /// in the same order with memory buffers of the same length. Suspended threads may then be
+    /// resumed with [`Self::last_call()`], [`Self::last_yield()`], [`Self::last_breakpoint()`], or
+    /// [`Self::last_atomic()`].
+    ///
+    /// The store is not modified if the snapshot is invalid. Validity is only checked against the
+    /// store: the types of operand values and the arity of blocks inside suspended functions are
+    /// not checked, so a snapshot must be trusted (e.g. produced by [`Self::snapshot()`] and stored
+    /// with integrity protection) or execution may panic when resumed.
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), Error> {
+        let mut reader = Reader { data: snapshot, store: self };
+        check(reader.bytes(4)? == MAGIC && reader.u8()? == FLAGS)?;
+        let host = &self.host;
+        let tables = host.tables.iter().map(|x| &x.2).collect();
+        let mems = host.mems.iter().map(|x| &x.1).collect();
+        let globals = host.globals.iter().map(|x| &x.2).collect();
+        let host = reader.state(tables, mems, globals, 0, 0)?;
+        check(reader.len()? == self.insts.len())?;
+        let mut insts = Vec::new();
+        for inst in &self.insts {
+            check(reader.u64()? == checksum(inst.module.binary()))?;
+            let tables = inst.tables.int.iter().collect();
+            let mems = inst.mems.int.iter().collect();
+            let globals = inst.globals.int.iter().collect();
+            let (elems, datas) = (inst.elems.len(), inst.datas.len());
+            insts.push(reader.state(tables, mems, globals, elems, datas)?);
+        }
+        let mut threads: Vec<Continuation> = Vec::new();
+        for _ in 0 .. reader.len()? {
+            // Suspended threads are still using the stack.
+            let stack = threads.iter().map(|x| x.thread.stack()).sum();
+            let thread = reader.thread(stack)?;
+            let pause = match reader.u8()? {
+                0 => Pause::Yield,
+                1 => {
+                    let index = reader.len()?;
+                    check(index < self.linker.funcs.len())?;
+                    let arity = reader.len()?;
+                    let args = reader.vals()?;
+                    Pause::Host(HostCall { index, args, arity })
+                }
+                2 => Pause::Breakpoint,
+                3 => {
+                    let (mem, addr) = (reader.u32()?, reader.u32()?);
+                    let timeout = Some(reader.u64()?).filter(|&x| x != u64::MAX);
+                    Pause::Atomic(AtomicOp::Wait { mem, addr, timeout })
+                }
+                4 => {
+                    let (mem, addr, count) = (reader.u32()?, reader.u32()?, reader.u32()?);
+                    Pause::Atomic(AtomicOp::Notify { mem, addr, count })
+                }
+                _ => return Err(invalid()),
+            };
+            threads.push(Continuation { thread, pause });
+        }
+        check(reader.data.is_empty())?;
+        let host_tables = self.host.tables.iter_mut().map(|x| &mut x.2);
+        let host_mems = self.host.mems.iter_mut().map(|x| &mut x.1);
+        let host_globals = self.host.globals.iter_mut().map(|x| &mut x.2);
+        host.apply(host_tables, host_mems, host_globals, &mut [], &mut []);
+        for (state, inst) in insts.into_iter().zip(self.insts.iter_mut()) {
+            let tables = inst.tables.int.iter_mut();
+            let mems = inst.mems.int.iter_mut();
+            let globals = inst.globals.int.iter_mut();
+            state.apply(tables, mems, globals, &mut inst.elems, &mut inst.datas);
+        }
+        self.threads = threads;
+        Ok(())
+    }
+}
+
+/// Mutable state of an instance (or of the host).
+struct State<'s> {
+    tables: Vec<Vec<Val>>,
+    /// The size and content of each memory.
+    mems: Vec<(u32, &'s [u8])>,
+    globals: Vec<Val>,
+    elems: Vec<bool>,
+    datas: Vec<bool>,
+}
+
+impl<'s> State<'s> {
+    fn apply<'a, 'm: 'a>(
+        self, tables: impl Iterator<Item = &'a mut Table>,
+        mems: impl Iterator<Item = &'a mut Memory<'m>>,
+        globals: impl Iterator<Item = &'a mut Global>, elems: &mut [bool], datas: &mut [bool],
+    ) {
+        for (elems, table) in self.tables.into_iter().zip(tables) {
+            table.elems = elems;
+        }
+        for ((size, data), mem) in self.mems.into_iter().zip(mems) {
+            mem.size = size;
+            mem.data.copy_from_slice(data);
+        }
+        for (value, global) in self.globals.into_iter().zip(globals) {
+            global.value = value;
+        }
+        elems.copy_from_slice(&self.elems);
+        datas.copy_from_slice(&self.datas);
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, x: u8) {
+        self.0.push(x);
+    }
+
+    fn u32(&mut self, x: u32) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    fn u64(&mut self, x: u64) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn u128(&mut self, x: u128) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    fn len(&mut self, x: usize) {
+        self.u32(x as u32);
+    }
+
+    fn val(&mut self, x: Val) {
+        match x {
+            Val::I32(x) => {
+                self.u8(0);
+                self.u32(x);
+            }
+            Val::I64(x) => {
+                self.u8(1);
+                self.u64(x);
+            }
+            #[cfg(feature = "float-types")]
+            Val::F32(x) => {
+                self.u8(2);
+                self.u32(x);
+            }
+            #[cfg(feature = "float-types")]
+            Val::F64(x) => {
+                self.u8(3);
+                self.u64(x);
+            }
+            #[cfg(feature = "vector-types")]
+            Val::V128(x) => {
+                self.u8(4);
+                self.u128(x);
+            }
+            Val::Null(RefType::FuncRef) => self.u8(5),
+            Val::Null(RefType::ExternRef) => self.u8(6),
+            Val::Ref(x) => {
+                self.u8(7);
+                self.u32(x.0);
+            }
+            Val::RefExtern(x) => {
+                self.u8(8);
+                self.u64(x as u64);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "untagged"))]
+    fn slot(&mut self, x: Slot) {
+        self.val(x);
+    }
+
+    #[cfg(all(feature = "untagged", not(feature = "vector-types")))]
+    fn slot(&mut self, x: Slot) {
+        self.u64(x.0);
+    }
+
+    #[cfg(all(feature = "untagged", feature = "vector-types"))]
+    fn slot(&mut self, x: Slot) {
+        self.u128(x.0);
+    }
+
+    fn slots(&mut self, xs: &[Slot]) {
+        self.len(xs.len());
+        xs.iter().for_each(|&x| self.slot(x));
+    }
+
+    fn state<'a, 'm: 'a>(
+        &mut self, tables: impl ExactSizeIterator<Item = &'a Table>,
+        mems: impl ExactSizeIterator<Item = &'a Memory<'m>>,
+        globals: impl ExactSizeIterator<Item = &'a Global>, elems: &[bool], datas: &[bool],
+    ) {
+        self.len(tables.len());
+        for table in tables {
+            self.len(table.elems.len());
+            table.elems.iter().for_each(|&x| self.val(x));
+        }
+        self.len(mems.len());
+        for mem in mems {
+            self.u32(mem.size);
+            self.len(mem.data.len());
+            self.0.extend_from_slice(mem.data);
+        }
+        self.len(globals.len());
+        globals.for_each(|x| self.val(x.value));
+        self.len(elems.len());
+        elems.iter().for_each(|&x| self.u8(x as u8));
+        self.len(datas.len());
+        datas.iter().for_each(|&x| self.u8(x as u8));
+    }
+
+    fn thread(&mut self, thread: &Thread) {
+        self.len(thread.frames.len());
+        for (i, frame) in thread.frames.iter().enumerate() {
+            self.len(frame.inst_id);
+            self.u32(frame.func);
+            self.len(frame.arity);
+            if i > 0 {
+                self.len(frame.ret.len());
+            }
+            self.slots(&frame.locals);
+            self.len(frame.labels.len());
+            for (j, label) in frame.labels.iter().enumerate() {
+                self.len(label.arity);
+                let (kind, target) = match label.kind {
+                    LabelKind::Block(x) => (0, x),
+                    LabelKind::Loop(x) => (1, x),
+                };
+                self.u8(kind);
+                // The target of the function label is not used.
+                if j > 0 {
+                    self.len(target.len);
+                }
+                self.slots(&label.values);
+            }
+        }
+        self.len(thread.parser.len());
+    }
+}
+
+struct Reader<'a, 's, 'm> {
+    data: &'s [u8],
+    store: &'a Store<'m>,
+}
+
+impl<'a, 's, 'm> Reader<'a, 's, 'm> {
+    fn bytes(&mut self, len: usize) -> Result<&'s [u8], Error> {
+        check(len <= self.data.len())?;
+        let (result, data) = self.data.split_at(len);
+        self.data = data;
+        Ok(result)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn u128(&mut self) -> Result<u128, Error> {
+        Ok(u128::from_le_bytes(self.bytes(16)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, Error> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn usize(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.u64()?).map_err(|_| invalid())
+    }
+
+    fn val(&mut self) -> Result<Val, Error> {
+        let val = match self.u8()? {
+            0 => Val::I32(self.u32()?),
+            1 => Val::I64(self.u64()?),
+            #[cfg(feature = "float-types")]
+            2 => Val::F32(self.u32()?),
+            #[cfg(feature = "float-types")]
+            3 => Val::F64(self.u64()?),
+            #[cfg(feature = "vector-types")]
+            4 => Val::V128(self.u128()?),
+            5 => Val::Null(RefType::FuncRef),
+            6 => Val::Null(RefType::ExternRef),
+            7 => {
+                let ptr = Ptr(self.u32()?);
+                let count = match ptr.instance() {
+                    Side::Host => self.store.linker.funcs.len(),
+                    Side::Wasm(x) => {
+                        check(x < self.store.insts.len())?;
+                        self.store.insts[x].module.func_count()
+                    }
+                };
+                check((ptr.index() as usize) < count)?;
+                Val::Ref(ptr)
+            }
+            8 => Val::RefExtern(self.usize()?),
+            _ => return Err(invalid()),
+        };
+        Ok(val)
+    }
+
+    fn vals(&mut self) -> Result<Vec<Val>, Error> {
+        (0 .. self.len()?).map(|_| self.val()).collect()
+    }
+
+    #[cfg(not(feature = "untagged"))]
+    fn slot(&mut self, type_: Option<ValType>) -> Result<Slot, Error> {
+        let val = self.val()?;
+        check(type_.map_or(true, |t| val.type_() == t))?;
+        Ok(val)
+    }
+
+    #[cfg(all(feature = "untagged", not(feature = "vector-types")))]
+    fn slot(&mut self, _: Option<ValType>) -> Result<Slot, Error> {
+        Ok(Slot(self.u64()?))
+    }
+
+    #[cfg(all(feature = "untagged", feature = "vector-types"))]
+    fn slot(&mut self, _: Option<ValType>) -> Result<Slot, Error> {
+        Ok(Slot(self.u128()?))
+    }
+
+    /// Reads slots, given their types if known.
+    fn slots(&mut self, types: Option<&[ValType]>) -> Result<Vec<Slot>, Error> {
+        let len = self.len()?;
+        if let Some(types) = types {
+            check(len == types.len())?;
+        }
+        (0 .. len).map(|i| self.slot(types.map(|x| x[i]))).collect()
+    }
+
+    /// Reads a position in a function body and returns the side table cursor at that position.
+    fn pos(&mut self, inst_id: usize, func: FuncIdx) -> Result<(Parser<'m>, usize), Error> {
+        let len = self.len()?;
+        self.store.insts[inst_id].module.func_locate(func, len).ok_or_else(invalid)
+    }
+
+    fn state(
+        &mut self, tables: Vec<&Table>, mems: Vec<&Memory>, globals: Vec<&Global>, elems: usize,
+        datas: usize,
+    ) -> Result<State<'s>, Error> {
+        check(self.len()? == tables.len())?;
+        let tables = tables.into_iter().map(|table| {
+            let elems = self.vals()?;
+            check(elems.len() <= table.max as usize)?;
+            let item = ValType::from(table.item);
+            check(elems.iter().all(|x| x.type_() == item))?;
+            Ok(elems)
+        });
+        let tables = tables.collect::<Result<_, Error>>()?;
+        check(self.len()? == mems.len())?;
+        let mems = mems.into_iter().map(|mem| {
+            let size = self.u32()?;
+            check(size <= mem.max)?;
+            let len = self.len()?;
+            check(len == mem.data.len())?;
+            Ok((size, self.bytes(len)?))
+        });
+        let mems = mems.collect::<Result<_, Error>>()?;
+        check(self.len()? == globals.len())?;
+        let globals = globals.into_iter().map(|global| {
+            let value = self.val()?;
+            check(value.type_() == global.value.type_())?;
+            Ok(value)
+        });
+        let globals = globals.collect::<Result<_, Error>>()?;
+        let mut flags = |len| {
+            check(self.len()? == len)?;
+            (0 .. len)
+                .map(|_| match self.u8()? {
+                    0 => Ok(false),
+                    1 => Ok(true),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        };
+        let elems = flags(elems)?;
+        let datas = flags(datas)?;
+        Ok(State { tables, mems, globals, elems, datas })
+    }
+
+    /// Reads a thread given the stack usage before it.
+    ///
+    /// The stack usage of labels is recomputed like during execution.
+    fn thread(&mut self, stack: usize) -> Result<Thread<'m>, Error> {
+        let mut frames: Vec<Frame<'m>> = Vec::new();
+        for _ in 0 .. self.len()? {
+            let inst_id = self.len()?;
+            check(inst_id < self.store.insts.len())?;
+            let func = self.u32()?;
+            let module = &self.store.insts[inst_id].module;
+            check((func as usize) < module.func_count())?;
+            let arity = self.len()?;
+            check(arity == module.func_type(func).results.len())?;
+            let ret = match frames.last_mut() {
+                None => Parser::default(),
+                Some(caller) => {
+                    let (ret, cursor) = self.pos(caller.inst_id, caller.func)?;
+                    caller.cursor = cursor;
+                    ret
+                }
+            };
+            let stack = match frames.last() {
+                None => stack,
+                Some(caller) => {
+                    let label = caller.labels.last().unwrap();
+                    label.stack + label.values.len() * size_of::<Slot>()
+                }
+            };
+            let types = self.store.insts[inst_id].module.func_locals(func);
+            let locals = self.slots(Some(&types))?;
+            let mut frame = Frame::new(inst_id, func, arity, ret, locals, stack);
+            let mut labels: Vec<Label> = Vec::new();
+            for j in 0 .. self.len()? {
+                let arity = self.len()?;
+                let kind = self.u8()?;
+                let target = match j {
+                    0 => Target { len: 0, cursor: 0 },
+                    _ => {
+                        let (pos, cursor) = self.pos(inst_id, func)?;
+                        Target { len: pos.len(), cursor }
+                    }
+                };
+                let kind = match kind {
+                    0 => LabelKind::Block(target),
+                    1 => LabelKind::Loop(target),
+                    _ => return Err(invalid()),
+                };
+                let values = self.slots(None)?;
+                let stack = match labels.last() {
+                    None => {
+                        check(arity == frame.arity)?;
+                        frame.labels[0].stack
+                    }
+                    Some(outer) => {
+                        outer.stack + outer.values.len() * size_of::<Slot>() + size_of::<Label>()
+                    }
+                };
+                labels.push(Label { arity, kind, values, stack });
+            }
+            check(!labels.is_empty())?;
+            frame.labels = labels;
+            frames.push(frame);
+        }
+        let last = frames.last_mut().ok_or_else(invalid)?;
+        // The position is an instruction boundary of the function.
+        let (parser, cursor) = self.pos(last.inst_id, last.func)?;
+        last.cursor = cursor;
+        let first = &frames[0];
+        let results = self.store.insts[first.inst_id].module.func_type(first.func).results;
+        Ok(Thread::new(parser, frames, results))
+    }
+}
+
+/// Returns the FNV-1a hash of a module binary.
+fn checksum(binary: Binary) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    binary.for_each_page(|page| {
+        for &x in page {
+            hash ^= x as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    });
+    hash
+}
@@ -16,39 +16,49 @@
 //!
 //! The main concepts of this crate are:
 //!
+//! - A [`Linker`] contains host functions that modules may import. It may be shared between
+//! stores.
+//!
 //! - A [`Store`] contains instantiated modules and permits execution. Note that execution within
 //! the same store must follow a stack behavior. A function "bar" may be called while a function
 //! "foo" is running: "bar" will temporarily interrupt "foo" until "bar" returns at which point
-//! "foo" would resume. This is to avoid corrupting the stack within the same instance. Once the
-//! WebAssembly threads proposal lands, this restriction may be removed.
+//! "foo" would resume. This is to avoid corrupting the stack within the same instance. The
+//! WebAssembly threads proposal is supported with the `threads` feature, but this restriction still
+//! applies: shared memories and atomic instructions behave as if threads were interleaved, and
+//! waiting and notifying is delegated to the host with [`RunResult::Atomic`].
 //!
 //! - A [`Module`] represents a valid module. Only valid modules may be instantiated. A module is
-//! just a byte slice holding a WebAssembly module in binary format.
+//! just a byte slice holding a WebAssembly module in binary format. The binary may also be stored
+//! in non-contiguous pages (see [`Pages`]), in which case it is read in place.
 //!
 //! - A [`RunResult`] represents the result of a (possibly partial) execution. When invoking an
 //! exported function from an instance in a store, or when resuming the execution after a call to
-//! the host, a `RunResult` is returned providing either the next call to the host or whether
-//! execution of the most recently invoked function terminated along with its results.
+//! the host, a `RunResult` is returned providing either the next call to the host, whether
+//! execution ran out of fuel, or whether execution of the most recently invoked function terminated
+//! along with its results.
 //!
 //! # Examples
 //!
 //! For a concrete "hello" example, see `examples/hello.rs` which walks through most important steps
 //! in using this crate. Otherwise, here are some short excerpts:
 //!
-//! Creating a store can simply use the `Default` trait:
+//! Linking a host function is done with [`Linker::link_func()`]:
 //!
 //! ```
 //! # use wasefire_interpreter::*;
-//! let mut store = Store::default();
+//! # fn doc(linker: &mut Linker) -> Result<(), Error> {
+//! linker.link_func("env", "add", 2, 1)?;
+//! # Ok(())
+//! # }
 //! ```
 //!
-//! Linking a host function in a store is done with [`Store::link_func()`]:
+//! Creating a store is done with [`Store::new()`], or simply the `Default` trait when not linking
+//! any host function:
 //!
 //! ```
 //! # use wasefire_interpreter::*;
-//! # fn doc(store: &mut Store) -> Result<(), Error> {
-//! store.link_func("env", "add", 2, 1)?;
-//! # Ok(())
+//! # fn doc(linker: &Linker) {
+//! let mut store = Store::new(linker);
 //! # }
 //! ```
 //!
@@ -58,7 +68,7 @@
 //! # use wasefire_interpreter::*;
 //! # fn doc<'a>(store: &mut Store<'a>, module: Module<'a>, memory: &'a mut [u8])
 //! # -> Result<(), Error> {
-//! let inst = store.instantiate(module, memory)?;
+//! let inst = store.instantiate(module, vec![memory])?;
 //! # Ok(())
 //! # }
 //! ```
@@ -83,6 +93,23 @@
 //!     let mut call = match result {
 //!         RunResult::Done(results) => return Ok(results),
 //!         RunResult::Host(call) => call,
+//!         RunResult::Yield(x) => {
+//!             result = x.resume()?;
+//!             continue;
+//!         }
+//!         RunResult::Breakpoint(x) => {
+//!             result = x.resume()?;
+//!             continue;
+//!         }
+//!         RunResult::Atomic(x) => {
+//!             // Without other threads, waiting times out and notifying wakes no thread.
+//!             let value = match x.op() {
+//!                 AtomicOp::Wait { .. } => 2,
+//!                 AtomicOp::Notify { .. } => 0,
+//!             };
+//!             result = x.resume(value)?;
+//!             continue;
+//!         }
 //!     };
 //!     let results = process(&mut call)?;
 //!     result = call.resume(&results)?;
@@ -90,6 +117,38 @@
 //! # }
 //! ```
 //!
+//! Limiting the number of executed instructions is done with [`Store::set_fuel()`]. When there is
+//! no fuel left, execution pauses with [`RunResult::Yield`] and can be resumed later:
+//!
+//! ```
+//! # use wasefire_interpreter::*;
+//! # fn doc<'a>(store: &mut Store<'a>, inst: InstId) -> Result<(), Error> {
+//! store.set_fuel(Some(1000));
+//! let mut result = store.invoke(inst, "main", vec![])?;
+//! while let RunResult::Yield(_) = result {
+//!     store.set_fuel(Some(1000));
+//!     result = store.last_yield().unwrap().resume()?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Debugging is done with [`Store::set_breakpoint()`]. Execution pauses with
+//! [`RunResult::Breakpoint`] before the instruction, which can then be inspected and stepped:
+//!
+//! ```
+//! # use wasefire_interpreter::*;
+//! # fn doc<'a>(store: &mut Store<'a>, inst: InstId, offset: usize) -> Result<(), Error> {
+//! store.set_breakpoint(inst, 0, offset)?;
+//! let mut result = store.invoke(inst, "main", vec![])?;
+//! while let RunResult::Breakpoint(x) = result {
+//!     println!("{:#x}: {:?} {:?}", x.offset(), x.locals(), x.globals());
+//!     result = x.step()?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! # Atomic support
 //!
 //! This crate uses atomic operations and relies on the `portable-atomic` crate to support
@@ -130,18 +189,26 @@ macro_rules! support_if {
     }};
 }
 
-mod cache;
 mod error;
 mod exec;
 mod id;
 mod module;
 mod parser;
+mod side_table;
+#[cfg(feature = "vector-types")]
+mod simd;
 mod syntax;
 mod toctou;
 mod valid;
 
-pub use error::Error;
-pub use exec::{Call, InstId, RunAnswer, RunResult, Store, StoreId, Val, MEMORY_ALIGN};
+pub use error::{Error, TrapReason};
+#[cfg(feature = "profile")]
+pub use exec::FuncProfile;
+pub use exec::{
+    Atomic, AtomicOp, Breakpoint, Call, CoreDump, CoreFrame, InstId, InstanceLimits, Linker,
+    RunAnswer, RunResult, Store, StoreId, Val, Yield, DEFAULT_STACK_LIMIT, MEMORY_ALIGN,
+};
 pub use module::Module;
-pub use syntax::{GlobalType, ImportDesc, Limits, Mut, RefType, TableType, ValType};
+pub use parser::Pages;
+pub use syntax::{GlobalType, ImportDesc, Limits, MemType, Mut, RefType, TableType, ValType};
 pub use valid::validate;
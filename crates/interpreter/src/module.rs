@@ -12,37 +12,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use alloc::vec;
 use alloc::vec::Vec;
 use core::cmp::Ordering;
+use core::ops::Range;
 
-use crate::cache::Cache;
-use crate::parser::{SkipData, SkipElem};
+use crate::error::*;
+use crate::parser::{Binary, Pages, SkipData, SkipElem};
+use crate::side_table::*;
 use crate::syntax::*;
 use crate::toctou::*;
-use crate::valid::validate;
+use crate::valid::side_tables;
 use crate::*;
 
 /// Valid module.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Module<'m> {
-    binary: &'m [u8],
+    binary: Binary<'m>,
     types: Vec<FuncType<'m>>,
-    cache: Cache<CacheKey, CacheValue>,
-}
-
-impl<'m> Default for Module<'m> {
-    fn default() -> Self {
-        Self { binary: &[], types: Vec::new(), cache: Cache::unbounded() }
-    }
-}
-
-#[derive(Debug, PartialEq, Eq, Hash)]
-enum CacheKey {
-    Skip { ptr: *const u8, depth: LabelIdx },
-}
-
-union CacheValue {
-    skip: usize, // delta
+    /// Side table of each function (excluding imported ones).
+    side_tables: Vec<SideTable>,
 }
 
 impl<'m> Import<'m> {
@@ -65,8 +54,49 @@ impl ImportDesc {
 impl<'m> Module<'m> {
     /// Validates a WASM module in binary format.
     pub fn new(binary: &'m [u8]) -> Result<Self, Error> {
-        validate(binary)?;
-        Ok(unsafe { Self::new_unchecked(binary) })
+        Self::new_binary(binary.into())
+    }
+
+    /// Validates a WASM module in binary format stored in pages.
+    ///
+    /// The module is validated and executed in place, without copying the pages. Names, function
+    /// types, and custom sections must not cross page boundaries (the module is unsupported
+    /// otherwise). Installers should use [`Self::page_layout()`] to store the module.
+    pub fn new_paged(pages: &'m dyn Pages) -> Result<Self, Error> {
+        Self::new_binary(Binary::Pages(pages))
+    }
+
+    /// Returns the offsets at which pages should start to store a module for [`Self::new_paged()`].
+    ///
+    /// The offsets are increasing and start with zero. Pages are at most `page_size` bytes and
+    /// don't split names, function types, or custom sections (when larger than a page, only their
+    /// names are kept together). Installers thus leave the end of a page unused when the next
+    /// offset is before the end of the page. Returns an unsupported error if a name or function
+    /// type is larger than a page.
+    pub fn page_layout(binary: &'m [u8], page_size: usize) -> Result<Vec<usize>, Error> {
+        assert!(page_size > 0);
+        let module = Self::new(binary)?;
+        let mut starts = vec![0];
+        let mut start = 0;
+        for range in module.contiguous_ranges(page_size) {
+            if range.len() > page_size {
+                return Err(unsupported());
+            }
+            while start + page_size < range.end {
+                start = core::cmp::min(start + page_size, range.start);
+                starts.push(start);
+            }
+        }
+        while start + page_size < binary.len() {
+            start += page_size;
+            starts.push(start);
+        }
+        Ok(starts)
+    }
+
+    fn new_binary(binary: Binary<'m>) -> Result<Self, Error> {
+        let side_tables = side_tables(binary)?;
+        Ok(unsafe { Self::new_with(binary, side_tables) })
     }
 
     /// Creates a valid module from binary format.
@@ -75,8 +105,23 @@ impl<'m> Module<'m> {
     ///
     /// The module must be valid.
     pub unsafe fn new_unchecked(binary: &'m [u8]) -> Self {
-        // Only keep the sections (i.e. skip the header).
-        let mut module = Module { binary: &binary[8 ..], ..Self::default() };
+        let mut side_tables = Vec::new();
+        let mut module = unsafe { Self::new_with(binary.into(), Vec::new()) };
+        if let Some(mut parser) = module.section(SectionId::Code) {
+            for _ in 0 .. parser.parse_vec().into_ok() {
+                let size = parser.parse_u32().into_ok() as usize;
+                let mut parser = parser.split_at(size).into_ok();
+                parser.parse_locals(&mut Vec::new()).into_ok();
+                side_tables.push(side_table(&mut parser).into_ok());
+            }
+        }
+        module.side_tables = side_tables;
+        module
+    }
+
+    /// Creates a valid module from binary format given its side tables.
+    unsafe fn new_with(binary: Binary<'m>, side_tables: Vec<SideTable>) -> Self {
+        let mut module = Module { binary, types: Vec::new(), side_tables };
         if let Some(mut parser) = module.section(SectionId::Type) {
             for _ in 0 .. parser.parse_vec().into_ok() {
                 module.types.push(parser.parse_functype().into_ok());
@@ -85,6 +130,52 @@ impl<'m> Module<'m> {
         module
     }
 
+    /// Returns the name of the module according to the name section, if any.
+    pub fn name(&self) -> Option<&'m str> {
+        self.name_subsection(0)?.parse_name().ok()
+    }
+
+    /// Returns the content of a custom section, if any.
+    ///
+    /// The content must not cross page boundaries (the module is unsupported otherwise).
+    pub fn custom_section_data(&self, name: &str) -> Result<Option<&'m [u8]>, Error> {
+        let section = match self.custom_section(name) {
+            None => return Ok(None),
+            Some(x) => x,
+        };
+        let (offset, end) = (section.offset(), section.offset() + section.len());
+        let mut parser = parser::Parser::<Check>::internal_new(self.binary, offset, end);
+        Ok(Some(parser.parse_bytes(section.len())?))
+    }
+
+    /// Returns the name of a function according to the name section, if any.
+    ///
+    /// The function index includes imported functions.
+    pub fn func_name(&self, func: FuncIdx) -> Option<&'m str> {
+        name_map(&mut self.name_subsection(1)?, func).ok().flatten()
+    }
+
+    /// Returns the name of a local according to the name section, if any.
+    ///
+    /// The local index includes the parameters of the function.
+    pub fn local_name(&self, func: FuncIdx, local: LocalIdx) -> Option<&'m str> {
+        let mut parser = self.name_subsection(2)?;
+        let result: Result<_, Error> = try {
+            let mut names = None;
+            for _ in 0 .. parser.parse_vec()? {
+                let x = parser.parse_u32()?;
+                let mut map = parser.clone();
+                skip_name_map(&mut parser)?;
+                if x == func {
+                    names = name_map(&mut map, local)?;
+                    break;
+                }
+            }
+            names
+        };
+        result.ok().flatten()
+    }
+
     pub(crate) fn types(&self) -> &[FuncType<'m>] {
         &self.types
     }
@@ -107,7 +198,7 @@ pub type Parser<'m> = parser::Parser<'m, Use>;
 
 impl<'m> Module<'m> {
     pub(crate) fn section(&self, expected_id: SectionId) -> Option<Parser<'m>> {
-        let mut parser = unsafe { Parser::new(self.binary) };
+        let mut parser = self.sections();
         loop {
             if parser.is_empty() {
                 return None;
@@ -147,17 +238,6 @@ impl<'m> Module<'m> {
         unreachable!()
     }
 
-    pub(crate) fn mem_type(&self, x: MemIdx) -> MemType {
-        let mut parser = self.section(SectionId::Memory).unwrap();
-        for i in 0 .. parser.parse_vec().into_ok() {
-            let t = parser.parse_memtype().into_ok();
-            if i == x as usize {
-                return t;
-            }
-        }
-        unreachable!()
-    }
-
     pub(crate) fn global_type(&self, x: GlobalIdx) -> GlobalType {
         let mut parser = self.section(SectionId::Global).unwrap();
         for i in 0 .. parser.parse_vec().into_ok() {
@@ -205,6 +285,176 @@ impl<'m> Module<'m> {
         unreachable!()
     }
 
+    /// Returns the function index and module offset of a position in the code section.
+    pub(crate) fn func_pos(&self, pos: &Parser<'m>) -> (FuncIdx, usize) {
+        let imported = self.imports().filter(|x| matches!(x.desc, ImportDesc::Func(_))).count();
+        ((imported + self.func_find(pos)) as FuncIdx, pos.offset())
+    }
+
+    /// Returns the position of an instruction given its function index and module offset.
+    ///
+    /// Returns `None` if there is no such instruction.
+    pub(crate) fn func_instr(&self, x: FuncIdx, offset: usize) -> Option<Parser<'m>> {
+        let imported = self.imports().filter(|x| matches!(x.desc, ImportDesc::Func(_))).count();
+        let x = (x as usize).checked_sub(imported).filter(|&x| x < self.func_count())?;
+        let body = self.func(x as FuncIdx);
+        let len = body.len().checked_sub(offset.checked_sub(body.offset())?)?;
+        let (pos, _) = self.func_locate(x as FuncIdx, len)?;
+        Some(pos)
+    }
+
+    /// Returns the types of the locals (including parameters) of the function containing a
+    /// position in the code section.
+    pub(crate) fn local_types(&self, pos: &Parser<'m>) -> Vec<ValType> {
+        self.func_locals(self.func_find(pos) as FuncIdx)
+    }
+
+    /// Returns the types of the locals (including parameters) of a function.
+    pub(crate) fn func_locals(&self, x: FuncIdx) -> Vec<ValType> {
+        let mut types = self.func_type(x).params.to_vec();
+        self.func(x).parse_locals(&mut types).into_ok();
+        types
+    }
+
+    /// Returns the number of functions defined by the module (excluding imported ones).
+    pub(crate) fn func_count(&self) -> usize {
+        self.side_tables.len()
+    }
+
+    /// Returns the position in a function body given its remaining length.
+    ///
+    /// The side table cursor at that position is also returned. Returns `None` if the position is
+    /// not an instruction boundary of the function.
+    pub(crate) fn func_locate(&self, x: FuncIdx, len: usize) -> Option<(Parser<'m>, usize)> {
+        let mut parser = self.func(x);
+        parser.parse_locals(&mut Vec::new()).into_ok();
+        let mut cursor = 0;
+        while len <= parser.len() {
+            if len == parser.len() {
+                return Some((parser.save(), cursor));
+            }
+            if let Instr::Block(_) | Instr::If(_) = parser.parse_instr().into_ok() {
+                cursor += 1;
+            }
+        }
+        None
+    }
+
+    /// Returns the module binary.
+    pub(crate) fn binary(&self) -> Binary<'m> {
+        self.binary
+    }
+
+    /// Returns a parser over the sections of the module (i.e. skipping the header).
+    fn sections(&self) -> Parser<'m> {
+        let mut parser = unsafe { Parser::new(self.binary) };
+        parser.skip(8);
+        parser
+    }
+
+    /// Returns the ranges of the binary that must not cross page boundaries, in increasing order.
+    fn contiguous_ranges(&self, page_size: usize) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let mut parser = self.sections();
+        while !parser.is_empty() {
+            let id = parser.parse_section_id().into_ok();
+            let mut section = parser.split_section().into_ok();
+            match id {
+                SectionId::Custom => {
+                    let content = section.offset() .. section.offset() + section.len();
+                    let name = section.save().parse_name().into_ok();
+                    let name_range = bytes_range(&mut section).into_ok();
+                    if content.len() <= page_size {
+                        ranges.push(content);
+                        continue;
+                    }
+                    ranges.push(name_range);
+                    if name == "name" {
+                        // The name section is not validated, so we ignore it when malformed.
+                        let (offset, end) = (section.offset(), section.offset() + section.len());
+                        let mut parser = parser::Parser::internal_new(self.binary, offset, end);
+                        let _ = name_ranges(&mut parser, &mut ranges);
+                    }
+                }
+                SectionId::Type => {
+                    for _ in 0 .. section.parse_vec().into_ok() {
+                        section.parse_byte().into_ok();
+                        ranges.push(bytes_range(&mut section).into_ok());
+                        ranges.push(bytes_range(&mut section).into_ok());
+                    }
+                }
+                SectionId::Import => {
+                    for _ in 0 .. section.parse_vec().into_ok() {
+                        ranges.push(bytes_range(&mut section).into_ok());
+                        ranges.push(bytes_range(&mut section).into_ok());
+                        section.parse_importdesc().into_ok();
+                    }
+                }
+                SectionId::Export => {
+                    for _ in 0 .. section.parse_vec().into_ok() {
+                        ranges.push(bytes_range(&mut section).into_ok());
+                        section.parse_exportdesc().into_ok();
+                    }
+                }
+                _ => (),
+            }
+        }
+        ranges
+    }
+
+    /// Returns the index of the function containing a position in the code section.
+    fn func_find(&self, pos: &Parser<'m>) -> usize {
+        let offset = pos.offset();
+        let mut parser = self.section(SectionId::Code).unwrap();
+        for i in 0 .. parser.parse_vec().into_ok() {
+            let size = parser.parse_u32().into_ok() as usize;
+            let start = parser.offset();
+            parser.skip(size);
+            if (start .. start + size).contains(&offset) {
+                return i;
+            }
+        }
+        unreachable!()
+    }
+
+    /// Returns the content of a subsection of the name section, if any.
+    ///
+    /// The name section is not validated, so it is parsed in check mode and callers ignore it when
+    /// malformed.
+    fn name_subsection(&self, expected_id: u8) -> Option<parser::Parser<'m, Check>> {
+        let section = self.custom_section("name")?;
+        let (offset, end) = (section.offset(), section.offset() + section.len());
+        let mut parser = parser::Parser::<Check>::internal_new(self.binary, offset, end);
+        let result: Result<_, Error> = try {
+            loop {
+                if parser.is_empty() {
+                    break None;
+                }
+                let actual_id = parser.parse_byte()?;
+                let section = parser.split_section()?;
+                // Subsections are in increasing order of their identifier.
+                break match actual_id.cmp(&expected_id) {
+                    Ordering::Less => continue,
+                    Ordering::Equal => Some(section),
+                    Ordering::Greater => None,
+                };
+            }
+        };
+        result.ok().flatten()
+    }
+
+    fn custom_section(&self, expected_name: &str) -> Option<Parser<'m>> {
+        let mut parser = self.sections();
+        while !parser.is_empty() {
+            let id = parser.parse_section_id().into_ok();
+            let mut section = parser.split_section().into_ok();
+            if id == SectionId::Custom && section.parse_name().into_ok() == expected_name {
+                return Some(section);
+            }
+        }
+        None
+    }
+
     pub(crate) fn data(&self, x: DataIdx) -> Parser<'m> {
         let mut parser = self.section(SectionId::Data).unwrap();
         for i in 0 .. parser.parse_vec().into_ok() {
@@ -216,27 +466,70 @@ impl<'m> Module<'m> {
         unreachable!()
     }
 
-    pub(crate) fn skip(
-        &mut self, parser: &mut Parser<'m>, depth: LabelIdx,
-        compute: impl Fn(&mut Parser<'m>, LabelIdx),
-    ) {
-        let saved = parser.save();
-        let key = CacheKey::Skip { ptr: saved.as_ptr(), depth };
-        match self.cache.get(&key) {
-            Some(delta) => unsafe { parser.restore(&saved[delta.skip ..]) },
-            None => {
-                compute(parser, depth);
-                let delta = saved.len() - parser.save().len();
-                self.cache.put(key, CacheValue { skip: delta });
+    /// Returns the side table entry of a `block` or `if` in a function.
+    pub(crate) fn side_entry(&self, x: FuncIdx, cursor: usize) -> SideEntry {
+        self.side_tables[x as usize][cursor]
+    }
+}
+
+/// Looks up an index in a name map.
+fn name_map<'m>(parser: &mut parser::Parser<'m, Check>, x: u32) -> Result<Option<&'m str>, Error> {
+    let mut name = None;
+    for _ in 0 .. parser.parse_vec()? {
+        let y = parser.parse_u32()?;
+        let n = parser.parse_name()?;
+        if x == y {
+            name = Some(n);
+        }
+    }
+    Ok(name)
+}
+
+/// Parses length-prefixed bytes (like a name) and returns their range in the binary.
+fn bytes_range<M: Mode>(parser: &mut parser::Parser<M>) -> MResult<Range<usize>, M> {
+    let len = parser.parse_u32()? as usize;
+    parser.check_len(len)?;
+    let start = parser.offset();
+    parser.skip(len);
+    Ok(start .. start + len)
+}
+
+/// Collects the ranges of the names in the name section.
+fn name_ranges(
+    parser: &mut parser::Parser<Check>, ranges: &mut Vec<Range<usize>>,
+) -> Result<(), Error> {
+    while !parser.is_empty() {
+        let id = parser.parse_byte()?;
+        let mut section = parser.split_section()?;
+        match id {
+            0 => ranges.push(bytes_range(&mut section)?),
+            1 => name_map_ranges(&mut section, ranges)?,
+            2 => {
+                for _ in 0 .. section.parse_vec()? {
+                    section.parse_u32()?;
+                    name_map_ranges(&mut section, ranges)?;
+                }
             }
+            _ => (),
         }
     }
+    Ok(())
+}
 
-    pub(crate) fn skip_to_else(&mut self, parser: &mut Parser<'m>) {
-        self.skip(parser, 0, |p, _| p.skip_to_else().into_ok());
+fn name_map_ranges(
+    parser: &mut parser::Parser<Check>, ranges: &mut Vec<Range<usize>>,
+) -> Result<(), Error> {
+    for _ in 0 .. parser.parse_vec()? {
+        parser.parse_u32()?;
+        ranges.push(bytes_range(parser)?);
     }
+    Ok(())
+}
 
-    pub(crate) fn skip_to_end(&mut self, parser: &mut Parser<'m>, l: LabelIdx) {
-        self.skip(parser, l, |p, l| p.skip_to_end(l).into_ok());
+fn skip_name_map(parser: &mut parser::Parser<Check>) -> Result<(), Error> {
+    for _ in 0 .. parser.parse_vec()? {
+        parser.parse_u32()?;
+        parser.parse_name()?;
     }
+    Ok(())
 }
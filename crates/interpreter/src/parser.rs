@@ -18,56 +18,225 @@ use core::marker::PhantomData;
 use crate::syntax::*;
 use crate::toctou::*;
 
-#[derive(Debug, Default, Clone)]
+/// Module binary stored in pages that are not necessarily contiguous (e.g. flash pages).
+///
+/// The pages are read in place: the module is validated and executed without copying its binary.
+/// Pages may have different lengths, which lets installers follow [`Module::page_layout()`].
+///
+/// [`Module::page_layout()`]: crate::Module::page_layout
+pub trait Pages {
+    /// Returns the length of the binary in bytes.
+    fn len(&self) -> usize;
+
+    /// Returns whether the binary is empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the bytes of the binary from an offset to the end of its page.
+    ///
+    /// The offset is smaller than the length of the binary. The result must not be empty.
+    fn page(&self, offset: usize) -> &[u8];
+}
+
+/// Module binary, either contiguous or stored in pages.
+#[derive(Copy, Clone)]
+pub enum Binary<'m> {
+    Slice(&'m [u8]),
+    Pages(&'m dyn Pages),
+}
+
+impl<'m> Default for Binary<'m> {
+    fn default() -> Self {
+        Binary::Slice(&[])
+    }
+}
+
+impl<'m> core::fmt::Debug for Binary<'m> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Binary::Slice(x) => f.debug_tuple("Slice").field(&x.len()).finish(),
+            Binary::Pages(x) => f.debug_tuple("Pages").field(&x.len()).finish(),
+        }
+    }
+}
+
+impl<'m> From<&'m [u8]> for Binary<'m> {
+    fn from(x: &'m [u8]) -> Self {
+        Binary::Slice(x)
+    }
+}
+
+impl<'m> Binary<'m> {
+    pub fn len(self) -> usize {
+        match self {
+            Binary::Slice(x) => x.len(),
+            Binary::Pages(x) => x.len(),
+        }
+    }
+
+    /// Returns the bytes from an offset to the end of its page.
+    fn page(self, offset: usize) -> &'m [u8] {
+        match self {
+            Binary::Slice(x) => &x[offset ..],
+            Binary::Pages(x) => x.page(offset),
+        }
+    }
+
+    /// Calls a function on each page of the binary in order.
+    pub fn for_each_page(self, mut f: impl FnMut(&'m [u8])) {
+        let mut offset = 0;
+        while offset < self.len() {
+            let page = self.page(offset);
+            f(page);
+            offset += page.len();
+        }
+    }
+}
+
+/// Parser over a range of a module binary.
+///
+/// A parser is also a position in the binary: it can be saved and restored to jump around.
+#[derive(Debug, Default)]
 pub struct Parser<'m, M: Mode> {
+    /// Remaining bytes of the current page (up to the end of the parser).
+    ///
+    /// This is only empty if the parser is empty.
     data: &'m [u8],
+    /// Number of remaining bytes after the current page.
+    rest: usize,
+    /// Offset of the end of the parser in the binary.
+    end: usize,
+    binary: Binary<'m>,
     mode: PhantomData<M>,
 }
 
+// We don't derive Clone because it would require the mode to be Clone.
+impl<'m, M: Mode> Clone for Parser<'m, M> {
+    fn clone(&self) -> Self {
+        let Parser { data, rest, end, binary, mode } = *self;
+        Parser { data, rest, end, binary, mode }
+    }
+}
+
 impl<'m> Parser<'m, Check> {
-    pub fn new(data: &'m [u8]) -> Self {
-        Self::internal_new(data)
+    pub fn new(binary: impl Into<Binary<'m>>) -> Self {
+        let binary = binary.into();
+        Self::internal_new(binary, 0, binary.len())
     }
 }
 
 impl<'m> Parser<'m, Use> {
     // Safety: The data must have been previously parsed in Check mode.
-    pub unsafe fn new(data: &'m [u8]) -> Self {
-        Self::internal_new(data)
+    pub unsafe fn new(binary: impl Into<Binary<'m>>) -> Self {
+        let binary = binary.into();
+        Self::internal_new(binary, 0, binary.len())
     }
 }
 
 impl<'m, M: Mode> Parser<'m, M> {
-    pub fn save(&self) -> &'m [u8] {
-        self.data
+    pub fn save(&self) -> Self {
+        self.clone()
     }
 
-    // Safety: The data must have been saved in a similar state.
-    pub unsafe fn restore(&mut self, data: &'m [u8]) {
-        self.data = data;
+    // Safety: The parser must have been saved in a similar state.
+    pub unsafe fn restore(&mut self, saved: Self) {
+        *self = saved;
+    }
+
+    // Safety: The position must have been saved from this parser as its remaining length.
+    pub unsafe fn seek(&mut self, len: usize) {
+        self.data = &[];
+        self.rest = len;
+        self.next_page();
     }
 
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
     }
 
+    /// Returns the number of remaining bytes.
+    pub fn len(&self) -> usize {
+        self.data.len() + self.rest
+    }
+
+    /// Returns the offset of the next byte in the binary.
+    pub fn offset(&self) -> usize {
+        self.end - self.len()
+    }
+
     pub fn check_len(&self, len: usize) -> MResult<(), M> {
-        M::check(|| len <= self.data.len())
+        M::check(|| len <= self.len())
     }
 
+    /// Parses contiguous bytes.
+    ///
+    /// Bytes crossing a page boundary are not supported.
     pub fn parse_bytes(&mut self, len: usize) -> MResult<&'m [u8], M> {
         self.check_len(len)?;
+        if self.data.len() < len {
+            return M::unsupported();
+        }
         let (result, data) = split_at(self.data, len);
         self.data = data;
+        self.next_page();
         Ok(result)
     }
 
+    pub fn parse_array<const N: usize>(&mut self) -> MResult<[u8; N], M> {
+        self.check_len(N)?;
+        let mut result = [0; N];
+        self.copy_into(&mut result);
+        Ok(result)
+    }
+
+    /// Copies the next bytes, possibly across page boundaries.
+    ///
+    /// The parser must have enough bytes.
+    pub fn copy_into(&mut self, mut dst: &mut [u8]) {
+        while !dst.is_empty() {
+            let len = core::cmp::min(dst.len(), self.data.len());
+            let (src, data) = split_at(self.data, len);
+            dst[.. len].copy_from_slice(src);
+            dst = &mut dst[len ..];
+            self.data = data;
+            self.next_page();
+        }
+    }
+
+    /// Skips the next bytes, possibly across page boundaries.
+    ///
+    /// The parser must have enough bytes.
+    pub fn skip(&mut self, len: usize) {
+        if len <= self.data.len() {
+            self.data = split_at(self.data, len).1;
+        } else {
+            self.rest -= len - self.data.len();
+            self.data = &[];
+        }
+        self.next_page();
+    }
+
     pub fn split_at(&mut self, len: usize) -> MResult<Parser<'m, M>, M> {
-        Ok(Self::internal_new(self.parse_bytes(len)?))
+        self.check_len(len)?;
+        let offset = self.offset();
+        let result = if len <= self.data.len() {
+            let data = split_at(self.data, len).0;
+            let end = offset + len;
+            Parser { data, rest: 0, end, binary: self.binary, mode: PhantomData }
+        } else {
+            Self::internal_new(self.binary, offset, offset + len)
+        };
+        self.skip(len);
+        Ok(result)
     }
 
     pub fn parse_byte(&mut self) -> MResult<u8, M> {
-        Ok(get(self.parse_bytes(1)?, 0))
+        self.check_len(1)?;
+        let byte = get(self.data, 0);
+        self.data = split_at(self.data, 1).1;
+        self.next_page();
+        Ok(byte)
     }
 
     pub fn parse_leb128(&mut self, signed: bool, bits: u8) -> MResult<u64, M> {
@@ -199,8 +368,13 @@ impl<'m, M: Mode> Parser<'m, M> {
         byte_enum::<M, _>(self.parse_byte()?)
     }
 
-    pub fn parse_limits(&mut self, mut max: u32) -> MResult<Limits, M> {
-        let has_max = byte_enum::<M, bool>(self.parse_byte()?)?;
+    pub fn parse_limits(&mut self, max: u32) -> MResult<Limits, M> {
+        let flags = self.parse_byte()?;
+        self.parse_limits_flags(flags, max)
+    }
+
+    fn parse_limits_flags(&mut self, flags: u8, mut max: u32) -> MResult<Limits, M> {
+        let has_max = byte_enum::<M, bool>(flags)?;
         let min = self.parse_u32()?;
         if has_max {
             max = self.parse_u32()?;
@@ -215,7 +389,15 @@ impl<'m, M: Mode> Parser<'m, M> {
     }
 
     pub fn parse_memtype(&mut self) -> MResult<MemType, M> {
-        self.parse_limits(MEM_MAX)
+        // The threads proposal encodes whether the memory is shared with bit 1 of the limits flags.
+        let flags = self.parse_byte()?;
+        let shared = flags & 2 != 0;
+        if shared {
+            // Shared memories must have a maximum.
+            support_if!("threads"[], M::check(|| flags == 3)?, M::unsupported()?);
+        }
+        let limits = self.parse_limits_flags(flags & !2, MEM_MAX)?;
+        Ok(MemType { limits, shared })
     }
 
     pub fn parse_mut(&mut self) -> MResult<Mut, M> {
@@ -249,9 +431,13 @@ impl<'m, M: Mode> Parser<'m, M> {
     }
 
     pub fn parse_memarg(&mut self) -> MResult<MemArg, M> {
-        let align = self.parse_u32()?;
+        // The multi-memory proposal encodes the memory index with bit 6 of the alignment.
+        let mut align = self.parse_u32()?;
+        M::check(|| align < 0x80)?;
+        let memory = if align & 0x40 == 0 { 0 } else { self.parse_memidx()? };
+        align &= !0x40;
         let offset = self.parse_u32()?;
-        Ok(MemArg { align, offset })
+        Ok(MemArg { memory, align, offset })
     }
 
     pub fn parse_blocktype(&mut self) -> MResult<BlockType, M> {
@@ -290,6 +476,13 @@ impl<'m, M: Mode> Parser<'m, M> {
                 let x = self.parse_tableidx()?;
                 Instr::CallIndirect(x, y)
             }
+            0x12 => Instr::ReturnCall(self.parse_funcidx()?),
+            0x13 => {
+                // For some reason, parsing order differs from field order here.
+                let y = self.parse_typeidx()?;
+                let x = self.parse_tableidx()?;
+                Instr::ReturnCallIndirect(x, y)
+            }
             0x1a => Instr::Drop,
             0x1b => Instr::Select(None),
             0x1c => Instr::Select(Some(self.parse_resulttype()?)),
@@ -318,24 +511,18 @@ impl<'m, M: Mode> Parser<'m, M> {
                 M::unsupported()?
             ),
             x @ 0x3a ..= 0x3e => Instr::IStore_((x - 0x3a).into(), self.parse_memarg()?),
-            0x3f => {
-                check_eq::<M, _>(self.parse_byte()?, 0)?;
-                Instr::MemorySize
-            }
-            0x40 => {
-                check_eq::<M, _>(self.parse_byte()?, 0)?;
-                Instr::MemoryGrow
-            }
+            0x3f => Instr::MemorySize(self.parse_memidx()?),
+            0x40 => Instr::MemoryGrow(self.parse_memidx()?),
             0x41 => Instr::I32Const(self.parse_i32()?),
             0x42 => Instr::I64Const(self.parse_i64()?),
             0x43 => support_if!(
                 "float-types"[],
-                Instr::F32Const(u32::from_le_bytes(self.parse_bytes(4)?.try_into().unwrap())),
+                Instr::F32Const(u32::from_le_bytes(self.parse_array()?)),
                 M::unsupported()?
             ),
             0x44 => support_if!(
                 "float-types"[],
-                Instr::F64Const(u64::from_le_bytes(self.parse_bytes(8)?.try_into().unwrap())),
+                Instr::F64Const(u64::from_le_bytes(self.parse_array()?)),
                 M::unsupported()?
             ),
             x @ 0x45 ..= 0x5a => {
@@ -427,19 +614,14 @@ impl<'m, M: Mode> Parser<'m, M> {
                     M::unsupported()?
                 ),
                 8 => {
-                    let x = self.parse_dataidx()?;
-                    check_eq::<M, _>(self.parse_byte()?, 0)?;
-                    Instr::MemoryInit(x)
+                    // For some reason, parsing order differs from field order here.
+                    let y = self.parse_dataidx()?;
+                    let x = self.parse_memidx()?;
+                    Instr::MemoryInit(x, y)
                 }
                 9 => Instr::DataDrop(self.parse_dataidx()?),
-                10 => {
-                    check_eq::<M, _>(self.parse_bytes(2)?, &[0; 2])?;
-                    Instr::MemoryCopy
-                }
-                11 => {
-                    check_eq::<M, _>(self.parse_byte()?, 0)?;
-                    Instr::MemoryFill
-                }
+                10 => Instr::MemoryCopy(self.parse_memidx()?, self.parse_memidx()?),
+                11 => Instr::MemoryFill(self.parse_memidx()?),
                 12 => {
                     // For some reason, parsing order differs from field order here.
                     let y = self.parse_elemidx()?;
@@ -453,7 +635,223 @@ impl<'m, M: Mode> Parser<'m, M> {
                 17 => Instr::TableFill(self.parse_tableidx()?),
                 _ => M::invalid()?,
             },
-            0xfd => support_if!("vector-types"[], unimplemented!(), M::unsupported()?),
+            0xfd => support_if!("vector-types"[], self.parse_vector_instr()?, M::unsupported()?),
+            0xfe => support_if!("threads"[], self.parse_atomic_instr()?, M::unsupported()?),
+            _ => M::invalid()?,
+        })
+    }
+
+    #[cfg(feature = "threads")]
+    fn parse_atomic_instr(&mut self) -> MResult<Instr<'m>, M> {
+        // Atomic accesses come in groups of 7 by width: i32, i64, then the narrow ones. The width is
+        // returned as `Ok` for the full types and `Err` for the narrow ones.
+        let width = |x: u32| match x as u8 % 7 {
+            x @ 0 ..= 1 => Ok(x.into()),
+            x => Err((x - 2).into()),
+        };
+        Ok(match self.parse_u32()? {
+            0 => Instr::AtomicNotify(self.parse_memarg()?),
+            x @ 1 ..= 2 => Instr::AtomicWait((x as u8 - 1).into(), self.parse_memarg()?),
+            3 => {
+                let zero = self.parse_byte()?;
+                M::check(|| zero == 0)?;
+                Instr::AtomicFence
+            }
+            x @ 0x10 ..= 0x16 => match width(x - 0x10) {
+                Ok(n) => Instr::AtomicLoad(n, self.parse_memarg()?),
+                Err(b) => Instr::AtomicLoad_(b, self.parse_memarg()?),
+            },
+            x @ 0x17 ..= 0x1d => match width(x - 0x17) {
+                Ok(n) => Instr::AtomicStore(n, self.parse_memarg()?),
+                Err(b) => Instr::AtomicStore_(b, self.parse_memarg()?),
+            },
+            x @ 0x1e ..= 0x47 => {
+                let op = ((x - 0x1e) as u8 / 7).into();
+                match width(x - 0x1e) {
+                    Ok(n) => Instr::AtomicRmw(op, n, self.parse_memarg()?),
+                    Err(b) => Instr::AtomicRmw_(op, b, self.parse_memarg()?),
+                }
+            }
+            x @ 0x48 ..= 0x4e => match width(x - 0x48) {
+                Ok(n) => Instr::AtomicCmpxchg(n, self.parse_memarg()?),
+                Err(b) => Instr::AtomicCmpxchg_(b, self.parse_memarg()?),
+            },
+            _ => M::invalid()?,
+        })
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn parse_vector_instr(&mut self) -> MResult<Instr<'m>, M> {
+        use Shape::*;
+        const INTS: [Shape; 4] = [I8x16, I16x8, I32x4, I64x2];
+        #[cfg(feature = "float-types")]
+        const FLOATS: [Shape; 2] = [F32x4, F64x2];
+        Ok(match self.parse_u32()? {
+            0 => Instr::V128Load(self.parse_memarg()?),
+            x @ 1 ..= 6 => {
+                let s = [I16x8, I32x4, I64x2][(x as usize - 1) / 2];
+                Instr::V128LoadExtend(s, ((x - 1) as u8 % 2).into(), self.parse_memarg()?)
+            }
+            x @ 7 ..= 10 => Instr::V128LoadSplat(INTS[x as usize - 7], self.parse_memarg()?),
+            11 => Instr::V128Store(self.parse_memarg()?),
+            12 => Instr::V128Const(u128::from_le_bytes(self.parse_array()?)),
+            13 => Instr::I8x16Shuffle(self.parse_array()?),
+            14 => Instr::I8x16Swizzle,
+            x @ 15 ..= 18 => Instr::VSplat(INTS[x as usize - 15]),
+            x @ 19 ..= 20 => support_if!(
+                "float-types"[x],
+                Instr::VSplat(FLOATS[x as usize - 19]),
+                M::unsupported()?
+            ),
+            x @ 21 ..= 26 => {
+                let s = INTS[(x as usize - 21) / 3];
+                match (x - 21) % 3 {
+                    y @ 0 ..= 1 => Instr::VExtractLane(s, (y as u8).into(), self.parse_byte()?),
+                    _ => Instr::VReplaceLane(s, self.parse_byte()?),
+                }
+            }
+            x @ 27 ..= 30 => {
+                let s = INTS[(x as usize - 27) / 2 + 2];
+                match (x - 27) % 2 {
+                    0 => Instr::VExtractLane(s, Sx::U, self.parse_byte()?),
+                    _ => Instr::VReplaceLane(s, self.parse_byte()?),
+                }
+            }
+            x @ 31 ..= 34 => support_if!(
+                "float-types"[x],
+                {
+                    let s = FLOATS[(x as usize - 31) / 2];
+                    match (x - 31) % 2 {
+                        0 => Instr::VExtractLane(s, Sx::U, self.parse_byte()?),
+                        _ => Instr::VReplaceLane(s, self.parse_byte()?),
+                    }
+                },
+                M::unsupported()?
+            ),
+            x @ 35 ..= 64 => {
+                Instr::VIRelOp(INTS[(x as usize - 35) / 10], ((x - 35) as u8 % 10).into())
+            }
+            x @ 65 ..= 76 => support_if!(
+                "float-types"[x],
+                Instr::VFRelOp(FLOATS[(x as usize - 65) / 6], ((x - 65) as u8 % 6).into()),
+                M::unsupported()?
+            ),
+            77 => Instr::V128Not,
+            x @ 78 ..= 81 => Instr::VBinOp((x as u8 - 78).into()),
+            82 => Instr::V128Bitselect,
+            83 => Instr::V128AnyTrue,
+            x @ 84 ..= 87 => {
+                let m = self.parse_memarg()?;
+                Instr::V128LoadLane(INTS[x as usize - 84], m, self.parse_byte()?)
+            }
+            x @ 88 ..= 91 => {
+                let m = self.parse_memarg()?;
+                Instr::V128StoreLane(INTS[x as usize - 88], m, self.parse_byte()?)
+            }
+            x @ 92 ..= 93 => Instr::V128LoadZero((x as u8 - 92).into(), self.parse_memarg()?),
+            94 => support_if!("float-types"[], Instr::VCvtOp(VCvtOp::Demote), M::unsupported()?),
+            95 => support_if!("float-types"[], Instr::VCvtOp(VCvtOp::Promote), M::unsupported()?),
+            x @ 96 ..= 223 => {
+                // Integer operations are mostly laid out by blocks of 32 opcodes per shape. The
+                // few floating-point operations in between are handled as exceptions.
+                let s = INTS[(x as usize - 96) / 32];
+                match ((x - 96) as u8 % 32, s) {
+                    (0, _) => Instr::VIUnOp(s, VIUnOp::Abs),
+                    (1, _) => Instr::VIUnOp(s, VIUnOp::Neg),
+                    (2, I8x16) => Instr::VIUnOp(s, VIUnOp::PopCnt),
+                    (2, I16x8) => Instr::VIBinOp(s, VIBinOp::Q15MulrSatS),
+                    (3, _) => Instr::VAllTrue(s),
+                    (4, _) => Instr::VBitmask(s),
+                    (y @ 5 ..= 6, I8x16 | I16x8) => Instr::VNarrow(s, (y - 5).into()),
+                    (y @ 7 ..= 10, I8x16) => support_if!(
+                        "float-types"[y],
+                        Instr::VFUnOp(F32x4, (y - 5).into()),
+                        M::unsupported()?
+                    ),
+                    (y @ 7 ..= 10, _) => {
+                        Instr::VExtend(s, ((y - 7) % 2).into(), ((y - 7) / 2).into())
+                    }
+                    (11, _) => Instr::VShiftOp(s, VShiftOp::Shl),
+                    (y @ 12 ..= 13, _) => Instr::VShiftOp(s, VShiftOp::Shr((y - 12).into())),
+                    (14, _) => Instr::VIBinOp(s, VIBinOp::Add),
+                    (y @ 15 ..= 16, I8x16 | I16x8) => {
+                        Instr::VIBinOp(s, VIBinOp::AddSat((y - 15).into()))
+                    }
+                    (17, _) => Instr::VIBinOp(s, VIBinOp::Sub),
+                    (y @ 18 ..= 19, I8x16 | I16x8) => {
+                        Instr::VIBinOp(s, VIBinOp::SubSat((y - 18).into()))
+                    }
+                    (y @ 20 ..= 21, I8x16) => support_if!(
+                        "float-types"[y],
+                        Instr::VFUnOp(F64x2, (y - 18).into()),
+                        M::unsupported()?
+                    ),
+                    (20, I16x8) => support_if!(
+                        "float-types"[],
+                        Instr::VFUnOp(F64x2, FUnOp::Nearest),
+                        M::unsupported()?
+                    ),
+                    (21, _) => Instr::VIBinOp(s, VIBinOp::Mul),
+                    (y @ 22 ..= 23, I8x16 | I16x8 | I32x4) => {
+                        Instr::VIBinOp(s, VIBinOp::Min((y - 22).into()))
+                    }
+                    (y @ 24 ..= 25, I8x16 | I16x8 | I32x4) => {
+                        Instr::VIBinOp(s, VIBinOp::Max((y - 24).into()))
+                    }
+                    (y @ 22 ..= 27, I64x2) => Instr::VIRelOp(
+                        s,
+                        match y - 22 {
+                            0 => IRelOp::Eq,
+                            1 => IRelOp::Ne,
+                            2 => IRelOp::Lt(Sx::S),
+                            3 => IRelOp::Gt(Sx::S),
+                            4 => IRelOp::Le(Sx::S),
+                            _ => IRelOp::Ge(Sx::S),
+                        },
+                    ),
+                    (26, I8x16) => support_if!(
+                        "float-types"[],
+                        Instr::VFUnOp(F64x2, FUnOp::Trunc),
+                        M::unsupported()?
+                    ),
+                    (26, I32x4) => Instr::I32x4DotI16x8S,
+                    (27, I8x16 | I16x8) => Instr::VIBinOp(s, VIBinOp::AvgrU),
+                    (y @ 28 ..= 31, I8x16) => Instr::VExtAddPairwise(
+                        INTS[(y as usize - 28) / 2 + 1],
+                        ((y - 28) % 2).into(),
+                    ),
+                    (y @ 28 ..= 31, _) => {
+                        Instr::VExtMul(s, ((y - 28) % 2).into(), ((y - 28) / 2).into())
+                    }
+                    _ => M::invalid()?,
+                }
+            }
+            x @ 224 ..= 247 => support_if!(
+                "float-types"[x],
+                {
+                    let s = FLOATS[(x as usize - 224) / 12];
+                    match (x - 224) as u8 % 12 {
+                        0 => Instr::VFUnOp(s, FUnOp::Abs),
+                        1 => Instr::VFUnOp(s, FUnOp::Neg),
+                        3 => Instr::VFUnOp(s, FUnOp::Sqrt),
+                        y @ 4 ..= 11 => Instr::VFBinOp(s, (y - 4).into()),
+                        _ => M::invalid()?,
+                    }
+                },
+                M::unsupported()?
+            ),
+            x @ 248 ..= 255 => support_if!(
+                "float-types"[x],
+                {
+                    let s = FLOATS[(x as usize - 248) / 4];
+                    let sx = ((x - 248) as u8 % 2).into();
+                    match (x - 248) % 4 {
+                        0 ..= 1 => Instr::VCvtOp(VCvtOp::TruncSat(s, sx)),
+                        _ => Instr::VCvtOp(VCvtOp::Convert(s, sx)),
+                    }
+                },
+                M::unsupported()?
+            ),
             _ => M::invalid()?,
         })
     }
@@ -537,26 +935,7 @@ impl<'m, M: Mode> Parser<'m, M> {
         user.mode(mode)?;
         // D: We parse the init.
         let len = self.parse_u32()? as usize;
-        user.init(self.parse_bytes(len)?)
-    }
-
-    pub fn skip_to_else(&mut self) -> MResult<(), M> {
-        let mut depth = 0;
-        loop {
-            let saved = self.save();
-            match self.parse_instr()? {
-                Instr::Block(_) => depth += 1,
-                Instr::Loop(_) => depth += 1,
-                Instr::If(_) => depth += 1,
-                Instr::End if depth == 0 => {
-                    unsafe { self.restore(saved) };
-                    return Ok(());
-                }
-                Instr::End => depth -= 1,
-                Instr::Else if depth == 0 => return Ok(()),
-                _ => (),
-            }
-        }
+        user.init(self.split_at(len)?)
     }
 
     pub fn skip_to_end(&mut self, l: LabelIdx) -> MResult<(), M> {
@@ -617,7 +996,7 @@ pub trait ParseData<'m, M: Mode> {
         }
     }
 
-    fn init(&mut self, _init: &'m [u8]) -> MResult<(), M> {
+    fn init(&mut self, _init: Parser<'m, M>) -> MResult<(), M> {
         Ok(())
     }
 }
@@ -633,8 +1012,21 @@ pub struct SkipData;
 impl<'m, M: Mode> ParseData<'m, M> for SkipData {}
 
 impl<'m, M: Mode> Parser<'m, M> {
-    fn internal_new(data: &'m [u8]) -> Self {
-        Self { data, mode: PhantomData }
+    pub(crate) fn internal_new(binary: Binary<'m>, offset: usize, end: usize) -> Self {
+        let rest = end - offset;
+        let mut parser = Parser { data: &[], rest, end, binary, mode: PhantomData };
+        parser.next_page();
+        parser
+    }
+
+    /// Moves to the next page if the current one is exhausted.
+    fn next_page(&mut self) {
+        if self.data.is_empty() && 0 < self.rest {
+            let page = self.binary.page(self.end - self.rest);
+            let len = core::cmp::min(page.len(), self.rest);
+            self.data = split_at(page, len).0;
+            self.rest -= len;
+        }
     }
 }
 
@@ -642,10 +1034,6 @@ impl<'m, M: Mode> Parser<'m, M> {
 // NOTE: This should be configurable.
 const MAX_LOCALS: usize = 100;
 
-fn check_eq<M: Mode, T: Eq>(x: T, y: T) -> MResult<(), M> {
-    M::check(|| x == y)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,108 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::parser::Parser;
+use crate::syntax::Instr;
+use crate::toctou::*;
+
+/// Branch targets of a `block` or `if` instruction.
+///
+/// Positions are byte offsets from the start of the instruction. Cursors are indices in the side
+/// table of the function and identify the entry of the next `block` or `if` to execute at that
+/// position. Stack adjustments are given by the arity of the label and don't need to be stored.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SideEntry {
+    /// Position after the `else` instruction (or of the `end` instruction if there is none).
+    pub else_pos: u32,
+    pub else_cursor: u32,
+    /// Position after the `end` instruction.
+    pub end_pos: u32,
+    pub end_cursor: u32,
+}
+
+/// Side table of a function (one entry per `block` and `if` in order of appearance).
+pub type SideTable = Vec<SideEntry>;
+
+/// Builds the side table of a function while its body is parsed.
+#[derive(Debug)]
+pub struct SideTableBuilder {
+    table: SideTable,
+    /// Start (as remaining length) and entry of the open labels.
+    labels: Vec<(usize, Option<usize>)>,
+}
+
+impl Default for SideTableBuilder {
+    fn default() -> Self {
+        // The function body is an implicit block without entry.
+        Self { table: Vec::new(), labels: vec![(0, None)] }
+    }
+}
+
+impl SideTableBuilder {
+    /// Records an instruction given the remaining length of the parser before and after it.
+    pub fn instr(&mut self, before: usize, instr: &Instr, after: usize) {
+        let cursor = self.table.len() as u32;
+        match instr {
+            Instr::Block(_) | Instr::If(_) => {
+                self.labels.push((before, Some(self.table.len())));
+                self.table.push(SideEntry::default());
+            }
+            Instr::Loop(_) => self.labels.push((before, None)),
+            Instr::Else => {
+                if let Some(&(start, Some(i))) = self.labels.last() {
+                    let entry = &mut self.table[i];
+                    entry.else_pos = (start - after) as u32;
+                    entry.else_cursor = cursor;
+                }
+            }
+            Instr::End => {
+                if let Some((start, Some(i))) = self.labels.pop() {
+                    let entry = &mut self.table[i];
+                    // An offset of zero means there was no else.
+                    if entry.else_pos == 0 {
+                        entry.else_pos = (start - before) as u32;
+                        entry.else_cursor = cursor;
+                    }
+                    entry.end_pos = (start - after) as u32;
+                    entry.end_cursor = cursor;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns whether the function body is complete.
+    pub fn is_done(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn finish(self) -> SideTable {
+        debug_assert!(self.is_done());
+        self.table
+    }
+}
+
+/// Computes the side table of a function body (after its locals).
+pub fn side_table<M: Mode>(parser: &mut Parser<M>) -> MResult<SideTable, M> {
+    let mut builder = SideTableBuilder::default();
+    while !builder.is_done() {
+        let before = parser.len();
+        let instr = parser.parse_instr()?;
+        builder.instr(before, &instr, parser.len());
+    }
+    Ok(builder.finish())
+}
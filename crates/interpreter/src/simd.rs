@@ -0,0 +1,329 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lane-wise semantics of vector instructions.
+//!
+//! Vectors are represented as `u128` in little-endian lane order (lane 0 is the least significant).
+//! Lanes are represented as `u64` and are zero-extended unless mentioned otherwise.
+
+use crate::syntax::*;
+
+impl Shape {
+    pub fn lanes(self) -> usize {
+        match self {
+            Shape::I8x16 => 16,
+            Shape::I16x8 => 8,
+            Shape::I32x4 | Shape::F32x4 => 4,
+            Shape::I64x2 | Shape::F64x2 => 2,
+        }
+    }
+
+    /// Returns the number of bits of a lane.
+    pub fn width(self) -> u32 {
+        128 / self.lanes() as u32
+    }
+
+    /// Returns the type of a lane once extracted.
+    pub fn unpacked(self) -> NumType {
+        match self {
+            Shape::I8x16 | Shape::I16x8 | Shape::I32x4 => NumType::I32,
+            Shape::I64x2 => NumType::I64,
+            Shape::F32x4 => NumType::F32,
+            Shape::F64x2 => NumType::F64,
+        }
+    }
+
+    /// Returns the integer shape with twice smaller lanes.
+    pub fn half(self) -> Shape {
+        match self {
+            Shape::I16x8 => Shape::I8x16,
+            Shape::I32x4 => Shape::I16x8,
+            Shape::I64x2 => Shape::I32x4,
+            _ => unreachable!(),
+        }
+    }
+
+    fn mask(self) -> u64 {
+        u64::MAX >> (64 - self.width())
+    }
+
+    fn sext(self, x: u64) -> i64 {
+        let n = 64 - self.width();
+        (x << n) as i64 >> n
+    }
+
+    fn ext(self, x: u64, s: Sx) -> u64 {
+        match s {
+            Sx::U => x,
+            Sx::S => self.sext(x) as u64,
+        }
+    }
+
+    /// Clamps a signed value to the signed or unsigned range of a lane.
+    fn sat(self, x: i64, s: Sx) -> u64 {
+        let (min, max) = match s {
+            Sx::U => (0, self.mask() as i64),
+            Sx::S => (self.sext(1 << (self.width() - 1)), (self.mask() >> 1) as i64),
+        };
+        x.clamp(min, max) as u64
+    }
+
+    pub fn lane(self, x: u128, i: usize) -> u64 {
+        (x >> (i as u32 * self.width())) as u64 & self.mask()
+    }
+
+    /// Returns a lane extended to 64 bits.
+    pub fn extract(self, x: u128, s: Sx, i: usize) -> u64 {
+        self.ext(self.lane(x, i), s)
+    }
+
+    pub fn replace(self, x: u128, i: usize, v: u64) -> u128 {
+        let n = i as u32 * self.width();
+        let m = self.mask() as u128;
+        x & !(m << n) | ((v as u128 & m) << n)
+    }
+
+    pub fn splat(self, v: u64) -> u128 {
+        self.build(|_| v)
+    }
+
+    fn build(self, f: impl Fn(usize) -> u64) -> u128 {
+        (0 .. self.lanes()).fold(0, |r, i| self.replace(r, i, f(i)))
+    }
+
+    fn map(self, x: u128, f: impl Fn(u64) -> u64) -> u128 {
+        self.build(|i| f(self.lane(x, i)))
+    }
+
+    fn zip(self, x: u128, y: u128, f: impl Fn(u64, u64) -> u64) -> u128 {
+        self.build(|i| f(self.lane(x, i), self.lane(y, i)))
+    }
+
+    pub fn all_true(self, x: u128) -> bool {
+        (0 .. self.lanes()).all(|i| self.lane(x, i) != 0)
+    }
+
+    pub fn bitmask(self, x: u128) -> u32 {
+        let n = self.width() - 1;
+        (0 .. self.lanes()).fold(0, |r, i| r | ((self.lane(x, i) >> n) as u32) << i)
+    }
+
+    /// Narrows the lanes of 2 vectors of the twice larger shape.
+    pub fn narrow(self, s: Sx, x: u128, y: u128) -> u128 {
+        let src = Shape::double(self);
+        let n = src.lanes();
+        self.build(|i| {
+            let v = if i < n { src.lane(x, i) } else { src.lane(y, i - n) };
+            self.sat(src.sext(v), s)
+        })
+    }
+
+    /// Extends half the lanes of a vector of the twice smaller shape.
+    pub fn extend(self, h: Half, s: Sx, x: u128) -> u128 {
+        let src = self.half();
+        let k = h.offset(self);
+        self.build(|i| src.extract(x, s, i + k))
+    }
+
+    pub fn ext_mul(self, h: Half, s: Sx, x: u128, y: u128) -> u128 {
+        let src = self.half();
+        let k = h.offset(self);
+        self.build(|i| src.extract(x, s, i + k).wrapping_mul(src.extract(y, s, i + k)))
+    }
+
+    pub fn ext_add_pairwise(self, s: Sx, x: u128) -> u128 {
+        let src = self.half();
+        self.build(|i| src.extract(x, s, 2 * i).wrapping_add(src.extract(x, s, 2 * i + 1)))
+    }
+
+    fn double(self) -> Shape {
+        match self {
+            Shape::I8x16 => Shape::I16x8,
+            Shape::I16x8 => Shape::I32x4,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Half {
+    fn offset(self, s: Shape) -> usize {
+        match self {
+            Half::Low => 0,
+            Half::High => s.lanes(),
+        }
+    }
+}
+
+impl VBinOp {
+    pub fn eval(&self, x: u128, y: u128) -> u128 {
+        match self {
+            VBinOp::And => x & y,
+            VBinOp::AndNot => x & !y,
+            VBinOp::Or => x | y,
+            VBinOp::Xor => x ^ y,
+        }
+    }
+}
+
+impl VIUnOp {
+    pub fn eval(&self, s: Shape, x: u128) -> u128 {
+        s.map(x, |a| match self {
+            VIUnOp::Abs => s.sext(a).unsigned_abs(),
+            VIUnOp::Neg => a.wrapping_neg(),
+            VIUnOp::PopCnt => a.count_ones() as u64,
+        })
+    }
+}
+
+impl VIBinOp {
+    pub fn eval(&self, s: Shape, x: u128, y: u128) -> u128 {
+        s.zip(x, y, |a, b| match *self {
+            VIBinOp::Add => a.wrapping_add(b),
+            VIBinOp::AddSat(t) => s.sat(s.ext(a, t) as i64 + s.ext(b, t) as i64, t),
+            VIBinOp::Sub => a.wrapping_sub(b),
+            VIBinOp::SubSat(t) => s.sat(s.ext(a, t) as i64 - s.ext(b, t) as i64, t),
+            VIBinOp::Mul => a.wrapping_mul(b),
+            VIBinOp::Min(Sx::U) => core::cmp::min(a, b),
+            VIBinOp::Min(Sx::S) => core::cmp::min(s.sext(a), s.sext(b)) as u64,
+            VIBinOp::Max(Sx::U) => core::cmp::max(a, b),
+            VIBinOp::Max(Sx::S) => core::cmp::max(s.sext(a), s.sext(b)) as u64,
+            VIBinOp::AvgrU => (a + b + 1) >> 1,
+            VIBinOp::Q15MulrSatS => s.sat((s.sext(a) * s.sext(b) + 0x4000) >> 15, Sx::S),
+        })
+    }
+}
+
+impl VShiftOp {
+    pub fn eval(&self, s: Shape, x: u128, n: u32) -> u128 {
+        let n = n % s.width();
+        s.map(x, |a| match self {
+            VShiftOp::Shl => a << n,
+            VShiftOp::Shr(Sx::U) => a >> n,
+            VShiftOp::Shr(Sx::S) => (s.sext(a) >> n) as u64,
+        })
+    }
+}
+
+impl IRelOp {
+    pub fn vector(&self, s: Shape, x: u128, y: u128) -> u128 {
+        let t = match self {
+            IRelOp::Lt(t) | IRelOp::Gt(t) | IRelOp::Le(t) | IRelOp::Ge(t) => *t,
+            IRelOp::Eq | IRelOp::Ne => Sx::U,
+        };
+        s.zip(x, y, |a, b| mask(self.n64(s.ext(a, t), s.ext(b, t))))
+    }
+}
+
+#[cfg(feature = "float-types")]
+impl FRelOp {
+    pub fn vector(&self, s: Shape, x: u128, y: u128) -> u128 {
+        s.zip(x, y, |a, b| match s {
+            Shape::F32x4 => mask(self.n32(a as u32, b as u32)),
+            Shape::F64x2 => mask(self.n64(a, b)),
+            _ => unreachable!(),
+        })
+    }
+}
+
+#[cfg(feature = "float-types")]
+impl FUnOp {
+    pub fn vector(&self, s: Shape, x: u128) -> u128 {
+        s.map(x, |a| match s {
+            Shape::F32x4 => self.n32(a as u32) as u64,
+            Shape::F64x2 => self.n64(a),
+            _ => unreachable!(),
+        })
+    }
+}
+
+#[cfg(feature = "float-types")]
+impl VFBinOp {
+    pub fn eval(&self, s: Shape, x: u128, y: u128) -> u128 {
+        macro_rules! lane {
+            ($a:ident, $b:ident, $n:ident, $f:ident) => {{
+                let op = match self {
+                    VFBinOp::Add => FBinOp::Add,
+                    VFBinOp::Sub => FBinOp::Sub,
+                    VFBinOp::Mul => FBinOp::Mul,
+                    VFBinOp::Div => FBinOp::Div,
+                    VFBinOp::Min => FBinOp::Min,
+                    VFBinOp::Max => FBinOp::Max,
+                    VFBinOp::PMin if $f::from_bits($b) < $f::from_bits($a) => return $b as u64,
+                    VFBinOp::PMax if $f::from_bits($a) < $f::from_bits($b) => return $b as u64,
+                    VFBinOp::PMin | VFBinOp::PMax => return $a as u64,
+                };
+                op.$n($a, $b) as u64
+            }};
+        }
+        s.zip(x, y, |a, b| match s {
+            Shape::F32x4 => {
+                let (a, b) = (a as u32, b as u32);
+                lane!(a, b, n32, f32)
+            }
+            Shape::F64x2 => lane!(a, b, n64, f64),
+            _ => unreachable!(),
+        })
+    }
+}
+
+#[cfg(feature = "float-types")]
+impl VCvtOp {
+    pub fn eval(&self, x: u128) -> u128 {
+        use Shape::*;
+        let f32_lane = |i| f32::from_bits(F32x4.lane(x, i) as u32);
+        let f64_lane = |i| f64::from_bits(F64x2.lane(x, i));
+        let i32_lane = |i, s| I32x4.extract(x, s, i) as i64;
+        // Conversions from F64x2 only produce the 2 low lanes and zero the others.
+        let low = |f: &dyn Fn(usize) -> u64| I32x4.build(|i| if i < 2 { f(i) } else { 0 });
+        match *self {
+            VCvtOp::TruncSat(F32x4, Sx::S) => I32x4.build(|i| f32_lane(i) as i32 as u64),
+            VCvtOp::TruncSat(F32x4, Sx::U) => I32x4.build(|i| f32_lane(i) as u32 as u64),
+            VCvtOp::TruncSat(F64x2, Sx::S) => low(&|i| f64_lane(i) as i32 as u32 as u64),
+            VCvtOp::TruncSat(F64x2, Sx::U) => low(&|i| f64_lane(i) as u32 as u64),
+            VCvtOp::Convert(F32x4, s) => F32x4.build(|i| (i32_lane(i, s) as f32).to_bits() as u64),
+            VCvtOp::Convert(F64x2, s) => F64x2.build(|i| (i32_lane(i, s) as f64).to_bits()),
+            VCvtOp::Demote => low(&|i| (f64_lane(i) as f32).to_bits() as u64),
+            VCvtOp::Promote => F64x2.build(|i| (f32_lane(i) as f64).to_bits()),
+            _ => unreachable!(),
+        }
+    }
+}
+
+pub fn shuffle(lanes: &[LaneIdx; 16], x: u128, y: u128) -> u128 {
+    Shape::I8x16.build(|i| match lanes[i] as usize {
+        j @ 0 ..= 15 => Shape::I8x16.lane(x, j),
+        j => Shape::I8x16.lane(y, j - 16),
+    })
+}
+
+pub fn swizzle(x: u128, y: u128) -> u128 {
+    Shape::I8x16.map(y, |j| match j {
+        0 ..= 15 => Shape::I8x16.lane(x, j as usize),
+        _ => 0,
+    })
+}
+
+pub fn bitselect(x: u128, y: u128, c: u128) -> u128 {
+    x & c | y & !c
+}
+
+pub fn dot(x: u128, y: u128) -> u128 {
+    let s = Shape::I16x8;
+    let mul = |i| s.sext(s.lane(x, i)).wrapping_mul(s.sext(s.lane(y, i)));
+    Shape::I32x4.build(|i| mul(2 * i).wrapping_add(mul(2 * i + 1)) as u64)
+}
+
+fn mask(x: bool) -> u64 {
+    (x as u64).wrapping_neg()
+}
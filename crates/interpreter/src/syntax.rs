@@ -79,7 +79,11 @@ impl Limits {
     }
 }
 
-pub type MemType = Limits;
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MemType {
+    pub limits: Limits,
+    pub shared: bool,
+}
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct TableType {
@@ -214,6 +218,98 @@ pub enum CvtOp {
     FReinterpret(Nx),
 }
 
+/// Interpretation of a vector as lanes.
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Shape {
+    I8x16,
+    I16x8,
+    I32x4,
+    I64x2,
+    F32x4,
+    F64x2,
+}
+
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Half {
+    Low,
+    High,
+}
+
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VBinOp {
+    And,
+    AndNot,
+    Or,
+    Xor,
+}
+
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VIUnOp {
+    Abs,
+    Neg,
+    PopCnt,
+}
+
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VIBinOp {
+    Add,
+    AddSat(Sx),
+    Sub,
+    SubSat(Sx),
+    Mul,
+    Min(Sx),
+    Max(Sx),
+    AvgrU,
+    Q15MulrSatS,
+}
+
+#[cfg(feature = "vector-types")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VShiftOp {
+    Shl,
+    Shr(Sx),
+}
+
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VFBinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+    PMin,
+    PMax,
+}
+
+#[cfg(feature = "threads")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RmwOp {
+    Add,
+    Sub,
+    And,
+    Or,
+    Xor,
+    Xchg,
+}
+
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VCvtOp {
+    /// Saturating truncation of the lanes of `F32x4` or `F64x2` to `I32x4`.
+    TruncSat(Shape, Sx),
+    /// Conversion of the (low) lanes of `I32x4` to `F32x4` or `F64x2`.
+    Convert(Shape, Sx),
+    Demote,
+    Promote,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instr<'m> {
     Unreachable,
@@ -229,6 +325,8 @@ pub enum Instr<'m> {
     Return,
     Call(FuncIdx),
     CallIndirect(TableIdx, TypeIdx), // parsing order differs
+    ReturnCall(FuncIdx),
+    ReturnCallIndirect(TableIdx, TypeIdx), // parsing order differs
     Drop,
     Select(Option<ResultType<'m>>),
     LocalGet(LocalIdx),
@@ -246,8 +344,31 @@ pub enum Instr<'m> {
     #[cfg(feature = "float-types")]
     FStore(Nx, MemArg),
     IStore_(Bx, MemArg),
-    MemorySize,
-    MemoryGrow,
+    MemorySize(MemIdx),
+    MemoryGrow(MemIdx),
+    #[cfg(feature = "threads")]
+    AtomicNotify(MemArg),
+    #[cfg(feature = "threads")]
+    AtomicWait(Nx, MemArg),
+    #[cfg(feature = "threads")]
+    AtomicFence,
+    #[cfg(feature = "threads")]
+    AtomicLoad(Nx, MemArg),
+    /// Atomic loads of less than the type width are unsigned.
+    #[cfg(feature = "threads")]
+    AtomicLoad_(Bx, MemArg),
+    #[cfg(feature = "threads")]
+    AtomicStore(Nx, MemArg),
+    #[cfg(feature = "threads")]
+    AtomicStore_(Bx, MemArg),
+    #[cfg(feature = "threads")]
+    AtomicRmw(RmwOp, Nx, MemArg),
+    #[cfg(feature = "threads")]
+    AtomicRmw_(RmwOp, Bx, MemArg),
+    #[cfg(feature = "threads")]
+    AtomicCmpxchg(Nx, MemArg),
+    #[cfg(feature = "threads")]
+    AtomicCmpxchg_(Bx, MemArg),
     I32Const(u32),
     I64Const(u64),
     #[cfg(feature = "float-types")]
@@ -269,16 +390,84 @@ pub enum Instr<'m> {
     RefNull(RefType),
     RefIsNull,
     RefFunc(FuncIdx),
-    MemoryInit(DataIdx),
+    MemoryInit(MemIdx, DataIdx),
     DataDrop(DataIdx),
-    MemoryCopy,
-    MemoryFill,
+    MemoryCopy(MemIdx, MemIdx),
+    MemoryFill(MemIdx),
     TableInit(TableIdx, ElemIdx), // parsing order differs
     ElemDrop(ElemIdx),
     TableCopy(TableIdx, TableIdx),
     TableGrow(TableIdx),
     TableSize(TableIdx),
     TableFill(TableIdx),
+    #[cfg(feature = "vector-types")]
+    V128Load(MemArg),
+    /// Loads 64 bits and extends them to the lanes of the shape.
+    #[cfg(feature = "vector-types")]
+    V128LoadExtend(Shape, Sx, MemArg),
+    #[cfg(feature = "vector-types")]
+    V128LoadSplat(Shape, MemArg),
+    #[cfg(feature = "vector-types")]
+    V128LoadZero(Nx, MemArg),
+    #[cfg(feature = "vector-types")]
+    V128LoadLane(Shape, MemArg, LaneIdx),
+    #[cfg(feature = "vector-types")]
+    V128Store(MemArg),
+    #[cfg(feature = "vector-types")]
+    V128StoreLane(Shape, MemArg, LaneIdx),
+    #[cfg(feature = "vector-types")]
+    V128Const(u128),
+    #[cfg(feature = "vector-types")]
+    I8x16Shuffle([LaneIdx; 16]),
+    #[cfg(feature = "vector-types")]
+    I8x16Swizzle,
+    #[cfg(feature = "vector-types")]
+    VSplat(Shape),
+    /// The sign extension is only relevant for lanes smaller than 32 bits.
+    #[cfg(feature = "vector-types")]
+    VExtractLane(Shape, Sx, LaneIdx),
+    #[cfg(feature = "vector-types")]
+    VReplaceLane(Shape, LaneIdx),
+    #[cfg(feature = "vector-types")]
+    VIRelOp(Shape, IRelOp),
+    #[cfg(all(feature = "vector-types", feature = "float-types"))]
+    VFRelOp(Shape, FRelOp),
+    #[cfg(feature = "vector-types")]
+    V128Not,
+    #[cfg(feature = "vector-types")]
+    VBinOp(VBinOp),
+    #[cfg(feature = "vector-types")]
+    V128Bitselect,
+    #[cfg(feature = "vector-types")]
+    V128AnyTrue,
+    #[cfg(feature = "vector-types")]
+    VIUnOp(Shape, VIUnOp),
+    #[cfg(all(feature = "vector-types", feature = "float-types"))]
+    VFUnOp(Shape, FUnOp),
+    #[cfg(feature = "vector-types")]
+    VIBinOp(Shape, VIBinOp),
+    #[cfg(all(feature = "vector-types", feature = "float-types"))]
+    VFBinOp(Shape, VFBinOp),
+    #[cfg(feature = "vector-types")]
+    VShiftOp(Shape, VShiftOp),
+    #[cfg(feature = "vector-types")]
+    VAllTrue(Shape),
+    #[cfg(feature = "vector-types")]
+    VBitmask(Shape),
+    /// Narrows 2 vectors of twice smaller lanes into the shape.
+    #[cfg(feature = "vector-types")]
+    VNarrow(Shape, Sx),
+    /// Extends half the lanes of a vector of twice smaller lanes into the shape.
+    #[cfg(feature = "vector-types")]
+    VExtend(Shape, Half, Sx),
+    #[cfg(feature = "vector-types")]
+    VExtMul(Shape, Half, Sx),
+    #[cfg(feature = "vector-types")]
+    VExtAddPairwise(Shape, Sx),
+    #[cfg(feature = "vector-types")]
+    I32x4DotI16x8S,
+    #[cfg(all(feature = "vector-types", feature = "float-types"))]
+    VCvtOp(VCvtOp),
 }
 
 pub type TypeIdx = u32;
@@ -290,6 +479,8 @@ pub type ElemIdx = u32;
 pub type DataIdx = u32;
 pub type LocalIdx = u32;
 pub type LabelIdx = u32;
+#[cfg(feature = "vector-types")]
+pub type LaneIdx = u8;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ExportDesc {
@@ -336,7 +527,10 @@ impl<'m> ExternType<'m> {
                 ExternType::Table(TableType { limits: t, item: x }),
                 ExternType::Table(TableType { limits: s, item: y }),
             ) => t.matches(*s) && x == y,
-            (ExternType::Mem(t), ExternType::Mem(s)) => t.matches(*s),
+            (
+                ExternType::Mem(MemType { limits: t, shared: x }),
+                ExternType::Mem(MemType { limits: s, shared: y }),
+            ) => t.matches(*s) && x == y,
             (ExternType::Global(x), ExternType::Global(y)) => x == y,
             _ => false,
         }
@@ -352,6 +546,7 @@ pub enum BlockType {
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct MemArg {
+    pub memory: MemIdx,
     pub align: u32,
     pub offset: u32,
 }
@@ -779,6 +974,62 @@ impl From<u8> for FBinOp {
     }
 }
 
+#[cfg(feature = "vector-types")]
+impl From<u8> for Half {
+    fn from(x: u8) -> Self {
+        match x {
+            0 => Half::Low,
+            1 => Half::High,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "threads")]
+impl From<u8> for RmwOp {
+    fn from(x: u8) -> Self {
+        match x {
+            0 => RmwOp::Add,
+            1 => RmwOp::Sub,
+            2 => RmwOp::And,
+            3 => RmwOp::Or,
+            4 => RmwOp::Xor,
+            5 => RmwOp::Xchg,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "vector-types")]
+impl From<u8> for VBinOp {
+    fn from(x: u8) -> Self {
+        match x {
+            0 => VBinOp::And,
+            1 => VBinOp::AndNot,
+            2 => VBinOp::Or,
+            3 => VBinOp::Xor,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(all(feature = "vector-types", feature = "float-types"))]
+impl From<u8> for VFBinOp {
+    fn from(x: u8) -> Self {
+        match x {
+            0 => VFBinOp::Add,
+            1 => VFBinOp::Sub,
+            2 => VFBinOp::Mul,
+            3 => VFBinOp::Div,
+            4 => VFBinOp::Min,
+            5 => VFBinOp::Max,
+            6 => VFBinOp::PMin,
+            7 => VFBinOp::PMax,
+            _ => unreachable!(),
+        }
+    }
+}
+
 impl SectionId {
     pub fn order(self) -> u8 {
         // DataCount is actually between Element and Code.
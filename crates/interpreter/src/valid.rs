@@ -18,13 +18,30 @@ use alloc::vec::Vec;
 use core::cmp::Ordering;
 
 use crate::error::*;
+use crate::parser::Binary;
+use crate::side_table::*;
 use crate::syntax::*;
 use crate::toctou::*;
 use crate::*;
 
 /// Checks whether a WASM module in binary format is valid.
 pub fn validate(binary: &[u8]) -> Result<(), Error> {
-    Context::default().check_module(&mut Parser::new(binary))
+    side_tables(binary.into()).map(|_| ())
+}
+
+/// Checks whether a WASM module in binary format is valid and returns its side tables.
+pub(crate) fn side_tables(binary: Binary) -> Result<Vec<SideTable>, Error> {
+    let mut context = Context::default();
+    context.check_module(&mut Parser::new(binary))?;
+    Ok(context.side_tables)
+}
+
+fn check_valtype(t: ValType) -> Result<(), Error> {
+    match t {
+        ValType::F32 | ValType::F64 if !cfg!(feature = "float-types") => Err(unsupported()),
+        ValType::V128 if !cfg!(feature = "vector-types") => Err(unsupported()),
+        _ => Ok(()),
+    }
 }
 
 type Parser<'m> = parser::Parser<'m, Check>;
@@ -39,11 +56,12 @@ struct Context<'m> {
     globals: Vec<GlobalType>,
     elems: Vec<RefType>,
     datas: Option<usize>,
+    side_tables: Vec<SideTable>,
 }
 
 impl<'m> Context<'m> {
     fn check_module(&mut self, parser: &mut Parser<'m>) -> CheckResult {
-        check(parser.parse_bytes(8)? == b"\0asm\x01\0\0\0")?;
+        check(&parser.parse_array::<8>()? == b"\0asm\x01\0\0\0")?;
         if let Some(mut parser) = self.check_section(parser, SectionId::Type)? {
             let n = parser.parse_vec()?;
             self.types.reserve(n);
@@ -78,7 +96,6 @@ impl<'m> Context<'m> {
             }
             check(parser.is_empty())?;
         }
-        check(self.mems.len() <= 1)?;
         let globals_len = self.globals.len();
         if let Some(mut parser) = self.check_section(parser, SectionId::Global)? {
             for _ in 0 .. parser.parse_vec()? {
@@ -130,7 +147,10 @@ impl<'m> Context<'m> {
                 let t = self.functype(x as FuncIdx).unwrap();
                 let mut locals = t.params.to_vec();
                 parser.parse_locals(&mut locals)?;
-                Expr::check_body(self, &mut parser, &refs, locals, t.results)?;
+                // Locals are zero-initialized, which needs their type to be supported.
+                locals[t.params.len() ..].iter().try_for_each(|&x| check_valtype(x))?;
+                let side_table = Expr::check_body(self, &mut parser, &refs, locals, t.results)?;
+                self.side_tables.push(side_table);
                 check(parser.is_empty())?;
             }
             check(parser.is_empty())?;
@@ -200,7 +220,7 @@ impl<'m> Context<'m> {
     }
 
     fn add_memtype(&mut self, m: MemType) -> CheckResult {
-        check(m.valid(MEM_MAX))?;
+        check(m.limits.valid(MEM_MAX))?;
         self.mems.push(m);
         Ok(())
     }
@@ -401,6 +421,7 @@ struct Expr<'a, 'm> {
     is_body: bool,
     locals: Vec<ValType>,
     labels: Vec<Label<'m>>,
+    side_table: SideTableBuilder,
 }
 
 #[derive(Debug, Default)]
@@ -434,6 +455,7 @@ impl<'a, 'm> Expr<'a, 'm> {
             is_body: false,
             locals: vec![],
             labels: vec![Label::default()],
+            side_table: SideTableBuilder::default(),
         }
     }
 
@@ -450,15 +472,16 @@ impl<'a, 'm> Expr<'a, 'm> {
     fn check_body(
         context: &'a Context<'m>, parser: &'a mut Parser<'m>, refs: &'a [bool],
         locals: Vec<ValType>, results: ResultType<'m>,
-    ) -> CheckResult {
+    ) -> MResult<SideTable, Check> {
         let mut expr = Expr::new(context, parser, Err(refs));
         expr.is_body = true;
         expr.locals = locals;
         expr.label().type_.results = results;
-        expr.check()
+        expr.check()?;
+        Ok(expr.side_table.finish())
     }
 
-    fn check(mut self) -> CheckResult {
+    fn check(&mut self) -> CheckResult {
         while !self.labels.is_empty() {
             self.instr()?;
         }
@@ -467,7 +490,9 @@ impl<'a, 'm> Expr<'a, 'm> {
 
     fn instr(&mut self) -> CheckResult {
         use Instr::*;
+        let before = self.parser.len();
         let instr = self.parser.parse_instr()?;
+        self.side_table.instr(before, &instr, self.parser.len());
         if matches!(instr, End) {
             return self.end_label();
         }
@@ -480,10 +505,14 @@ impl<'a, 'm> Expr<'a, 'm> {
                 }
                 I32Const(_) => (),
                 I64Const(_) => (),
+                // The extended-const proposal allows some integer arithmetic.
+                IBinOp(_, syntax::IBinOp::Add | syntax::IBinOp::Sub | syntax::IBinOp::Mul) => (),
                 #[cfg(feature = "float-types")]
                 F32Const(_) => (),
                 #[cfg(feature = "float-types")]
                 F64Const(_) => (),
+                #[cfg(feature = "vector-types")]
+                V128Const(_) => (),
                 RefNull(_) => (),
                 RefFunc(_) => (),
                 _ => return Err(invalid()),
@@ -540,6 +569,12 @@ impl<'a, 'm> Expr<'a, 'm> {
                 self.pop_check(ValType::I32)?;
                 self.call(self.context.type_(y)?)?;
             }
+            ReturnCall(x) => self.return_call(self.context.functype(x)?)?,
+            ReturnCallIndirect(x, y) => {
+                check(self.context.table(x)?.item == RefType::FuncRef)?;
+                self.pop_check(ValType::I32)?;
+                self.return_call(self.context.type_(y)?)?;
+            }
             Drop => drop(self.pop()?),
             Select(None) => {
                 self.pop_check(ValType::I32)?;
@@ -576,14 +611,41 @@ impl<'a, 'm> Expr<'a, 'm> {
             #[cfg(feature = "float-types")]
             FStore(n, m) => self.store(NumType::f(n), n.into(), m)?,
             IStore_(b, m) => self.store(NumType::i(b.into()), b.into(), m)?,
-            MemorySize => {
-                check(!self.context.mems.is_empty())?;
+            MemorySize(x) => {
+                self.context.mem(x)?;
                 self.push(OpdType::I32);
             }
-            MemoryGrow => {
-                check(!self.context.mems.is_empty())?;
+            MemoryGrow(x) => {
+                self.context.mem(x)?;
                 self.swap(ValType::I32)?;
             }
+            #[cfg(feature = "threads")]
+            AtomicNotify(m) => {
+                self.atomic(32, m, &[ValType::I32, ValType::I32], Some(ValType::I32))?
+            }
+            #[cfg(feature = "threads")]
+            AtomicWait(n, m) => {
+                let t = NumType::i(n).into();
+                self.atomic(n.into(), m, &[ValType::I32, t, ValType::I64], Some(ValType::I32))?
+            }
+            #[cfg(feature = "threads")]
+            AtomicFence => (),
+            #[cfg(feature = "threads")]
+            AtomicLoad(n, m) => self.atomic_load(NumType::i(n), n.into(), m)?,
+            #[cfg(feature = "threads")]
+            AtomicLoad_(b, m) => self.atomic_load(NumType::i(b.into()), b.into(), m)?,
+            #[cfg(feature = "threads")]
+            AtomicStore(n, m) => self.atomic_store(NumType::i(n), n.into(), m)?,
+            #[cfg(feature = "threads")]
+            AtomicStore_(b, m) => self.atomic_store(NumType::i(b.into()), b.into(), m)?,
+            #[cfg(feature = "threads")]
+            AtomicRmw(_, n, m) => self.atomic_rmw(NumType::i(n), n.into(), m)?,
+            #[cfg(feature = "threads")]
+            AtomicRmw_(_, b, m) => self.atomic_rmw(NumType::i(b.into()), b.into(), m)?,
+            #[cfg(feature = "threads")]
+            AtomicCmpxchg(n, m) => self.atomic_cmpxchg(NumType::i(n), n.into(), m)?,
+            #[cfg(feature = "threads")]
+            AtomicCmpxchg_(b, m) => self.atomic_cmpxchg(NumType::i(b.into()), b.into(), m)?,
             I32Const(_) => self.push(OpdType::I32),
             I64Const(_) => self.push(OpdType::I64),
             #[cfg(feature = "float-types")]
@@ -615,14 +677,19 @@ impl<'a, 'm> Expr<'a, 'm> {
                 }
                 self.push(OpdType::FuncRef);
             }
-            MemoryInit(x) => {
-                check(!self.context.mems.is_empty())?;
-                self.context.data(x)?;
+            MemoryInit(x, y) => {
+                self.context.mem(x)?;
+                self.context.data(y)?;
                 self.pops([ValType::I32; 3][..].into())?;
             }
             DataDrop(x) => self.context.data(x)?,
-            MemoryCopy | MemoryFill => {
-                check(!self.context.mems.is_empty())?;
+            MemoryCopy(x, y) => {
+                self.context.mem(x)?;
+                self.context.mem(y)?;
+                self.pops([ValType::I32; 3][..].into())?;
+            }
+            MemoryFill(x) => {
+                self.context.mem(x)?;
                 self.pops([ValType::I32; 3][..].into())?;
             }
             TableInit(x, y) => {
@@ -647,6 +714,72 @@ impl<'a, 'm> Expr<'a, 'm> {
                 let t = self.context.table(x)?.item;
                 self.pops([ValType::I32, t.into(), ValType::I32][..].into())?;
             }
+            #[cfg(feature = "vector-types")]
+            V128Load(m) => self.vload(128, m)?,
+            #[cfg(feature = "vector-types")]
+            V128LoadExtend(_, _, m) => self.vload(64, m)?,
+            #[cfg(feature = "vector-types")]
+            V128LoadSplat(s, m) => self.vload(s.width() as usize, m)?,
+            #[cfg(feature = "vector-types")]
+            V128LoadZero(n, m) => self.vload(n.into(), m)?,
+            #[cfg(feature = "vector-types")]
+            V128LoadLane(s, m, i) => {
+                check((i as usize) < s.lanes())?;
+                self.pop_check(ValType::V128)?;
+                self.vload(s.width() as usize, m)?;
+            }
+            #[cfg(feature = "vector-types")]
+            V128Store(m) => self.vstore(128, m)?,
+            #[cfg(feature = "vector-types")]
+            V128StoreLane(s, m, i) => {
+                check((i as usize) < s.lanes())?;
+                self.vstore(s.width() as usize, m)?;
+            }
+            #[cfg(feature = "vector-types")]
+            V128Const(_) => self.push(OpdType::V128),
+            #[cfg(feature = "vector-types")]
+            I8x16Shuffle(ls) => {
+                check(ls.iter().all(|&i| i < 32))?;
+                self.vbinop()?;
+            }
+            #[cfg(feature = "vector-types")]
+            VSplat(s) => self.cvtop_v128(s.unpacked())?,
+            #[cfg(feature = "vector-types")]
+            VExtractLane(s, _, i) => {
+                check((i as usize) < s.lanes())?;
+                self.pop_check(ValType::V128)?;
+                self.push(s.unpacked().into());
+            }
+            #[cfg(feature = "vector-types")]
+            VReplaceLane(s, i) => {
+                check((i as usize) < s.lanes())?;
+                self.pop_check(s.unpacked().into())?;
+                self.swap(ValType::V128)?;
+            }
+            #[cfg(feature = "vector-types")]
+            I8x16Swizzle | VIRelOp(..) | VBinOp(_) | VIBinOp(..) | VNarrow(..) | VExtMul(..)
+            | I32x4DotI16x8S => self.vbinop()?,
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VFRelOp(..) | VFBinOp(..) => self.vbinop()?,
+            #[cfg(feature = "vector-types")]
+            V128Not | VIUnOp(..) | VExtend(..) | VExtAddPairwise(..) => self.swap(ValType::V128)?,
+            #[cfg(all(feature = "vector-types", feature = "float-types"))]
+            VFUnOp(..) | VCvtOp(_) => self.swap(ValType::V128)?,
+            #[cfg(feature = "vector-types")]
+            V128Bitselect => {
+                self.pops([ValType::V128; 3][..].into())?;
+                self.push(OpdType::V128);
+            }
+            #[cfg(feature = "vector-types")]
+            V128AnyTrue | VAllTrue(_) | VBitmask(_) => {
+                self.pop_check(ValType::V128)?;
+                self.push(OpdType::I32);
+            }
+            #[cfg(feature = "vector-types")]
+            VShiftOp(..) => {
+                self.pop_check(ValType::I32)?;
+                self.swap(ValType::V128)?;
+            }
         }
         Ok(())
     }
@@ -772,6 +905,14 @@ impl<'a, 'm> Expr<'a, 'm> {
         Ok(())
     }
 
+    fn return_call(&mut self, t: FuncType) -> CheckResult {
+        check(self.is_body)?;
+        check(t.results == self.labels[0].type_.results)?;
+        self.pops(t.params)?;
+        self.stack_polymorphic();
+        Ok(())
+    }
+
     fn stack_polymorphic(&mut self) {
         let label = self.label();
         label.stack.clear();
@@ -779,7 +920,7 @@ impl<'a, 'm> Expr<'a, 'm> {
     }
 
     fn load(&mut self, t: NumType, n: usize, m: MemArg) -> CheckResult {
-        check(!self.context.mems.is_empty())?;
+        self.context.mem(m.memory)?;
         check(1 << m.align <= n / 8)?;
         self.pop_check(ValType::I32)?;
         self.push(t.into());
@@ -787,13 +928,76 @@ impl<'a, 'm> Expr<'a, 'm> {
     }
 
     fn store(&mut self, t: NumType, n: usize, m: MemArg) -> CheckResult {
-        check(!self.context.mems.is_empty())?;
+        self.context.mem(m.memory)?;
         check(1 << m.align <= n / 8)?;
         self.pop_check(t.into())?;
         self.pop_check(ValType::I32)?;
         Ok(())
     }
 
+    #[cfg(feature = "threads")]
+    fn atomic(
+        &mut self, n: usize, m: MemArg, params: &[ValType], result: Option<ValType>,
+    ) -> CheckResult {
+        self.context.mem(m.memory)?;
+        // Atomic accesses must be naturally aligned.
+        check(1 << m.align == n / 8)?;
+        self.pops(params.into())?;
+        if let Some(t) = result {
+            self.push(t.into());
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_load(&mut self, t: NumType, n: usize, m: MemArg) -> CheckResult {
+        self.atomic(n, m, &[ValType::I32], Some(t.into()))
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_store(&mut self, t: NumType, n: usize, m: MemArg) -> CheckResult {
+        self.atomic(n, m, &[ValType::I32, t.into()], None)
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_rmw(&mut self, t: NumType, n: usize, m: MemArg) -> CheckResult {
+        self.atomic(n, m, &[ValType::I32, t.into()], Some(t.into()))
+    }
+
+    #[cfg(feature = "threads")]
+    fn atomic_cmpxchg(&mut self, t: NumType, n: usize, m: MemArg) -> CheckResult {
+        self.atomic(n, m, &[ValType::I32, t.into(), t.into()], Some(t.into()))
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn vload(&mut self, n: usize, m: MemArg) -> CheckResult {
+        self.context.mem(m.memory)?;
+        check(1 << m.align <= n / 8)?;
+        self.pop_check(ValType::I32)?;
+        self.push(OpdType::V128);
+        Ok(())
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn vstore(&mut self, n: usize, m: MemArg) -> CheckResult {
+        self.context.mem(m.memory)?;
+        check(1 << m.align <= n / 8)?;
+        self.pops([ValType::I32, ValType::V128][..].into())
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn vbinop(&mut self) -> CheckResult {
+        self.pop_check(ValType::V128)?;
+        self.swap(ValType::V128)
+    }
+
+    #[cfg(feature = "vector-types")]
+    fn cvtop_v128(&mut self, src: NumType) -> CheckResult {
+        self.pop_check(src.into())?;
+        self.push(OpdType::V128);
+        Ok(())
+    }
+
     fn testop(&mut self, t: NumType) -> CheckResult {
         self.pop_check(t.into())?;
         self.push(OpdType::I32);
@@ -23,5 +23,13 @@ RUSTFLAGS=--cfg=portable_atomic_unsafe_assume_single_core \
   cargo check --target=riscv32imc-unknown-none-elf
 cargo fmt -- --check
 cargo clippy -- --deny=warnings
-[ -e ../../third_party/WebAssembly/spec/.git ] || git submodule update --init
-cargo test --features=debug,toctou,float-types,vector-types
+cargo clippy --features=untagged -- --deny=warnings
+cargo clippy --features=profile -- --deny=warnings
+cargo clippy --features=threads -- --deny=warnings
+for x in spec multi-memory tail-call threads extended-const; do
+  [ -e ../../third_party/WebAssembly/$x/.git ] \
+    || git submodule update --init ../../third_party/WebAssembly/$x
+done
+cargo test --features=debug,toctou,float-types,vector-types,threads
+cargo test --features=debug,toctou,float-types,vector-types,threads,untagged
+cargo test --lib --features=profile
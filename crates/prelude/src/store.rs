@@ -23,7 +23,7 @@ pub use self::api::Error;
 
 /// Inserts an entry in the store.
 ///
-/// The `key` argument must be a small integer (currently less than 4096). The `value` argument is
+/// The `key` argument must be a small integer (currently less than 256). The `value` argument is
 /// the slice to associate with this key. If there was already a value, it is overwritten.
 /// Overwritten values are zeroized from flash.
 pub fn insert(key: usize, value: &[u8]) -> Result<(), Error> {
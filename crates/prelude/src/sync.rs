@@ -14,38 +14,53 @@
 
 //! Provides API for mutexes and atomics.
 //!
-//! This module is currently only correct when compiled without the atomics proposal.
-#![cfg(not(target_feature = "atomics"))]
+//! Atomics use the atomic instructions of the threads proposal when compiled with the `atomics`
+//! target feature. Mutexes then spin while locked by another thread. Otherwise, acquiring a locked
+//! mutex panics since it can only be a recursive acquisition.
 
-use core::cell::{Cell, RefCell, RefMut};
+use core::cell::{RefCell, RefMut};
 
 pub use portable_atomic::*;
 
 pub struct Mutex<T: ?Sized> {
-    locked: Cell<bool>,
+    locked: AtomicBool,
     data: RefCell<T>,
 }
 
 pub struct MutexGuard<'a, T: ?Sized + 'a> {
-    locked: &'a Cell<bool>,
+    locked: &'a AtomicBool,
     data: RefMut<'a, T>,
 }
 
-// SAFETY: This is a single-threaded environment. The bound on Send comes from std::sync::Mutex, not
-// sure if relevant or needed, but it's safer to have it.
+// SAFETY: The data is only accessed while the lock is held. The bound on Send comes from
+// std::sync::Mutex, not sure if relevant or needed, but it's safer to have it.
 unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
 
 impl<T> Mutex<T> {
     pub const fn new(value: T) -> Self {
-        Self { locked: Cell::new(false), data: RefCell::new(value) }
+        Self { locked: AtomicBool::new(false), data: RefCell::new(value) }
     }
 
+    #[cfg(not(target_feature = "atomics"))]
     pub fn lock(&self) -> MutexGuard<T> {
+        // Without threads, the lock can only be held by the caller.
         self.try_lock().expect("cannot recursively acquire mutex")
     }
 
+    #[cfg(target_feature = "atomics")]
+    pub fn lock(&self) -> MutexGuard<T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            while self.locked.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
     pub fn try_lock(&self) -> Option<MutexGuard<T>> {
-        if self.locked.replace(true) {
+        if self.locked.swap(true, Ordering::Acquire) {
             return None;
         }
         Some(MutexGuard { locked: &self.locked, data: self.data.borrow_mut() })
@@ -68,6 +83,6 @@ impl<T: ?Sized> core::ops::DerefMut for MutexGuard<'_, T> {
 
 impl<T: ?Sized> Drop for MutexGuard<'_, T> {
     fn drop(&mut self) {
-        self.locked.set(false);
+        self.locked.store(false, Ordering::Release);
     }
 }
@@ -26,7 +26,7 @@ use wasefire_board_api::{Api, Event, Unsupported};
 use wasefire_store::FileStorage;
 
 use self::timer::Timers;
-use crate::RECEIVER;
+use crate::{with_state, RECEIVER};
 
 pub struct State {
     pub sender: Sender<Event<Board>>,
@@ -36,6 +36,7 @@ pub struct State {
     #[cfg(feature = "usb")]
     pub usb: usb::Usb,
     pub storage: Option<FileStorage>,
+    pub applet_key: Option<[u8; 64]>,
 }
 
 pub enum Board {}
@@ -49,6 +50,10 @@ impl Api for Board {
         RECEIVER.lock().unwrap().as_mut().unwrap().blocking_recv().unwrap()
     }
 
+    fn applet_key() -> Option<[u8; 64]> {
+        with_state(|state| state.applet_key)
+    }
+
     type Button = button::Impl;
     type Crypto = Unsupported;
     type Debug = debug::Impl;
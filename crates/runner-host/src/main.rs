@@ -24,7 +24,7 @@ use board::Board;
 use tokio::runtime::Handle;
 use tokio::sync::mpsc::{channel, Receiver};
 use wasefire_board_api::Event;
-use wasefire_scheduler::Scheduler;
+use wasefire_scheduler::{Config, Scheduler};
 use wasefire_store::{FileOptions, FileStorage};
 
 use crate::board::timer::Timers;
@@ -45,6 +45,15 @@ async fn main() -> Result<()> {
     const STORAGE: &str = "../../target/storage.bin";
     let options = FileOptions { word_size: 4, page_size: 4096, num_pages: 16 };
     let storage = Some(FileStorage::new(Path::new(STORAGE), options).unwrap());
+    // Applets must be signed when a public key is given (hex-encoded x and y coordinates).
+    let applet_key =
+        std::env::var("WASEFIRE_APPLET_KEY").ok().map(|x| parse_key(&x)).transpose()?;
+    // Applets are refused without key, unless unsigned applets are explicitly allowed.
+    let allow_unsigned = cfg!(feature = "unsigned");
+    anyhow::ensure!(
+        applet_key.is_some() || allow_unsigned,
+        "WASEFIRE_APPLET_KEY must be set without the unsigned feature"
+    );
     let (sender, receiver) = channel(10);
     *RECEIVER.lock().unwrap() = Some(receiver);
     *STATE.lock().unwrap() = Some(board::State {
@@ -55,6 +64,7 @@ async fn main() -> Result<()> {
         #[cfg(feature = "usb")]
         usb: board::usb::Usb::default(),
         storage,
+        applet_key,
     });
     #[cfg(feature = "usb")]
     board::usb::Usb::init();
@@ -76,5 +86,17 @@ async fn main() -> Result<()> {
     });
     println!("Running.");
     const WASM: &[u8] = include_bytes!("../../../target/applet.wasm");
-    Handle::current().spawn_blocking(|| Scheduler::<board::Board>::run(WASM)).await?
+    let config = Config { allow_unsigned, ..Config::default() };
+    Handle::current()
+        .spawn_blocking(move || Scheduler::<board::Board>::run_with(config, &[WASM]))
+        .await?
+}
+
+fn parse_key(hex: &str) -> Result<[u8; 64]> {
+    anyhow::ensure!(hex.len() == 128 && hex.is_ascii(), "applet key must be 64 hex-encoded bytes");
+    let mut key = [0; 64];
+    for (i, x) in key.iter_mut().enumerate() {
+        *x = u8::from_str_radix(&hex[2 * i .. 2 * i + 2], 16)?;
+    }
+    Ok(key)
 }
@@ -21,6 +21,7 @@ if [ ! -e ../../target/applet.wasm ]; then
 fi
 cargo check --features=debug
 cargo check --features=release
+cargo check --features=release,unsigned
 cargo check --no-default-features --features=debug
 cargo fmt -- --check
 cargo clippy --features=debug -- --deny=warnings
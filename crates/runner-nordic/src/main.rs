@@ -68,7 +68,7 @@ use usb_device::device::{UsbDevice, UsbDeviceBuilder, UsbVidPid};
 use usbd_serial::{SerialPort, USB_CLASS_CDC};
 use wasefire_board_api::usb::serial::Serial;
 use wasefire_board_api::{Id, Support};
-use wasefire_scheduler::Scheduler;
+use wasefire_scheduler::{Config, Scheduler};
 use {wasefire_board_api as board, wasefire_logger as logger};
 
 #[cfg(feature = "debug")]
@@ -185,7 +185,43 @@ fn main() -> ! {
     }
     logger::debug!("Runner is initialized.");
     const WASM: &[u8] = include_bytes!("../../../target/applet.wasm");
-    Scheduler::<Board>::run(WASM)
+    let config = Config { allow_unsigned: cfg!(feature = "unsigned"), ..Config::default() };
+    Scheduler::<Board>::run_with(config, &[WASM])
+}
+
+/// Public key verifying applets, if any.
+///
+/// It is read at compile time from the `WASEFIRE_APPLET_KEY` environment variable (hex-encoded x
+/// and y coordinates).
+const APPLET_KEY: Option<[u8; 64]> = match option_env!("WASEFIRE_APPLET_KEY") {
+    Some(x) => Some(parse_key(x)),
+    None => None,
+};
+
+// Applets are refused without key, unless unsigned applets are explicitly allowed.
+#[cfg(not(feature = "unsigned"))]
+const _: () =
+    assert!(APPLET_KEY.is_some(), "WASEFIRE_APPLET_KEY must be set without the unsigned feature");
+
+const fn parse_key(hex: &str) -> [u8; 64] {
+    let hex = hex.as_bytes();
+    assert!(hex.len() == 128, "applet key must be 64 hex-encoded bytes");
+    let mut key = [0; 64];
+    let mut i = 0;
+    while i < 64 {
+        key[i] = parse_digit(hex[2 * i]) << 4 | parse_digit(hex[2 * i + 1]);
+        i += 1;
+    }
+    key
+}
+
+const fn parse_digit(x: u8) -> u8 {
+    match x {
+        b'0' ..= b'9' => x - b'0',
+        b'a' ..= b'f' => x - b'a' + 10,
+        b'A' ..= b'F' => x - b'A' + 10,
+        _ => panic!("applet key must be 64 hex-encoded bytes"),
+    }
 }
 
 pub struct RadioMetadata {
@@ -40,6 +40,10 @@ impl board::Api for Board {
         }
     }
 
+    fn applet_key() -> Option<[u8; 64]> {
+        crate::APPLET_KEY
+    }
+
     type Button = button::Impl;
     type Crypto = crypto::Impl;
     type Debug = debug::Impl;
@@ -19,12 +19,14 @@ if [ ! -e ../../target/applet.wasm ]; then
   mkdir -p ../../target
   touch ../../target/applet.wasm
 fi
-cargo check --target=thumbv7em-none-eabi --features=debug,nrf52840
-cargo check --target=thumbv7em-none-eabi --features=debug,nrf52833
-DEFMT_LOG=trace cargo check --target=thumbv7em-none-eabi --features=debug,nrf52840
-cargo check --target=thumbv7em-none-eabi --features=debug,nrf52833
-cargo check --target=thumbv7em-none-eabi --features=release,nrf52840
-cargo check --target=thumbv7em-none-eabi --features=debug,nrf52833
+cargo check --target=thumbv7em-none-eabi --features=debug,nrf52840,unsigned
+cargo check --target=thumbv7em-none-eabi --features=debug,nrf52833,unsigned
+DEFMT_LOG=trace cargo check --target=thumbv7em-none-eabi --features=debug,nrf52840,unsigned
+cargo check --target=thumbv7em-none-eabi --features=debug,nrf52833,unsigned
+cargo check --target=thumbv7em-none-eabi --features=release,nrf52840,unsigned
+cargo check --target=thumbv7em-none-eabi --features=debug,nrf52833,unsigned
+WASEFIRE_APPLET_KEY=abababababababababababababababababababababababababababababababababababababababababababababababababababababababababababababababab \
+  cargo check --target=thumbv7em-none-eabi --features=release,nrf52840
 cargo fmt -- --check
-cargo clippy --target=thumbv7em-none-eabi --features=debug,nrf52840 -- --deny=warnings
-cargo check --target=thumbv7em-none-eabi --features=debug,nrf52833
+cargo clippy --target=thumbv7em-none-eabi --features=debug,nrf52840,unsigned -- --deny=warnings
+cargo check --target=thumbv7em-none-eabi --features=debug,nrf52833,unsigned
@@ -18,6 +18,7 @@ use wasefire_board_api::{self as board, Api as Board, Id, Support};
 
 use crate::event::button::Key;
 use crate::event::Handler;
+use crate::manifest::Resource;
 use crate::{DispatchSchedulerCall, SchedulerCall, Trap};
 
 pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
@@ -38,8 +39,9 @@ fn register<B: Board>(mut call: SchedulerCall<B, api::register::Sig>) {
     let api::register::Params { button, handler_func, handler_data } = call.read();
     let inst = call.inst();
     let results = try {
+        call.scheduler().applet().check(Resource::Button, *button)?;
         let button = Id::new(*button as usize).ok_or(Trap)?;
-        call.scheduler().applet.enable(Handler {
+        call.scheduler().enable_event(Handler {
             key: Key { button }.into(),
             inst,
             func: *handler_func,
@@ -54,9 +56,10 @@ fn register<B: Board>(mut call: SchedulerCall<B, api::register::Sig>) {
 fn unregister<B: Board>(mut call: SchedulerCall<B, api::unregister::Sig>) {
     let api::unregister::Params { button } = call.read();
     let results = try {
+        call.scheduler().applet().check(Resource::Button, *button)?;
         let button = Id::new(*button as usize).ok_or(Trap)?;
-        board::Button::<B>::disable(button).map_err(|_| Trap)?;
         call.scheduler().disable_event(Key { button }.into())?;
+        board::Button::<B>::disable(button).map_err(|_| Trap)?;
         api::unregister::Results {}
     };
     call.reply(results);
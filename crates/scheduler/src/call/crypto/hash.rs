@@ -50,13 +50,13 @@ fn is_supported<B: Board>(call: SchedulerCall<B, api::is_supported::Sig>) {
 
 fn initialize<B: Board>(mut call: SchedulerCall<B, api::initialize::Sig>) {
     let api::initialize::Params { algorithm } = call.read();
-    let scheduler = call.scheduler();
+    let applet = call.scheduler().applet();
     let results = try {
         let context = match Algorithm::try_from(*algorithm).map_err(|_| Trap)? {
             Algorithm::Sha256 => HashContext::Sha256(board::crypto::Sha256::<B>::default()),
             Algorithm::Sha384 => HashContext::Sha384(board::crypto::Sha384::<B>::default()),
         };
-        let id = scheduler.applet.hashes.insert(context)? as u32;
+        let id = applet.hashes.insert(context)? as u32;
         api::initialize::Results { id: id.into() }
     };
     call.reply(results);
@@ -64,11 +64,11 @@ fn initialize<B: Board>(mut call: SchedulerCall<B, api::initialize::Sig>) {
 
 fn update<B: Board>(mut call: SchedulerCall<B, api::update::Sig>) {
     let api::update::Params { id, data, length } = call.read();
-    let scheduler = call.scheduler();
-    let memory = scheduler.applet.store.memory();
+    let applet = call.scheduler().applet();
+    let memory = applet.store.memory();
     let results = try {
         let data = memory.get(*data, *length)?;
-        match scheduler.applet.hashes.get_mut(*id as usize)? {
+        match applet.hashes.get_mut(*id as usize)? {
             HashContext::Sha256(context) => context.update(data),
             HashContext::Sha384(context) => context.update(data),
             _ => Err(Trap)?,
@@ -80,10 +80,10 @@ fn update<B: Board>(mut call: SchedulerCall<B, api::update::Sig>) {
 
 fn finalize<B: Board>(mut call: SchedulerCall<B, api::finalize::Sig>) {
     let api::finalize::Params { id, digest } = call.read();
-    let scheduler = call.scheduler();
-    let memory = scheduler.applet.store.memory();
+    let applet = call.scheduler().applet();
+    let memory = applet.store.memory();
     let results = try {
-        let context = scheduler.applet.hashes.take(*id as usize)?;
+        let context = applet.hashes.take(*id as usize)?;
         match context {
             _ if *digest == 0 => (),
             HashContext::Sha256(context) => {
@@ -115,8 +115,8 @@ fn is_hmac_supported<B: Board>(call: SchedulerCall<B, api::is_hmac_supported::Si
 
 fn hmac_initialize<B: Board>(mut call: SchedulerCall<B, api::hmac_initialize::Sig>) {
     let api::hmac_initialize::Params { algorithm, key, key_len } = call.read();
-    let scheduler = call.scheduler();
-    let memory = scheduler.applet.store.memory();
+    let applet = call.scheduler().applet();
+    let memory = applet.store.memory();
     let results = try {
         let key = memory.get(*key, *key_len)?;
         let context = match Algorithm::try_from(*algorithm).map_err(|_| Trap)? {
@@ -127,7 +127,7 @@ fn hmac_initialize<B: Board>(mut call: SchedulerCall<B, api::hmac_initialize::Si
                 board::crypto::HmacSha384::<B>::new_from_slice(key).map_err(|_| Trap)?,
             ),
         };
-        let id = scheduler.applet.hashes.insert(context)? as u32;
+        let id = applet.hashes.insert(context)? as u32;
         api::hmac_initialize::Results { id: id.into() }
     };
     call.reply(results);
@@ -135,11 +135,11 @@ fn hmac_initialize<B: Board>(mut call: SchedulerCall<B, api::hmac_initialize::Si
 
 fn hmac_update<B: Board>(mut call: SchedulerCall<B, api::hmac_update::Sig>) {
     let api::hmac_update::Params { id, data, length } = call.read();
-    let scheduler = call.scheduler();
-    let memory = scheduler.applet.store.memory();
+    let applet = call.scheduler().applet();
+    let memory = applet.store.memory();
     let results = try {
         let data = memory.get(*data, *length)?;
-        match scheduler.applet.hashes.get_mut(*id as usize)? {
+        match applet.hashes.get_mut(*id as usize)? {
             HashContext::HmacSha256(context) => context.update(data),
             HashContext::HmacSha384(context) => context.update(data),
             _ => Err(Trap)?,
@@ -151,10 +151,10 @@ fn hmac_update<B: Board>(mut call: SchedulerCall<B, api::hmac_update::Sig>) {
 
 fn hmac_finalize<B: Board>(mut call: SchedulerCall<B, api::hmac_finalize::Sig>) {
     let api::hmac_finalize::Params { id, hmac } = call.read();
-    let scheduler = call.scheduler();
-    let memory = scheduler.applet.store.memory();
+    let applet = call.scheduler().applet();
+    let memory = applet.store.memory();
     let results = try {
-        let context = scheduler.applet.hashes.take(*id as usize)?;
+        let context = applet.hashes.take(*id as usize)?;
         match context {
             _ if *hmac == 0 => (),
             HashContext::HmacSha256(context) => {
@@ -187,8 +187,8 @@ fn is_hkdf_supported<B: Board>(call: SchedulerCall<B, api::is_hkdf_supported::Si
 fn hkdf_expand<B: Board>(mut call: SchedulerCall<B, api::hkdf_expand::Sig>) {
     let api::hkdf_expand::Params { algorithm, prk, prk_len, info, info_len, okm, okm_len } =
         call.read();
-    let scheduler = call.scheduler();
-    let memory = scheduler.applet.store.memory();
+    let applet = call.scheduler().applet();
+    let memory = applet.store.memory();
     let results = try {
         let prk = memory.get(*prk, *prk_len)?;
         let info = memory.get(*info, *info_len)?;
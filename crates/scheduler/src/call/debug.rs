@@ -38,5 +38,12 @@ fn println<B: Board>(mut call: SchedulerCall<B, api::println::Sig>) {
 
 fn exit<B: Board>(call: SchedulerCall<B, api::exit::Sig>) {
     let api::exit::Params { code } = call.read();
-    board::Debug::<B>::exit(*code == 0);
+    let success = *code == 0;
+    #[cfg(feature = "profile")]
+    {
+        let mut call = call;
+        let inst = call.inst();
+        call.scheduler().dump_profile(inst);
+    }
+    board::Debug::<B>::exit(success);
 }
@@ -16,6 +16,7 @@ use wasefire_applet_api::led::{self as api, Api};
 use wasefire_board_api::led::Api as _;
 use wasefire_board_api::{self as board, Api as Board, Id, Support};
 
+use crate::manifest::Resource;
 use crate::{DispatchSchedulerCall, SchedulerCall, Trap};
 
 pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
@@ -32,9 +33,10 @@ fn count<B: Board>(call: SchedulerCall<B, api::count::Sig>) {
     call.reply(Ok(api::count::Results { cnt: count.into() }));
 }
 
-fn get<B: Board>(call: SchedulerCall<B, api::get::Sig>) {
+fn get<B: Board>(mut call: SchedulerCall<B, api::get::Sig>) {
     let api::get::Params { led } = call.read();
     let results = try {
+        call.scheduler().applet().check(Resource::Led, *led)?;
         let id = Id::new(*led as usize).ok_or(Trap)?;
         let status = match board::Led::<B>::get(id).map_err(|_| Trap)? {
             false => api::Status::Off.into(),
@@ -45,9 +47,10 @@ fn get<B: Board>(call: SchedulerCall<B, api::get::Sig>) {
     call.reply(results);
 }
 
-fn set<B: Board>(call: SchedulerCall<B, api::set::Sig>) {
+fn set<B: Board>(mut call: SchedulerCall<B, api::set::Sig>) {
     let api::set::Params { led, status } = call.read();
     let results = try {
+        call.scheduler().applet().check(Resource::Led, *led)?;
         let id = Id::new(*led as usize).ok_or(Trap)?;
         let on = matches!(api::Status::try_from(*status)?, api::Status::On);
         board::Led::<B>::set(id, on).map_err(|_| Trap)?;
@@ -32,7 +32,7 @@ fn register<B: Board>(mut call: SchedulerCall<B, api::register::Sig>) {
     let api::register::Params { handler_func, handler_data } = call.read();
     let inst = call.inst();
     let results = try {
-        call.scheduler().applet.enable(Handler {
+        call.scheduler().enable_event(Handler {
             key: Key::Received.into(),
             inst,
             func: *handler_func,
@@ -46,8 +46,8 @@ fn register<B: Board>(mut call: SchedulerCall<B, api::register::Sig>) {
 
 fn unregister<B: Board>(mut call: SchedulerCall<B, api::unregister::Sig>) {
     let results = try {
-        board::Radio::<B>::disable().map_err(|_| Trap)?;
         call.scheduler().disable_event(Key::Received.into())?;
+        board::Radio::<B>::disable().map_err(|_| Trap)?;
         api::unregister::Results {}
     };
     call.reply(results);
@@ -56,7 +56,7 @@ fn unregister<B: Board>(mut call: SchedulerCall<B, api::unregister::Sig>) {
 fn read<B: Board>(mut call: SchedulerCall<B, api::read::Sig>) {
     let api::read::Params { ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applet().memory();
     let results = try {
         let output = memory.get_mut(*ptr, *len)?;
         let len = match board::Radio::<B>::read(output) {
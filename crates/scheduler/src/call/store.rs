@@ -16,7 +16,8 @@ use wasefire_applet_api::store::{self as api, Api};
 use wasefire_board_api::Api as Board;
 use wasefire_store::StoreError;
 
-use crate::{DispatchSchedulerCall, SchedulerCall, Trap};
+use crate::manifest::Resource;
+use crate::{namespace, DispatchSchedulerCall, SchedulerCall, Trap};
 
 pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
     match call {
@@ -29,10 +30,17 @@ pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
 fn insert<B: Board>(mut call: SchedulerCall<B, api::insert::Sig>) {
     let api::insert::Params { key, ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
     let results = try {
+        scheduler.applet().check(Resource::StoreKey, *key)?;
+        let namespace = scheduler.applet().namespace;
+        let memory = scheduler.applets[scheduler.current.0].memory();
         let value = memory.get(*ptr, *len)?;
-        let res = match scheduler.store.insert(*key as usize, value) {
+        let store = &mut scheduler.store;
+        let result = namespace::key(namespace, *key).and_then(|key| {
+            namespace::check_quota(store, key, value.len())?;
+            store.insert(key, value)
+        });
+        let res = match result {
             Ok(()) => 0.into(),
             Err(e) => convert(e).into(),
         };
@@ -43,11 +51,17 @@ fn insert<B: Board>(mut call: SchedulerCall<B, api::insert::Sig>) {
 
 fn remove<B: Board>(mut call: SchedulerCall<B, api::remove::Sig>) {
     let api::remove::Params { key } = call.read();
-    let res = match call.scheduler().store.remove(*key as usize) {
-        Ok(()) => 0.into(),
-        Err(e) => convert(e).into(),
+    let scheduler = call.scheduler();
+    let results = try {
+        scheduler.applet().check(Resource::StoreKey, *key)?;
+        let key = namespace::key(scheduler.applet().namespace, *key);
+        let res = match key.and_then(|key| scheduler.store.remove(key)) {
+            Ok(()) => 0.into(),
+            Err(e) => convert(e).into(),
+        };
+        api::remove::Results { res }
     };
-    call.reply(Ok(api::remove::Results { res }));
+    call.reply(results);
 }
 
 fn find<B: Board>(mut call: SchedulerCall<B, api::find::Sig>) {
@@ -56,10 +70,12 @@ fn find<B: Board>(mut call: SchedulerCall<B, api::find::Sig>) {
     #[cfg(not(feature = "multivalue"))]
     let api::find::Params { key, ptr: ptr_ptr, len: len_ptr } = call.read();
     let scheduler = call.scheduler();
-    let mut memory = scheduler.applet.memory();
     let results = try {
+        scheduler.applet().check(Resource::StoreKey, *key)?;
+        let key = namespace::key(scheduler.applet().namespace, *key);
+        let mut memory = scheduler.applets[scheduler.current.0].memory();
         let mut results = api::find::Results::default();
-        match scheduler.store.find(*key as usize) {
+        match key.and_then(|key| scheduler.store.find(key)) {
             Ok(None) => (),
             Ok(Some(value)) => {
                 let len = value.len() as u32;
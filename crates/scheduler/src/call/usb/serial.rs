@@ -33,7 +33,7 @@ pub fn process<B: Board>(call: Api<DispatchSchedulerCall<B>>) {
 fn read<B: Board>(mut call: SchedulerCall<B, api::read::Sig>) {
     let api::read::Params { ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applet().memory();
     let results = try {
         let output = memory.get_mut(*ptr, *len)?;
         let len = match board::usb::Serial::<B>::read(output) {
@@ -48,7 +48,7 @@ fn read<B: Board>(mut call: SchedulerCall<B, api::read::Sig>) {
 fn write<B: Board>(mut call: SchedulerCall<B, api::write::Sig>) {
     let api::write::Params { ptr, len } = call.read();
     let scheduler = call.scheduler();
-    let memory = scheduler.applet.memory();
+    let memory = scheduler.applet().memory();
     let results = try {
         let input = memory.get(*ptr, *len)?;
         let len = match board::usb::Serial::<B>::write(input) {
@@ -66,7 +66,7 @@ fn register<B: Board>(mut call: SchedulerCall<B, api::register::Sig>) {
     let scheduler = call.scheduler();
     let results = try {
         let event = convert_event(*event)?;
-        scheduler.applet.enable(Handler {
+        scheduler.enable_event(Handler {
             key: Key::from(&event).into(),
             inst,
             func: *handler_func,
@@ -83,8 +83,8 @@ fn unregister<B: Board>(mut call: SchedulerCall<B, api::unregister::Sig>) {
     let scheduler = call.scheduler();
     let results = try {
         let event = convert_event(*event)?;
-        board::usb::Serial::<B>::disable(&event).map_err(|_| Trap)?;
         scheduler.disable_event(Key::from(&event).into())?;
+        board::usb::Serial::<B>::disable(&event).map_err(|_| Trap)?;
         api::unregister::Results {}
     };
     call.reply(results);
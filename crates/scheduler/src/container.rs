@@ -0,0 +1,330 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signed applet container.
+
+use digest::{FixedOutput, Update};
+use wasefire_board_api::crypto::ecc::Api as _;
+use wasefire_board_api::{self as board, Api as Board, Support};
+
+/// Signed applet.
+///
+/// Signed applets are containers with integers encoded as little-endian u32:
+///
+/// ```text
+/// container := len:u32 wasm:u8^len id:u32 version:u32 r:u8^32 s:u8^32
+/// ```
+///
+/// The signature `(r, s)` is a P-256 ECDSA signature of the SHA-256 digest of everything before
+/// it. The id and version are the metadata of the applet and must match its manifest.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Container {
+    /// Signed part of the container.
+    signed: &'static [u8],
+
+    /// Module of the applet.
+    pub wasm: &'static [u8],
+
+    /// Metadata of the applet.
+    pub metadata: Metadata,
+
+    /// Signature of the signed part.
+    signature: &'static [u8],
+}
+
+/// Metadata of a signed applet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// Identifier of the applet.
+    pub id: u32,
+
+    /// Version of the applet.
+    pub version: u32,
+}
+
+/// Reasons for a container to be refused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The container is not well-formed.
+    Malformed,
+
+    /// The board doesn't support P-256 or SHA-256.
+    Unsupported,
+
+    /// The signature doesn't verify.
+    InvalidSignature,
+}
+
+/// Verifies a signed applet with a public key (see [`Board::applet_key()`]).
+pub fn verify<B: Board>(key: &[u8; 64], container: &'static [u8]) -> Result<Container, Error> {
+    if !board::crypto::P256::<B>::SUPPORT || !board::crypto::Sha256::<B>::SUPPORT {
+        return Err(Error::Unsupported);
+    }
+    let container = parse(container)?;
+    let mut digest = board::crypto::Sha256::<B>::default();
+    digest.update(container.signed);
+    let digest = digest.finalize_fixed();
+    let (x, y) = key.split_at(32);
+    let (r, s) = container.signature.split_at(32);
+    match board::crypto::P256::<B>::ecdsa_verify(&digest, x.into(), y.into(), r.into(), s.into()) {
+        Ok(true) => Ok(container),
+        Ok(false) | Err(_) => Err(Error::InvalidSignature),
+    }
+}
+
+/// Parses a signed applet without verifying it.
+fn parse(container: &'static [u8]) -> Result<Container, Error> {
+    let signed_len = container.len().checked_sub(64).ok_or(Error::Malformed)?;
+    let (signed, signature) = container.split_at(signed_len);
+    let mut rest = signed;
+    let len = u32(&mut rest)? as usize;
+    let wasm = split(&mut rest, len)?;
+    let id = u32(&mut rest)?;
+    let version = u32(&mut rest)?;
+    if !rest.is_empty() {
+        return Err(Error::Malformed);
+    }
+    Ok(Container { signed, wasm, metadata: Metadata { id, version }, signature })
+}
+
+/// Splits the first bytes of a slice.
+fn split(data: &mut &'static [u8], len: usize) -> Result<&'static [u8], Error> {
+    if data.len() < len {
+        return Err(Error::Malformed);
+    }
+    let (result, rest) = data.split_at(len);
+    *data = rest;
+    Ok(result)
+}
+
+/// Splits a little-endian u32.
+fn u32(data: &mut &'static [u8]) -> Result<u32, Error> {
+    Ok(u32::from_le_bytes(split(data, 4)?.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    use crypto_common::{BlockSizeUser, Output, OutputSizeUser, Reset};
+    use digest::{FixedOutputReset, HashMarker};
+    use generic_array::GenericArray;
+    use typenum::{U32, U64};
+    use wasefire_board_api::crypto::ecc::{self, Int};
+    use wasefire_board_api::crypto::{Api as Crypto, UnsupportedCrypto};
+    use wasefire_board_api::{Error as BoardError, Unsupported};
+
+    use super::*;
+
+    /// Board with fake P-256 and SHA-256.
+    enum TestBoard {}
+
+    impl Board for TestBoard {
+        fn try_event() -> Option<board::Event<Self>> {
+            unreachable!()
+        }
+
+        fn wait_event() -> board::Event<Self> {
+            unreachable!()
+        }
+
+        type Button = Unsupported;
+        type Crypto = TestCrypto;
+        type Debug = Unsupported;
+        type Led = Unsupported;
+        type Radio = Unsupported;
+        type Rng = Unsupported;
+        type Storage = Unsupported;
+        type Timer = Unsupported;
+        type Usb = Unsupported;
+    }
+
+    enum TestCrypto {}
+
+    impl Crypto for TestCrypto {
+        type Aes128Ccm = <UnsupportedCrypto<Self> as Crypto>::Aes128Ccm;
+        type Aes256Gcm = <UnsupportedCrypto<Self> as Crypto>::Aes256Gcm;
+        type HmacSha256 = <UnsupportedCrypto<Self> as Crypto>::HmacSha256;
+        type HmacSha384 = <UnsupportedCrypto<Self> as Crypto>::HmacSha384;
+        type P256 = FakeP256;
+        type P384 = <UnsupportedCrypto<Self> as Crypto>::P384;
+        type Sha256 = FakeSha256;
+        type Sha384 = <UnsupportedCrypto<Self> as Crypto>::Sha384;
+    }
+
+    /// Fake ECDSA where the signature of a digest `m` with key `(x, y)` is `(m, x)`.
+    enum FakeP256 {}
+
+    impl Support<bool> for FakeP256 {
+        const SUPPORT: bool = true;
+    }
+
+    impl ecc::Api<U32> for FakeP256 {
+        fn is_valid_scalar(_: &Int<U32>) -> bool {
+            unreachable!()
+        }
+
+        fn is_valid_point(_: &Int<U32>, _: &Int<U32>) -> bool {
+            unreachable!()
+        }
+
+        fn base_point_mul(
+            _: &Int<U32>, _: &mut Int<U32>, _: &mut Int<U32>,
+        ) -> Result<(), BoardError> {
+            unreachable!()
+        }
+
+        fn point_mul(
+            _: &Int<U32>, _: &Int<U32>, _: &Int<U32>, _: &mut Int<U32>, _: &mut Int<U32>,
+        ) -> Result<(), BoardError> {
+            unreachable!()
+        }
+
+        fn ecdsa_sign(
+            _: &Int<U32>, _: &Int<U32>, _: &mut Int<U32>, _: &mut Int<U32>,
+        ) -> Result<(), BoardError> {
+            unreachable!()
+        }
+
+        fn ecdsa_verify(
+            m: &Int<U32>, x: &Int<U32>, _: &Int<U32>, r: &Int<U32>, s: &Int<U32>,
+        ) -> Result<bool, BoardError> {
+            Ok(r == m && s == x)
+        }
+    }
+
+    /// Fake hash folding the input over 32 bytes.
+    #[derive(Default)]
+    struct FakeSha256 {
+        state: [u8; 32],
+        len: usize,
+    }
+
+    impl Support<bool> for FakeSha256 {
+        const SUPPORT: bool = true;
+    }
+
+    impl BlockSizeUser for FakeSha256 {
+        type BlockSize = U64;
+    }
+
+    impl OutputSizeUser for FakeSha256 {
+        type OutputSize = U32;
+    }
+
+    impl HashMarker for FakeSha256 {}
+
+    impl Update for FakeSha256 {
+        fn update(&mut self, data: &[u8]) {
+            for &byte in data {
+                let x = &mut self.state[self.len % 32];
+                *x = x.rotate_left(1) ^ byte;
+                self.len += 1;
+            }
+        }
+    }
+
+    impl FixedOutput for FakeSha256 {
+        fn finalize_into(self, out: &mut Output<Self>) {
+            out.copy_from_slice(&self.state);
+        }
+    }
+
+    impl FixedOutputReset for FakeSha256 {
+        fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+            out.copy_from_slice(&self.state);
+            self.reset();
+        }
+    }
+
+    impl Reset for FakeSha256 {
+        fn reset(&mut self) {
+            *self = Self::default();
+        }
+    }
+
+    const KEY: [u8; 64] = [0x55; 64];
+    const WASM: &[u8] = b"\0asm\x01\0\0\0";
+
+    fn signed(wasm: &[u8], id: u32, version: u32) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(&(wasm.len() as u32).to_le_bytes());
+        result.extend_from_slice(wasm);
+        result.extend_from_slice(&id.to_le_bytes());
+        result.extend_from_slice(&version.to_le_bytes());
+        result
+    }
+
+    fn sign(mut signed: Vec<u8>) -> &'static [u8] {
+        let mut digest = FakeSha256::default();
+        digest.update(&signed);
+        let digest: GenericArray<u8, U32> = digest.finalize_fixed();
+        signed.extend_from_slice(&digest);
+        signed.extend_from_slice(&KEY[.. 32]);
+        Box::leak(signed.into_boxed_slice())
+    }
+
+    #[test]
+    fn verify_ok() {
+        let container = verify::<TestBoard>(&KEY, sign(signed(WASM, 3, 1))).unwrap();
+        assert_eq!(container.wasm, WASM);
+        assert_eq!(container.metadata, Metadata { id: 3, version: 1 });
+    }
+
+    #[test]
+    fn verify_bad_signature() {
+        let container = sign(signed(WASM, 3, 1));
+        let mut key = KEY;
+        key[0] ^= 1;
+        assert_eq!(verify::<TestBoard>(&key, container), Err(Error::InvalidSignature));
+        for i in 0 .. container.len() {
+            let mut tampered = container.to_vec();
+            tampered[i] ^= 1;
+            let tampered = Box::leak(tampered.into_boxed_slice());
+            assert!(verify::<TestBoard>(&KEY, tampered).is_err(), "{i}");
+        }
+    }
+
+    #[test]
+    fn verify_unsupported() {
+        let container = sign(signed(WASM, 3, 1));
+        assert_eq!(verify::<crate::tests::TestBoard>(&KEY, container), Err(Error::Unsupported));
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let container = sign(signed(WASM, 3, 1));
+        assert!(parse(container).is_ok());
+        for len in 0 .. container.len() {
+            assert_eq!(parse(&container[.. len]), Err(Error::Malformed), "{len}");
+        }
+    }
+
+    #[test]
+    fn parse_bad_length() {
+        for len in [0, 7, 9, 12, u32::MAX] {
+            let mut data = signed(WASM, 3, 1);
+            data[.. 4].copy_from_slice(&len.to_le_bytes());
+            assert_eq!(parse(sign(data)), Err(Error::Malformed), "{len}");
+        }
+    }
+
+    #[test]
+    fn parse_trailing_bytes() {
+        let mut data = signed(WASM, 3, 1);
+        data.push(0);
+        assert_eq!(parse(sign(data)), Err(Error::Malformed));
+    }
+}
@@ -67,16 +67,31 @@ impl<B: Board> Borrow<Key<B>> for Handler<B> {
     }
 }
 
+/// Disables an event at the board level.
+///
+/// This is used to release the events of an applet that is stopped or restarted.
+pub fn disable<B: Board>(key: Key<B>) {
+    let result = match key {
+        Key::Button(key) => button::disable(key),
+        Key::Radio(key) => radio::disable::<B>(key),
+        Key::Timer(key) => timer::disable(key),
+        Key::Usb(key) => usb::disable::<B>(key),
+    };
+    if result.is_err() {
+        logger::warn!("Failed to disable {}", logger::Debug2Format(&key));
+    }
+}
+
 pub fn process<B: Board>(scheduler: &mut Scheduler<B>, event: Event<B>) {
-    let Handler { inst, func, data, .. } = match scheduler.applet.get(Key::from(&event)) {
-        Some(x) => x,
+    let (inst, func, data) = match scheduler.applet().get(Key::from(&event)) {
+        Some(&Handler { inst, func, data, .. }) => (inst, func, data),
         None => {
             // This should not happen because we remove pending events when disabling an event.
             logger::error!("Missing handler for event.");
             return;
         }
     };
-    let mut params = vec![*func, *data];
+    let mut params = vec![func, data];
     match event {
         Event::Button(event) => button::process(event, &mut params),
         Event::Radio(_) => radio::process(),
@@ -88,5 +103,5 @@ pub fn process<B: Board>(scheduler: &mut Scheduler<B>, event: Event<B>) {
         1 => "cb1",
         _ => unimplemented!(),
     };
-    scheduler.call(*inst, name, &params);
+    scheduler.call(inst, name, &params);
 }
@@ -15,7 +15,7 @@
 use alloc::vec::Vec;
 
 use derivative::Derivative;
-use wasefire_board_api::button::Event;
+use wasefire_board_api::button::{Api as _, Event};
 use wasefire_board_api::{self as board, Api as Board, Id};
 
 #[derive(Derivative)]
@@ -37,6 +37,10 @@ impl<'a, B: Board> From<&'a Event<B>> for Key<B> {
     }
 }
 
+pub fn disable<B: Board>(key: Key<B>) -> Result<(), board::Error> {
+    board::Button::<B>::disable(key.button)
+}
+
 pub fn process<B: Board>(event: Event<B>, params: &mut Vec<u32>) {
     params.push(event.pressed as u32);
 }
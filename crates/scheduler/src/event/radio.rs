@@ -12,8 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use wasefire_board_api::radio::Event;
-use wasefire_board_api::Api as Board;
+use wasefire_board_api::radio::{Api as _, Event};
+use wasefire_board_api::{self as board, Api as Board};
 
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Key {
@@ -34,4 +34,10 @@ impl<'a> From<&'a Event> for Key {
     }
 }
 
+pub fn disable<B: Board>(key: Key) -> Result<(), board::Error> {
+    match key {
+        Key::Received => board::Radio::<B>::disable(),
+    }
+}
+
 pub fn process() {}
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use derivative::Derivative;
-use wasefire_board_api::timer::Event;
+use wasefire_board_api::timer::{Api as _, Event};
 use wasefire_board_api::{self as board, Api as Board, Id};
 
 #[derive(Derivative)]
@@ -35,4 +35,8 @@ impl<'a, B: Board> From<&'a Event<B>> for Key<B> {
     }
 }
 
+pub fn disable<B: Board>(key: Key<B>) -> Result<(), board::Error> {
+    board::Timer::<B>::disarm(key.timer)
+}
+
 pub fn process() {}
@@ -15,7 +15,7 @@
 pub mod serial;
 
 use wasefire_board_api::usb::Event;
-use wasefire_board_api::Api as Board;
+use wasefire_board_api::{self as board, Api as Board};
 
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Key {
@@ -36,6 +36,12 @@ impl<'a> From<&'a Event> for Key {
     }
 }
 
+pub fn disable<B: Board>(key: Key) -> Result<(), board::Error> {
+    match key {
+        Key::Serial(key) => serial::disable::<B>(key),
+    }
+}
+
 pub fn process(event: Event) {
     match event {
         Event::Serial(_) => serial::process(),
@@ -12,8 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use wasefire_board_api::usb::serial::Event;
-use wasefire_board_api::Api as Board;
+use wasefire_board_api::usb::serial::{Api as _, Event};
+use wasefire_board_api::{self as board, Api as Board};
 
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Key {
@@ -36,4 +36,12 @@ impl<'a> From<&'a Event> for Key {
     }
 }
 
+pub fn disable<B: Board>(key: Key) -> Result<(), board::Error> {
+    let event = match key {
+        Key::Read => Event::Read,
+        Key::Write => Event::Write,
+    };
+    board::usb::Serial::<B>::disable(&event)
+}
+
 pub fn process() {}
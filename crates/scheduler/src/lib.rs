@@ -18,6 +18,8 @@
 
 extern crate alloc;
 
+use alloc::alloc::Layout;
+use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -26,18 +28,25 @@ use core::marker::PhantomData;
 use core::ops::Range;
 
 use derivative::Derivative;
-use event::Key;
+use event::{Handler, Key};
+use manifest::Manifest;
 use stores::{Applet, EventAction};
 use wasefire_applet_api::{self as api, Api, ArrayU32, Dispatch, Id, Signature};
 use wasefire_board_api::{self as board, Api as Board, Singleton, Support};
 use wasefire_interpreter::{
-    self as interpreter, Call, Error, InstId, Module, RunAnswer, RunResult, Store, Val,
+    self as interpreter, AtomicOp, Call, Error, InstId, InstanceLimits, Linker, Module, RunAnswer,
+    RunResult, Store, Val, MEMORY_ALIGN,
 };
 use wasefire_logger::{self as logger, *};
 use wasefire_store as store;
 
+pub use crate::stores::{Fault, TrapRecord};
+
 mod call;
+mod container;
 mod event;
+mod manifest;
+mod namespace;
 mod stores;
 
 #[derive(Derivative)]
@@ -73,13 +82,67 @@ impl<B: Board> Events<B> {
 pub struct Scheduler<B: Board> {
     store: store::Store<B::Storage>,
     host_funcs: Vec<Api<Id>>,
-    applet: Applet<B>,
+    applets: Vec<Applet<B>>,
+    /// The applet currently executing.
+    current: AppletId,
     timers: Vec<Option<Timer>>,
+    config: Config,
+}
+
+/// Configuration of the scheduler.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    /// What to do when an applet traps.
+    pub trap_policy: TrapPolicy,
+
+    /// Called each time an applet traps, with its manifest id and trap record.
+    ///
+    /// This is called before applying the trap policy, such that the platform may persist the
+    /// record before rebooting.
+    pub on_trap: Option<fn(u32, TrapRecord)>,
+
+    /// Whether to run unsigned applets when the board doesn't provide an applet key.
+    ///
+    /// Otherwise, all applets are refused when the board doesn't provide an applet key. This is
+    /// insecure and only meant for development.
+    pub allow_unsigned: bool,
+
+    /// Manifest ids of the applets to uninstall.
+    ///
+    /// The store namespace of those applets is wiped before loading applets. The store namespace
+    /// of other applets is kept, even if they are not loaded (e.g. because they fail to verify).
+    pub uninstall: &'static [u32],
 }
 
+/// What to do when an applet traps.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TrapPolicy {
+    /// Panics the platform (which usually reboots it).
+    #[default]
+    Reboot,
+
+    /// Stops the applet and keeps the platform (and other applets) running.
+    Stop,
+
+    /// Restarts the applet from `init` and `main`.
+    ///
+    /// An applet that keeps trapping is eventually stopped.
+    Restart,
+}
+
+/// Number of instructions an applet executes before another applet may be scheduled.
+const FUEL: usize = 10_000;
+
+/// Number of instructions the `init` function of an applet may execute.
+const INIT_FUEL: usize = 100_000;
+
+/// Identifies an applet by its index in the scheduler.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct AppletId(usize);
+
 #[derive(Clone)]
 struct Timer {
-    // TODO: Add AppletId.
+    applet: AppletId,
 }
 
 impl<B: Board> core::fmt::Debug for Scheduler<B> {
@@ -156,12 +219,15 @@ impl<'a, B: Board, T: Signature> SchedulerCall<'a, B, T> {
                 let answer = self.call().resume(&results).map(|x| x.forget());
                 self.erased.scheduler.process_answer(answer);
             }
-            Err(Trap) => logger::panic!("Applet trapped in host."),
+            Err(Trap) => {
+                let error = self.call().reject();
+                self.erased.scheduler.process_answer(Err(error));
+            }
         }
     }
 
     fn applet(&mut self) -> &mut Applet<B> {
-        &mut self.erased.scheduler.applet
+        self.erased.scheduler.applet()
     }
 
     fn store(&mut self) -> &mut Store<'static> {
@@ -175,87 +241,317 @@ impl<'a, B: Board, T: Signature> SchedulerCall<'a, B, T> {
 
 impl<B: Board> Scheduler<B> {
     pub fn run(wasm: &'static [u8]) -> ! {
-        let mut scheduler = Self::new();
-        debug!("Loading applet.");
-        scheduler.load(wasm);
+        Self::run_with(Config::default(), &[wasm])
+    }
+
+    /// Runs multiple applets side by side with the given configuration.
+    ///
+    /// Applets are scheduled in turn each time they call into the host. When no applet can make
+    /// progress, the scheduler waits for the next board event.
+    pub fn run_with(config: Config, wasms: &[&'static [u8]]) -> ! {
+        assert!(!wasms.is_empty());
+        let mut scheduler = Self::new(config);
+        for &wasm in wasms {
+            scheduler.load(wasm);
+        }
         loop {
             scheduler.flush_events();
+            scheduler.schedule();
             scheduler.process_applet();
         }
     }
 
-    fn new() -> Self {
+    fn new(config: Config) -> Self {
         let mut host_funcs = Vec::new();
         Api::<Id>::iter(&mut host_funcs, |x| x);
         host_funcs.sort_by_key(|x| x.descriptor().name);
         assert!(host_funcs.windows(2).all(|x| x[0].descriptor().name != x[1].descriptor().name));
-        let mut applet = Applet::default();
-        let store = applet.store_mut();
-        for f in &host_funcs {
-            let d = f.descriptor();
-            store.link_func("env", d.name, d.params, d.results).unwrap();
+        let mut store = store::Store::new(board::Storage::<B>::take().unwrap()).ok().unwrap();
+        for &id in config.uninstall {
+            if let Err(e) = namespace::release(&mut store, id) {
+                logger::error!("Failed to uninstall applet {}: {}", id, Debug2Format(&e));
+            }
         }
-        let store = store::Store::new(board::Storage::<B>::take().unwrap()).ok().unwrap();
         let timers = vec![None; board::Timer::<B>::SUPPORT];
-        Self { store, host_funcs, applet, timers }
-    }
-
-    fn load(&mut self, wasm: &'static [u8]) {
-        #[repr(align(16))]
-        struct Memory([u8; 0x10000]);
-        static mut MEMORY: Memory = Memory([0; 0x10000]);
-        let module = Module::new(wasm).unwrap();
-        let store = self.applet.store_mut();
-        // SAFETY: This function is called once in `run()`.
-        let inst = store.instantiate(module, unsafe { &mut MEMORY.0 }).unwrap();
-        match store.invoke(inst, "init", vec![]) {
-            Ok(RunResult::Done(x)) => assert!(x.is_empty()),
-            Ok(RunResult::Host { .. }) => logger::panic!("init called into host"),
+        let applets = Vec::new();
+        Self { store, host_funcs, applets, current: AppletId(0), timers, config }
+    }
+
+    fn load(&mut self, mut wasm: &'static [u8]) {
+        const MEMORY_SIZE: usize = 0x10000;
+        let id = self.applets.len();
+        debug!("Loading applet {}.", id);
+        // Applets are signed containers when the board provides a key, and plain modules otherwise.
+        let metadata = match B::applet_key() {
+            None if self.config.allow_unsigned => None,
+            None => {
+                logger::error!("Applet {} is refused because the board has no applet key.", id);
+                return;
+            }
+            Some(key) => match container::verify::<B>(&key, wasm) {
+                Ok(x) => {
+                    wasm = x.wasm;
+                    Some(x.metadata)
+                }
+                Err(e) => {
+                    logger::error!("Applet {} failed verification: {}", id, Debug2Format(&e));
+                    return;
+                }
+            },
+        };
+        // The module is only validated here. It is trusted when the applet is (re)started.
+        let section = Module::new(wasm).and_then(|x| x.custom_section_data(manifest::SECTION));
+        let manifest = match section {
+            Ok(Some(data)) => match Manifest::parse(data) {
+                Some(x) => x,
+                None => {
+                    logger::error!("Applet {} has an invalid manifest.", id);
+                    return;
+                }
+            },
+            // Applets without manifest keep running as before manifests were introduced. Their id
+            // is the one from their metadata, if any.
+            Ok(None) => {
+                logger::warn!("Applet {} has no manifest and is granted everything.", id);
+                let metadata = metadata.unwrap_or(container::Metadata { id: 0, version: 0 });
+                let imports = self.host_funcs.iter().map(|x| x.descriptor().name).collect();
+                Manifest::fallback(metadata.id, metadata.version, imports)
+            }
+            Err(e) => {
+                logger::error!("Applet {} is invalid: {}", id, Debug2Format(&e));
+                return;
+            }
+        };
+        debug!("Applet {} has id {} and version {}.", id, manifest.id, manifest.version);
+        if metadata.is_some_and(|x| x.id != manifest.id || x.version != manifest.version) {
+            logger::error!("Applet {} has a manifest not matching its metadata.", id);
+            return;
+        }
+        if self.applets.iter().any(|x| x.manifest.id == manifest.id) {
+            logger::error!("Applet {} has the id of another applet.", id);
+            return;
+        }
+        let namespace = match namespace::assign(&mut self.store, manifest.id) {
+            Ok(x) => x,
+            Err(e) => {
+                logger::error!("Applet {} has no store namespace: {}", id, Debug2Format(&e));
+                return;
+            }
+        };
+        // Only the host functions declared in the manifest are linked.
+        let host_funcs: Vec<_> = (self.host_funcs.iter())
+            .filter(|x| manifest.allows_import(x.descriptor().name))
+            .cloned()
+            .collect();
+        let linker = new_linker(&host_funcs);
+        // The scheduler runs forever, so it's fine to leak the applet memory.
+        let layout = Layout::from_size_align(MEMORY_SIZE, MEMORY_ALIGN).unwrap();
+        // SAFETY: The layout has a non-zero size.
+        let memory = unsafe { alloc::alloc::alloc_zeroed(layout) };
+        if memory.is_null() {
+            alloc::alloc::handle_alloc_error(layout);
+        }
+        // SAFETY: The memory is valid and zeroed for its size.
+        let memory = unsafe { core::slice::from_raw_parts_mut(memory, MEMORY_SIZE) };
+        self.current = AppletId(id);
+        self.applets.push(Applet::new(wasm, memory, manifest, namespace, linker, host_funcs));
+        self.start();
+    }
+
+    /// Instantiates the current applet and calls its `init` and `main` functions.
+    fn start(&mut self) {
+        let id = self.current.0;
+        let applet = self.applet();
+        let memory = applet.reset(Store::new(applet.linker));
+        applet.set_stopped(false);
+        // SAFETY: The module was validated when loading the applet.
+        let module = unsafe { Module::new_unchecked(applet.wasm) };
+        let store = applet.store_mut();
+        // The applet memory can't grow beyond the allocated one.
+        let max_pages = (memory.len() / 0x10000) as u32;
+        store.set_instance_limits(InstanceLimits { max_pages, ..Default::default() });
+        let inst = match store.instantiate(module, vec![memory]) {
+            Ok(x) => x,
+            Err(e) => {
+                // This happens in particular when importing host functions outside the manifest.
+                logger::error!("Applet {} failed to instantiate: {}", id, Debug2Format(&e));
+                return self.stop();
+            }
+        };
+        store.set_fuel(Some(INIT_FUEL));
+        match store.invoke(inst, "init", vec![]).map(|x| x.forget()) {
+            Ok(RunAnswer::Done(x)) if x.is_empty() => (),
             Err(Error::NotFound) => (),
-            Err(e) => Err(e).unwrap(),
+            Err(Error::Trap(reason)) => return self.trap(Fault::Trap(reason)),
+            // The applet is stopped regardless of the trap policy, since restarting would fail
+            // the same way.
+            result => {
+                logger::error!("Applet {} failed to initialize: {}", id, Debug2Format(&result));
+                self.record(Fault::Init);
+                return self.stop();
+            }
         }
+        self.applet().store_mut().set_fuel(Some(FUEL));
         self.call(inst, "main", &[]);
     }
 
+    /// Stops the current applet and releases its events, timers, and hash contexts.
+    fn stop(&mut self) {
+        let current = self.current;
+        debug!("Stopping applet {}.", current.0);
+        for key in self.applet().keys() {
+            event::disable(key);
+        }
+        for timer in &mut self.timers {
+            if timer.as_ref().is_some_and(|x| x.applet == current) {
+                *timer = None;
+            }
+        }
+        let applet = self.applet();
+        applet.reset(Store::new(applet.linker));
+        applet.set_stopped(true);
+    }
+
+    /// Records a trap of the current applet and returns its number of traps.
+    fn record(&mut self, reason: Fault) -> usize {
+        let applet = self.applet();
+        applet.traps.count += 1;
+        applet.traps.last = Some(reason);
+        let (id, record) = (applet.manifest.id, applet.traps);
+        if let Some(on_trap) = self.config.on_trap {
+            on_trap(id, record);
+        }
+        record.count
+    }
+
+    /// Records a trap of the current applet and applies the trap policy.
+    fn trap(&mut self, reason: Fault) {
+        const MAX_RESTARTS: usize = 3;
+        let id = self.current.0;
+        let count = self.record(reason);
+        match self.config.trap_policy {
+            TrapPolicy::Reboot => {
+                logger::panic!("Applet {} trapped: {}", id, Debug2Format(&reason))
+            }
+            TrapPolicy::Stop => {
+                logger::error!("Applet {} trapped: {}", id, Debug2Format(&reason));
+                self.stop();
+            }
+            TrapPolicy::Restart if MAX_RESTARTS < count => {
+                logger::error!("Applet {} trapped {} times: {}", id, count, Debug2Format(&reason));
+                self.stop();
+            }
+            TrapPolicy::Restart => {
+                logger::error!("Applet {} trapped: {}", id, Debug2Format(&reason));
+                self.stop();
+                self.start();
+            }
+        }
+    }
+
+    fn applet(&mut self) -> &mut Applet<B> {
+        &mut self.applets[self.current.0]
+    }
+
     fn flush_events(&mut self) {
         while let Some(event) = B::try_event() {
-            self.applet.push(event);
+            self.push_event(event);
+        }
+    }
+
+    /// Routes an event to the applet handling it.
+    fn push_event(&mut self, event: board::Event<B>) {
+        let key = Key::from(&event);
+        match self.applets.iter_mut().find(|x| x.get(key).is_some()) {
+            Some(applet) => applet.push(event),
+            // This can happen after an event is disabled and the event queue of the board is
+            // flushed.
+            None => trace!("Discarding {}", Debug2Format(&event)),
+        }
+    }
+
+    /// Selects the next applet that can make progress.
+    ///
+    /// Applets are selected in a round-robin fashion. If all applets wait for events, this function
+    /// waits for the next board event.
+    fn schedule(&mut self) {
+        loop {
+            let applets = &self.applets;
+            if let Some(id) = next_ready(self.current, applets.len(), |x| applets[x].ready()) {
+                self.current = id;
+                return;
+            }
+            self.push_event(B::wait_event());
         }
     }
 
     /// Returns whether execution should resume.
+    ///
+    /// If the applet has no events to handle, it waits and another applet may be scheduled.
     fn process_event(&mut self) -> bool {
-        let event = loop {
-            match self.applet.pop() {
-                EventAction::Handle(event) => break event,
-                EventAction::Wait => self.applet.push(B::wait_event()),
-                EventAction::Reply => return true,
-            }
+        let event = match self.applet().pop() {
+            EventAction::Handle(event) => event,
+            EventAction::Wait => return false,
+            EventAction::Reply => return true,
         };
         event::process(self, event);
         false
     }
 
     fn process_applet(&mut self) {
-        let call = match self.applet.store_mut().last_call() {
-            Some(x) => x,
+        // The applet yields when it runs out of fuel, such that other applets may be scheduled.
+        self.applet().store_mut().set_fuel(Some(FUEL));
+        if let Some(x) = self.applet().store_mut().last_yield() {
+            let answer = x.resume().map(|x| x.forget());
+            self.process_answer(answer);
+            return;
+        }
+        if let Some(x) = self.applet().store_mut().last_atomic() {
+            // Applets don't share memory and have a single thread: nobody is waiting and nobody
+            // can notify.
+            let result = match x.op() {
+                AtomicOp::Notify { .. } => 0,
+                AtomicOp::Wait { timeout: Some(_), .. } => 2,
+                AtomicOp::Wait { timeout: None, .. } => return self.trap(Fault::WaitForever),
+            };
+            let answer = x.resume(result).map(|x| x.forget());
+            self.process_answer(answer);
+            return;
+        }
+        let (index, args) = match self.applet().store_mut().last_call() {
+            Some(x) => (x.index(), x.args().iter().map(|x| x.unwrap_i32()).collect::<Vec<_>>()),
             None => {
                 self.process_event();
                 return;
             }
         };
-        let api_id = self.host_funcs[call.index()].id();
-        let args = call.args();
+        let api_id = self.applet().host_funcs[index].id();
         debug_assert_eq!(args.len(), api_id.descriptor().params);
-        let args = args.iter().map(|x| x.unwrap_i32()).collect();
         let erased = SchedulerCallT { scheduler: self, args };
         let call = api_id.merge(erased);
         debug!("Calling {}", Debug2Format(&call.id()));
         call::process(call);
     }
 
+    /// Enables an event handler for the current applet.
+    ///
+    /// Each event is routed to a single applet, so the handler must not already exist.
+    fn enable_event(&mut self, handler: Handler<B>) -> Result<(), Trap> {
+        let current = self.current.0;
+        let mut others = self.applets.iter().enumerate().filter(|(i, _)| *i != current);
+        if others.any(|(_, x)| x.get(handler.key).is_some()) {
+            warn!("Tried to handle an event of another applet");
+            return Err(Trap);
+        }
+        self.applet().enable(handler)
+    }
+
+    /// Disables an event handler of the current applet.
+    ///
+    /// This fails if the current applet doesn't handle the event, so it must be called before
+    /// disabling the event at the board level.
     fn disable_event(&mut self, key: Key<B>) -> Result<(), Trap> {
-        self.applet.disable(key)?;
+        self.applet().disable(key)?;
         self.flush_events();
         Ok(())
     }
@@ -263,7 +559,7 @@ impl<B: Board> Scheduler<B> {
     fn call(&mut self, inst: InstId, name: &'static str, args: &[u32]) {
         debug!("Schedule thread {}{:?}.", name, args);
         let args = args.iter().map(|&x| Val::I32(x)).collect();
-        let answer = self.applet.store_mut().invoke(inst, name, args).map(|x| x.forget());
+        let answer = self.applet().store_mut().invoke(inst, name, args).map(|x| x.forget());
         self.process_answer(answer);
     }
 
@@ -272,19 +568,51 @@ impl<B: Board> Scheduler<B> {
             Ok(RunAnswer::Done(x)) => {
                 debug!("Thread is done.");
                 debug_assert!(x.is_empty());
-                self.applet.done();
+                self.applet().done();
+            }
+            Ok(RunAnswer::Host | RunAnswer::Yield | RunAnswer::Breakpoint | RunAnswer::Atomic) => {}
+            Err(Error::Trap(reason)) => {
+                if let Some(dump) = self.applet().store_mut().take_core_dump() {
+                    for (i, frame) in dump.frames.iter().enumerate() {
+                        logger::error!("#{} {}", i, Display2Format(frame));
+                    }
+                    #[cfg(feature = "profile")]
+                    self.dump_profile(dump.frames[0].inst);
+                }
+                self.trap(Fault::Trap(reason));
             }
-            Ok(RunAnswer::Host) => (),
-            Err(Error::Trap) => logger::panic!("Applet trapped in wasm."),
             Err(e) => Err(e).unwrap(),
         }
     }
+
+    /// Prints the execution profile of the applet from the hottest function.
+    #[cfg(feature = "profile")]
+    fn dump_profile(&mut self, inst: InstId) {
+        let mut profile = self.applet().store_mut().profile(inst).unwrap();
+        profile.sort_by_key(|x| core::cmp::Reverse(x.instrs));
+        logger::println!("Profile: instructions, host calls, function");
+        for x in profile.iter().filter(|x| x.instrs > 0) {
+            let name = x.name.unwrap_or("?");
+            logger::println!("{} {} #{} {}", x.instrs, x.host_calls, x.func, name);
+        }
+    }
+}
+
+fn new_linker(host_funcs: &[Api<Id>]) -> &'static Linker<'static> {
+    let mut linker = Linker::default();
+    for f in host_funcs {
+        let d = f.descriptor();
+        linker.link_func("env", d.name, d.params, d.results).unwrap();
+    }
+    // The scheduler runs forever, so it's fine to leak the linker.
+    Box::leak(Box::new(linker))
 }
 
 fn convert_results<T: Signature>(results: T::Results) -> Vec<Val> {
     <T::Results as ArrayU32>::into(&results).iter().map(|&x| Val::I32(x)).collect()
 }
 
+#[derive(Debug)]
 pub struct Trap;
 
 impl From<()> for Trap {
@@ -397,3 +725,223 @@ impl<'a> Memory<'a> {
         }
     }
 }
+
+/// Returns the first applet after the current one, in round-robin order, that is ready.
+fn next_ready(current: AppletId, count: usize, ready: impl Fn(usize) -> bool) -> Option<AppletId> {
+    (1 ..= count).map(|i| (current.0 + i) % count).find(|&x| ready(x)).map(AppletId)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::sync::Mutex;
+
+    use wasefire_board_api::Unsupported;
+    use wasefire_store::{BufferOptions, BufferStorage, StorageIndex, StorageResult};
+
+    use super::*;
+    use crate::interpreter::TrapReason;
+
+    /// Board without any hardware support, except for storage.
+    pub(crate) enum TestBoard {}
+
+    impl Board for TestBoard {
+        fn try_event() -> Option<board::Event<Self>> {
+            None
+        }
+
+        fn wait_event() -> board::Event<Self> {
+            unreachable!()
+        }
+
+        type Button = Unsupported;
+        type Crypto = Unsupported;
+        type Debug = Unsupported;
+        type Led = Unsupported;
+        type Radio = Unsupported;
+        type Rng = Unsupported;
+        type Storage = TestStorage;
+        type Timer = Unsupported;
+        type Usb = Unsupported;
+    }
+
+    /// Storage in memory.
+    pub(crate) struct TestStorage(BufferStorage);
+
+    impl Singleton for TestStorage {
+        fn take() -> Option<Self> {
+            let options = BufferOptions {
+                word_size: 4,
+                page_size: 1024,
+                max_word_writes: 2,
+                max_page_erases: 10000,
+                strict_mode: true,
+            };
+            let storage = vec![0xff; 20 * 1024].into_boxed_slice();
+            Some(TestStorage(BufferStorage::new(storage, options)))
+        }
+    }
+
+    impl store::Storage for TestStorage {
+        fn word_size(&self) -> usize {
+            self.0.word_size()
+        }
+
+        fn page_size(&self) -> usize {
+            self.0.page_size()
+        }
+
+        fn num_pages(&self) -> usize {
+            self.0.num_pages()
+        }
+
+        fn max_word_writes(&self) -> usize {
+            self.0.max_word_writes()
+        }
+
+        fn max_page_erases(&self) -> usize {
+            self.0.max_page_erases()
+        }
+
+        fn read_slice(&self, index: StorageIndex, length: usize) -> StorageResult<Cow<[u8]>> {
+            self.0.read_slice(index, length)
+        }
+
+        fn write_slice(&mut self, index: StorageIndex, value: &[u8]) -> StorageResult<()> {
+            self.0.write_slice(index, value)
+        }
+
+        fn erase_page(&mut self, page: usize) -> StorageResult<()> {
+            self.0.erase_page(page)
+        }
+    }
+
+    /// Trap records reported to [`on_trap()`].
+    static TRAPS: Mutex<Vec<(u32, TrapRecord)>> = Mutex::new(Vec::new());
+
+    fn on_trap(id: u32, record: TrapRecord) {
+        TRAPS.lock().unwrap().push((id, record));
+    }
+
+    /// Returns the trap records reported for an applet.
+    fn traps(id: u32) -> Vec<TrapRecord> {
+        TRAPS.lock().unwrap().iter().filter(|x| x.0 == id).map(|x| x.1).collect()
+    }
+
+    fn scheduler(trap_policy: TrapPolicy) -> Scheduler<TestBoard> {
+        let config = Config { trap_policy, on_trap: Some(on_trap), ..Config::default() };
+        Scheduler::new(Config { allow_unsigned: true, ..config })
+    }
+
+    /// Returns an applet with the given manifest id and module fields.
+    fn applet(id: u32, fields: &str) -> &'static [u8] {
+        let manifest: String = [id, 0, 0, 0]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .map(|x| alloc::format!("\\{x:02x}"))
+            .collect();
+        module(&alloc::format!(r#"(@custom "wasefire-manifest" "{manifest}") {fields}"#))
+    }
+
+    /// Returns a module with the given fields.
+    fn module(fields: &str) -> &'static [u8] {
+        let wat = alloc::format!("(module {fields})");
+        let buffer = wast::parser::ParseBuffer::new(&wat).unwrap();
+        let mut wat = wast::parser::parse::<wast::Wat>(&buffer).unwrap();
+        Box::leak(wat.encode().unwrap().into_boxed_slice())
+    }
+
+    fn trap(count: usize, reason: Fault) -> TrapRecord {
+        TrapRecord { count, last: Some(reason) }
+    }
+
+    #[test]
+    fn next_ready_is_round_robin() {
+        let ready = [true, false, true, false];
+        let next = |current| next_ready(AppletId(current), ready.len(), |x| ready[x]);
+        assert_eq!(next(0), Some(AppletId(2)));
+        assert_eq!(next(1), Some(AppletId(2)));
+        assert_eq!(next(2), Some(AppletId(0)));
+        assert_eq!(next(3), Some(AppletId(0)));
+    }
+
+    #[test]
+    fn next_ready_reschedules_current() {
+        let ready = [false, true, false];
+        assert_eq!(next_ready(AppletId(1), ready.len(), |x| ready[x]), Some(AppletId(1)));
+    }
+
+    #[test]
+    fn next_ready_without_ready_applet() {
+        assert_eq!(next_ready(AppletId(0), 3, |_| false), None);
+    }
+
+    #[test]
+    fn trap_is_recorded() {
+        let mut scheduler = scheduler(TrapPolicy::Stop);
+        scheduler.load(applet(1, r#"(memory 1) (func (export "main") unreachable)"#));
+        assert_eq!(traps(1), [trap(1, Fault::Trap(TrapReason::Unreachable))]);
+        assert!(!scheduler.applets[0].ready());
+    }
+
+    #[test]
+    fn restart_is_bounded() {
+        let mut scheduler = scheduler(TrapPolicy::Restart);
+        scheduler.load(applet(2, r#"(memory 1) (func (export "main") unreachable)"#));
+        let reason = Fault::Trap(TrapReason::Unreachable);
+        assert_eq!(traps(2), [trap(1, reason), trap(2, reason), trap(3, reason), trap(4, reason)]);
+        assert!(!scheduler.applets[0].ready());
+    }
+
+    #[test]
+    fn init_fault_stops_applet() {
+        let mut scheduler = scheduler(TrapPolicy::Restart);
+        let fields = r#"(memory 1) (func (export "init") (loop br 0)) (func (export "main"))"#;
+        scheduler.load(applet(3, fields));
+        assert_eq!(traps(3), [trap(1, Fault::Init)]);
+        assert!(!scheduler.applets[0].ready());
+    }
+
+    #[test]
+    fn wait_forever_traps() {
+        let mut scheduler = scheduler(TrapPolicy::Stop);
+        let fields = r#"(memory 1 1 shared) (func (export "main")
+            (drop (memory.atomic.wait32 (i32.const 0) (i32.const 0) (i64.const -1))))"#;
+        scheduler.load(applet(4, fields));
+        assert!(traps(4).is_empty());
+        scheduler.process_applet();
+        assert_eq!(traps(4), [trap(1, Fault::WaitForever)]);
+        assert!(!scheduler.applets[0].ready());
+    }
+
+    #[test]
+    fn compute_loop_yields() {
+        let mut scheduler = scheduler(TrapPolicy::Stop);
+        scheduler.load(applet(5, r#"(memory 1) (func (export "main") (loop br 0))"#));
+        scheduler.load(applet(6, r#"(memory 1) (func (export "main") unreachable)"#));
+        assert!(scheduler.applets[0].ready());
+        assert!(scheduler.applets[0].store_mut().last_yield().is_some());
+        scheduler.current = AppletId(0);
+        scheduler.process_applet();
+        assert!(scheduler.applets[0].store_mut().last_yield().is_some());
+        assert!(traps(5).is_empty());
+        assert_eq!(traps(6), [trap(1, Fault::Trap(TrapReason::Unreachable))]);
+    }
+
+    #[test]
+    fn missing_manifest_falls_back() {
+        let mut scheduler = scheduler(TrapPolicy::Stop);
+        scheduler.load(module(r#"(memory 1) (func (export "main") unreachable)"#));
+        assert_eq!(scheduler.applets[0].manifest.id, 0);
+        assert!(scheduler.applets[0].manifest.allows_import("si"));
+        assert!(scheduler.applets[0].manifest.allows(manifest::Resource::Led, 0));
+        assert_eq!(traps(0), [trap(1, Fault::Trap(TrapReason::Unreachable))]);
+    }
+
+    #[test]
+    fn unsigned_is_refused_by_default() {
+        let mut scheduler = Scheduler::<TestBoard>::new(Config::default());
+        scheduler.load(applet(7, r#"(memory 1) (func (export "main"))"#));
+        assert!(scheduler.applets.is_empty());
+    }
+}
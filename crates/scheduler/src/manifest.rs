@@ -0,0 +1,226 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Applet manifest.
+//!
+//! The manifest is stored in the `wasefire-manifest` custom section of the applet module. All
+//! integers are encoded as little-endian u32:
+//!
+//! ```text
+//! manifest := id version count:u32 import^count count:u32 range^count
+//! import := len:u32 name:u8^len
+//! range := resource start end
+//! ```
+//!
+//! Imports are host function names (e.g. `si` for `store::insert`). Ranges are half-open and
+//! resources are identified by their [`Resource`] value.
+//!
+//! Applets without manifest get a [fallback](Manifest::fallback) manifest, granting all host
+//! functions and resources, as before manifests were introduced. This fallback is temporary until
+//! the applet tooling embeds a manifest.
+
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Name of the custom section holding the manifest.
+pub const SECTION: &str = "wasefire-manifest";
+
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    /// Identifier of the applet.
+    pub id: u32,
+
+    /// Version of the applet.
+    pub version: u32,
+
+    /// Host functions the applet may import.
+    pub imports: Vec<&'static str>,
+
+    /// Resources the applet may access.
+    pub ranges: Vec<(Resource, Range<u32>)>,
+}
+
+/// Resources indexed by an integer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Resource {
+    StoreKey = 0,
+    Button = 1,
+    Led = 2,
+}
+
+impl Resource {
+    const ALL: [Resource; 3] = [Resource::StoreKey, Resource::Button, Resource::Led];
+
+    fn new(x: u32) -> Option<Self> {
+        Some(match x {
+            0 => Resource::StoreKey,
+            1 => Resource::Button,
+            2 => Resource::Led,
+            _ => return None,
+        })
+    }
+}
+
+impl Manifest {
+    /// Parses a manifest from the content of its custom section.
+    pub fn parse(data: &'static [u8]) -> Option<Self> {
+        let mut parser = Parser(data);
+        let id = parser.u32()?;
+        let version = parser.u32()?;
+        let mut imports = Vec::new();
+        for _ in 0 .. parser.u32()? {
+            let len = parser.u32()? as usize;
+            imports.push(core::str::from_utf8(parser.bytes(len)?).ok()?);
+        }
+        let mut ranges = Vec::new();
+        for _ in 0 .. parser.u32()? {
+            let resource = Resource::new(parser.u32()?)?;
+            ranges.push((resource, parser.u32()? .. parser.u32()?));
+        }
+        parser.0.is_empty().then_some(Manifest { id, version, imports, ranges })
+    }
+
+    /// Returns the manifest of an applet without manifest.
+    ///
+    /// It grants the given host functions and all resources.
+    pub fn fallback(id: u32, version: u32, imports: Vec<&'static str>) -> Self {
+        let ranges = Resource::ALL.iter().map(|&x| (x, 0 .. u32::MAX)).collect();
+        Manifest { id, version, imports, ranges }
+    }
+
+    pub fn allows_import(&self, name: &str) -> bool {
+        self.imports.contains(&name)
+    }
+
+    pub fn allows(&self, resource: Resource, index: u32) -> bool {
+        self.ranges.iter().any(|(x, range)| *x == resource && range.contains(&index))
+    }
+}
+
+struct Parser(&'static [u8]);
+
+impl Parser {
+    fn bytes(&mut self, len: usize) -> Option<&'static [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (result, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(result)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+
+    use super::*;
+
+    fn encode(words: &[&[u8]]) -> &'static [u8] {
+        Box::leak(words.concat().into_boxed_slice())
+    }
+
+    fn u32(x: u32) -> &'static [u8] {
+        Box::leak(Box::new(x.to_le_bytes()))
+    }
+
+    #[test]
+    fn parse_ok() {
+        let data = encode(&[
+            u32(7),
+            u32(2),
+            u32(2),
+            u32(2),
+            b"si",
+            u32(3),
+            b"lgc",
+            u32(2),
+            u32(0),
+            u32(0),
+            u32(10),
+            u32(2),
+            u32(1),
+            u32(2),
+        ]);
+        let manifest = Manifest::parse(data).unwrap();
+        assert_eq!(manifest.id, 7);
+        assert_eq!(manifest.version, 2);
+        assert_eq!(manifest.imports, ["si", "lgc"]);
+        assert_eq!(manifest.ranges, [(Resource::StoreKey, 0 .. 10), (Resource::Led, 1 .. 2)]);
+        assert!(manifest.allows_import("si"));
+        assert!(!manifest.allows_import("sr"));
+        assert!(manifest.allows(Resource::StoreKey, 9));
+        assert!(!manifest.allows(Resource::StoreKey, 10));
+        assert!(manifest.allows(Resource::Led, 1));
+        assert!(!manifest.allows(Resource::Led, 0));
+        assert!(!manifest.allows(Resource::Button, 1));
+    }
+
+    #[test]
+    fn parse_empty() {
+        let manifest = Manifest::parse(encode(&[u32(1), u32(0), u32(0), u32(0)])).unwrap();
+        assert!(manifest.imports.is_empty());
+        assert!(manifest.ranges.is_empty());
+        assert!(!manifest.allows(Resource::StoreKey, 0));
+    }
+
+    #[test]
+    fn fallback() {
+        let manifest = Manifest::fallback(0, 0, alloc::vec!["si"]);
+        assert!(manifest.allows_import("si"));
+        assert!(!manifest.allows_import("sr"));
+        for resource in Resource::ALL {
+            assert!(manifest.allows(resource, 0));
+            assert!(manifest.allows(resource, 255));
+        }
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let data = encode(&[u32(1), u32(0), u32(1), u32(2), b"si", u32(0)]);
+        assert!(Manifest::parse(data).is_some());
+        for len in 0 .. data.len() {
+            assert!(Manifest::parse(&data[.. len]).is_none(), "{len}");
+        }
+    }
+
+    #[test]
+    fn parse_trailing_bytes() {
+        assert!(Manifest::parse(encode(&[u32(1), u32(0), u32(0), u32(0), b"\0"])).is_none());
+    }
+
+    #[test]
+    fn parse_invalid_import() {
+        let data = encode(&[u32(1), u32(0), u32(1), u32(1), b"\xff", u32(0)]);
+        assert!(Manifest::parse(data).is_none());
+        let data = encode(&[u32(1), u32(0), u32(1), u32(u32::MAX), u32(0)]);
+        assert!(Manifest::parse(data).is_none());
+    }
+
+    #[test]
+    fn parse_invalid_resource() {
+        let data = encode(&[u32(1), u32(0), u32(0), u32(1), u32(3), u32(0), u32(1)]);
+        assert!(Manifest::parse(data).is_none());
+    }
+
+    #[test]
+    fn parse_large_count() {
+        let data = encode(&[u32(1), u32(0), u32(u32::MAX)]);
+        assert!(Manifest::parse(data).is_none());
+    }
+}
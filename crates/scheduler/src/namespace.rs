@@ -0,0 +1,360 @@
+// Copyright 2023 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-applet namespaces of the store.
+//!
+//! The first [`COUNT`] ranges of [`SIZE`] keys are namespaces. Each namespace is owned by at most
+//! one applet, identified by its manifest id, and is wiped before being owned by another applet.
+//! The last range of keys is reserved for the platform. Its first key holds the owner table.
+//!
+//! Stores written before namespaces existed don't have an owner table. They are migrated when the
+//! first applet loads: this applet owns the first namespace without wiping it, such that it keeps
+//! its entries in the first [`SIZE`] keys. The other namespaces holding entries are considered
+//! legacy: they are neither wiped nor owned by an applet, such that those entries are not lost. An
+//! entry at the key of the owner table is moved to a free key of the reserved range.
+
+use alloc::vec::Vec;
+
+use wasefire_store::{Storage, Store, StoreError, StoreResult};
+
+/// Number of keys of a namespace.
+pub const SIZE: usize = 256;
+
+/// Number of namespaces.
+pub const COUNT: usize = 15;
+
+/// Key of the owner table (the first key of the reserved range).
+const TABLE_KEY: usize = COUNT * SIZE;
+
+/// Prefix of the owner table, to tell it apart from entries written before namespaces existed.
+const MAGIC: [u8; 4] = *b"WfNs";
+
+/// Size of a store word in bytes.
+const WORD_SIZE: usize = 4;
+
+/// Owner of a namespace.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Owner {
+    /// The namespace is not used.
+    Free,
+
+    /// The namespace holds entries written before namespaces existed.
+    Legacy,
+
+    /// An applet with the given manifest id.
+    Applet(u32),
+}
+
+impl Owner {
+    fn decode(data: &[u8]) -> Option<Self> {
+        let id = u32::from_le_bytes(data[4 ..].try_into().unwrap());
+        Some(match data[.. 4] {
+            [0, 0, 0, 0] => Owner::Free,
+            [1, 0, 0, 0] => Owner::Legacy,
+            [2, 0, 0, 0] => Owner::Applet(id),
+            _ => return None,
+        })
+    }
+
+    fn encode(self) -> [u8; 8] {
+        let (tag, id) = match self {
+            Owner::Free => (0u32, 0),
+            Owner::Legacy => (1, 0),
+            Owner::Applet(id) => (2, id),
+        };
+        let mut result = [0; 8];
+        result[.. 4].copy_from_slice(&tag.to_le_bytes());
+        result[4 ..].copy_from_slice(&id.to_le_bytes());
+        result
+    }
+}
+
+/// Returns the store key of an applet key.
+pub fn key(namespace: usize, key: u32) -> Result<usize, StoreError> {
+    let key = key as usize;
+    if SIZE <= key {
+        return Err(StoreError::InvalidArgument);
+    }
+    Ok(namespace * SIZE + key)
+}
+
+/// Returns the namespace of an applet, given its manifest id.
+///
+/// If the applet doesn't own a namespace yet, a free one is wiped and assigned to it.
+pub fn assign<S: Storage>(store: &mut Store<S>, id: u32) -> StoreResult<usize> {
+    let mut table = match read_table(store)? {
+        Some(x) => x,
+        None => return migrate(store, id),
+    };
+    if let Some(namespace) = table.iter().position(|&x| x == Owner::Applet(id)) {
+        return Ok(namespace);
+    }
+    let namespace = table.iter().position(|&x| x == Owner::Free).ok_or(StoreError::NoCapacity)?;
+    // Free namespaces are usually empty, unless releasing them was interrupted.
+    wipe(store, namespace)?;
+    table[namespace] = Owner::Applet(id);
+    write_table(store, &table)?;
+    Ok(namespace)
+}
+
+/// Wipes and frees the namespace of an uninstalled applet, given its manifest id.
+///
+/// This does nothing if the applet doesn't own a namespace.
+pub fn release<S: Storage>(store: &mut Store<S>, id: u32) -> StoreResult<()> {
+    let mut table = match read_table(store)? {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+    let namespace = match table.iter().position(|&x| x == Owner::Applet(id)) {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+    wipe(store, namespace)?;
+    table[namespace] = Owner::Free;
+    write_table(store, &table)
+}
+
+/// Checks that inserting a value keeps its namespace within quota.
+///
+/// Each namespace may use an equal share of the store capacity (including the reserved range).
+/// Usage is measured like [`Store::capacity()`], so it accounts for the length of values.
+pub fn check_quota<S: Storage>(store: &Store<S>, key: usize, len: usize) -> StoreResult<()> {
+    let namespace = key / SIZE;
+    let quota = store.capacity()?.total() / (COUNT + 1);
+    let mut used = words(len);
+    for handle in store.iter()? {
+        let handle = handle?;
+        let other = handle.get_key();
+        if other / SIZE == namespace && other != key {
+            used += words(handle.get_length(store)?);
+        }
+    }
+    match used <= quota {
+        true => Ok(()),
+        false => Err(StoreError::NoCapacity),
+    }
+}
+
+/// Returns the capacity used by an entry in words.
+fn words(len: usize) -> usize {
+    1 + (len + WORD_SIZE - 1) / WORD_SIZE
+}
+
+/// Removes all entries of a namespace.
+fn wipe<S: Storage>(store: &mut Store<S>, namespace: usize) -> StoreResult<()> {
+    let mut keys = Vec::new();
+    for handle in store.iter()? {
+        let key = handle?.get_key();
+        if key / SIZE == namespace {
+            keys.push(key);
+        }
+    }
+    for key in keys {
+        store.remove(key)?;
+    }
+    Ok(())
+}
+
+/// Returns the owner table, or `None` if the store was written before namespaces existed.
+fn read_table<S: Storage>(store: &Store<S>) -> StoreResult<Option<Vec<Owner>>> {
+    let data = match store.find(TABLE_KEY)? {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    // An entry without the prefix was written before namespaces existed.
+    let data = match data.strip_prefix(&MAGIC) {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    if data.len() != 8 * COUNT {
+        return Err(StoreError::InvalidStorage);
+    }
+    let table = data.chunks_exact(8).map(|x| Owner::decode(x).ok_or(StoreError::InvalidStorage));
+    table.collect::<StoreResult<_>>().map(Some)
+}
+
+/// Writes the owner table of a store written before namespaces existed.
+///
+/// The first namespace is assigned to the given applet and returned.
+fn migrate<S: Storage>(store: &mut Store<S>, id: u32) -> StoreResult<usize> {
+    let mut table = alloc::vec![Owner::Free; COUNT];
+    let mut reserved = Vec::new();
+    for handle in store.iter()? {
+        let key = handle?.get_key();
+        match table.get_mut(key / SIZE) {
+            Some(owner) => *owner = Owner::Legacy,
+            None => reserved.push(key),
+        }
+    }
+    if let Some(data) = store.find(TABLE_KEY)? {
+        // If moving the entry is interrupted, it may be moved again to another key.
+        let key = (TABLE_KEY + 1 .. TABLE_KEY + SIZE)
+            .find(|x| !reserved.contains(x))
+            .ok_or(StoreError::NoCapacity)?;
+        store.insert(key, &data)?;
+    }
+    table[0] = Owner::Applet(id);
+    write_table(store, &table)?;
+    Ok(0)
+}
+
+fn write_table<S: Storage>(store: &mut Store<S>, table: &[Owner]) -> StoreResult<()> {
+    let mut data = MAGIC.to_vec();
+    data.extend(table.iter().flat_map(|x| x.encode()));
+    store.insert(TABLE_KEY, &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use wasefire_store::{BufferOptions, BufferStorage};
+
+    use super::*;
+
+    fn new_store() -> Store<BufferStorage> {
+        let options = BufferOptions {
+            word_size: 4,
+            page_size: 1024,
+            max_word_writes: 2,
+            max_page_erases: 10000,
+            strict_mode: true,
+        };
+        let storage = vec![0xff; 20 * 1024].into_boxed_slice();
+        Store::new(BufferStorage::new(storage, options)).ok().unwrap()
+    }
+
+    fn insert(store: &mut Store<BufferStorage>, namespace: usize, key: u32, value: &[u8]) {
+        store.insert(super::key(namespace, key).unwrap(), value).unwrap();
+    }
+
+    fn find(store: &Store<BufferStorage>, namespace: usize, key: u32) -> Option<Vec<u8>> {
+        store.find(super::key(namespace, key).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn key_ok() {
+        assert_eq!(key(0, 0), Ok(0));
+        assert_eq!(key(2, 255), Ok(767));
+        assert_eq!(key(0, 256), Err(StoreError::InvalidArgument));
+        assert_eq!(key(COUNT - 1, u32::MAX), Err(StoreError::InvalidArgument));
+    }
+
+    #[test]
+    fn assign_follows_id() {
+        let mut store = new_store();
+        assert_eq!(assign(&mut store, 7), Ok(0));
+        assert_eq!(assign(&mut store, 3), Ok(1));
+        insert(&mut store, 1, 5, b"secret");
+        // The load order doesn't matter.
+        assert_eq!(assign(&mut store, 3), Ok(1));
+        assert_eq!(assign(&mut store, 7), Ok(0));
+        assert_eq!(find(&store, 1, 5).as_deref(), Some(&b"secret"[..]));
+    }
+
+    #[test]
+    fn release_wipes_uninstalled() {
+        let mut store = new_store();
+        assert_eq!(assign(&mut store, 1), Ok(0));
+        assert_eq!(assign(&mut store, 2), Ok(1));
+        insert(&mut store, 0, 0, b"first");
+        insert(&mut store, 1, 0, b"second");
+        // The first applet is uninstalled and a new one takes its namespace.
+        release(&mut store, 1).unwrap();
+        assert_eq!(find(&store, 0, 0), None);
+        assert_eq!(assign(&mut store, 3), Ok(0));
+        assert_eq!(find(&store, 0, 0), None);
+        assert_eq!(assign(&mut store, 2), Ok(1));
+        assert_eq!(find(&store, 1, 0).as_deref(), Some(&b"second"[..]));
+    }
+
+    #[test]
+    fn assign_wipes_free() {
+        let mut store = new_store();
+        assert_eq!(assign(&mut store, 1), Ok(0));
+        release(&mut store, 1).unwrap();
+        // Simulates an interrupted release.
+        insert(&mut store, 0, 0, b"stale");
+        assert_eq!(assign(&mut store, 2), Ok(0));
+        assert_eq!(find(&store, 0, 0), None);
+    }
+
+    #[test]
+    fn assign_no_capacity() {
+        let mut store = new_store();
+        for id in 0 .. COUNT as u32 {
+            assert_eq!(assign(&mut store, id), Ok(id as usize));
+        }
+        assert_eq!(assign(&mut store, COUNT as u32), Err(StoreError::NoCapacity));
+    }
+
+    #[test]
+    fn release_unknown_is_noop() {
+        let mut store = new_store();
+        store.insert(5, b"legacy").unwrap();
+        release(&mut store, 1).unwrap();
+        assert_eq!(assign(&mut store, 1), Ok(0));
+        insert(&mut store, 0, 0, b"first");
+        release(&mut store, 2).unwrap();
+        assert_eq!(find(&store, 0, 0).as_deref(), Some(&b"first"[..]));
+        assert_eq!(find(&store, 0, 5).as_deref(), Some(&b"legacy"[..]));
+    }
+
+    #[test]
+    fn legacy_entries_are_migrated() {
+        let mut store = new_store();
+        for key in [5, 300, 4095] {
+            store.insert(key, b"legacy").unwrap();
+        }
+        // The first applet keeps the legacy entries of the first namespace.
+        assert_eq!(assign(&mut store, 1), Ok(0));
+        assert_eq!(find(&store, 0, 5).as_deref(), Some(&b"legacy"[..]));
+        assert_eq!(assign(&mut store, 2), Ok(2));
+        release(&mut store, 2).unwrap();
+        assert_eq!(assign(&mut store, 3), Ok(2));
+        assert_eq!(assign(&mut store, 1), Ok(0));
+        for key in [5, 300, 4095] {
+            assert_eq!(store.find(key).unwrap().as_deref(), Some(&b"legacy"[..]));
+        }
+    }
+
+    #[test]
+    fn legacy_entry_at_table_key() {
+        let mut store = new_store();
+        store.insert(TABLE_KEY, b"legacy").unwrap();
+        store.insert(TABLE_KEY + 1, b"other").unwrap();
+        assert_eq!(assign(&mut store, 1), Ok(0));
+        assert_eq!(store.find(TABLE_KEY + 1).unwrap().as_deref(), Some(&b"other"[..]));
+        assert_eq!(store.find(TABLE_KEY + 2).unwrap().as_deref(), Some(&b"legacy"[..]));
+        assert_eq!(assign(&mut store, 2), Ok(1));
+        assert_eq!(assign(&mut store, 1), Ok(0));
+    }
+
+    #[test]
+    fn quota_counts_bytes() {
+        let mut store = new_store();
+        let quota = store.capacity().unwrap().total() / (COUNT + 1);
+        let len = (quota - 1) * WORD_SIZE;
+        assert_eq!(check_quota(&store, 0, len), Ok(()));
+        assert_eq!(check_quota(&store, 0, len + 1), Err(StoreError::NoCapacity));
+        insert(&mut store, 0, 0, &[0; 100]);
+        // Replacing a value doesn't count the old one.
+        assert_eq!(check_quota(&store, 0, len), Ok(()));
+        // Each entry also uses a word for its header.
+        assert_eq!(check_quota(&store, 1, len - 104), Ok(()));
+        assert_eq!(check_quota(&store, 1, len - 103), Err(StoreError::NoCapacity));
+        // Other namespaces are not affected.
+        assert_eq!(check_quota(&store, SIZE, len), Ok(()));
+    }
+}
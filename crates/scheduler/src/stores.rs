@@ -13,39 +13,82 @@
 // limitations under the License.
 
 use alloc::collections::{BTreeSet, VecDeque};
+use alloc::vec::Vec;
 
+use wasefire_applet_api::{Api, Id};
 use wasefire_board_api::{self as board, Api as Board, Event};
-use wasefire_interpreter::Store;
+use wasefire_interpreter::{Linker, Store, TrapReason};
 use wasefire_logger as log;
 
 use crate::event::{Handler, Key};
+use crate::manifest::{Manifest, Resource};
 use crate::{Memory, Trap};
 
 pub struct Applet<B: Board> {
     pub store: AppletStore,
 
+    /// Module of the applet, to instantiate it again when restarting.
+    pub wasm: &'static [u8],
+
+    /// Linear memory of the applet, lent to the store while instantiated.
+    memory: *mut [u8],
+
+    /// Manifest of the applet.
+    pub manifest: Manifest,
+
+    /// Store namespace of the applet.
+    pub namespace: usize,
+
+    /// Host functions the applet may import.
+    pub linker: &'static Linker<'static>,
+
+    /// Host functions in the order of the linker.
+    pub host_funcs: Vec<Api<Id>>,
+
+    /// Whether the applet was stopped after a trap.
+    stopped: bool,
+
+    /// Traps of the applet since the platform started.
+    pub traps: TrapRecord,
+
     /// Pending events.
     events: VecDeque<Event<B>>,
 
     /// Whether we returned from a callback.
     done: bool,
 
+    /// Whether the applet waits for events.
+    waiting: bool,
+
     handlers: BTreeSet<Handler<B>>,
 
     pub hashes: AppletHashes<B>,
 }
 
-// We have to implement manually because derive is not able to find the correct bounds.
-impl<B: Board> Default for Applet<B> {
-    fn default() -> Self {
-        Self {
-            store: Default::default(),
-            events: Default::default(),
-            done: Default::default(),
-            handlers: Default::default(),
-            hashes: Default::default(),
-        }
-    }
+/// Traps of an applet.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TrapRecord {
+    /// Number of traps.
+    pub count: usize,
+
+    /// Reason of the last trap.
+    pub last: Option<Fault>,
+}
+
+/// Reasons for an applet to trap.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Execution trapped.
+    Trap(TrapReason),
+
+    /// The `init` function didn't return.
+    ///
+    /// This happens when it calls into the host, runs out of fuel, hits a breakpoint, waits or
+    /// notifies, or fails to execute.
+    Init,
+
+    /// The applet waits without timeout for a notification that can't happen.
+    WaitForever,
 }
 
 #[derive(Debug, Default)]
@@ -91,6 +134,56 @@ impl<B: Board> AppletHashes<B> {
 }
 
 impl<B: Board> Applet<B> {
+    pub fn new(
+        wasm: &'static [u8], memory: &'static mut [u8], manifest: Manifest, namespace: usize,
+        linker: &'static Linker<'static>, host_funcs: Vec<Api<Id>>,
+    ) -> Self {
+        Self {
+            store: Default::default(),
+            wasm,
+            memory,
+            manifest,
+            namespace,
+            linker,
+            host_funcs,
+            stopped: false,
+            traps: Default::default(),
+            events: Default::default(),
+            done: false,
+            waiting: false,
+            handlers: Default::default(),
+            hashes: Default::default(),
+        }
+    }
+
+    /// Resets the applet to the given store and returns its memory.
+    ///
+    /// Events, handlers, and hash contexts are dropped. The trap record is preserved.
+    pub fn reset(&mut self, store: Store<'static>) -> &'static mut [u8] {
+        // Dropping the previous store ends its borrow of the memory.
+        self.store = AppletStore(store);
+        self.events.clear();
+        self.done = false;
+        self.waiting = false;
+        self.handlers.clear();
+        self.hashes = Default::default();
+        // SAFETY: The memory is leaked and only borrowed by the store, which was just replaced.
+        unsafe { &mut *self.memory }
+    }
+
+    pub fn set_stopped(&mut self, stopped: bool) {
+        self.stopped = stopped;
+    }
+
+    /// Checks that the manifest allows accessing a resource.
+    pub fn check(&self, resource: Resource, index: u32) -> Result<(), Trap> {
+        if !self.manifest.allows(resource, index) {
+            log::warn!("Tried to access undeclared {} {}", log::Debug2Format(&resource), index);
+            return Err(Trap);
+        }
+        Ok(())
+    }
+
     pub fn store_mut(&mut self) -> &mut Store<'static> {
         &mut self.store.0
     }
@@ -117,15 +210,24 @@ impl<B: Board> Applet<B> {
 
     /// Returns the next event action.
     pub fn pop(&mut self) -> EventAction<B> {
+        self.waiting = false;
         if core::mem::replace(&mut self.done, false) {
             return EventAction::Reply;
         }
         match self.events.pop_front() {
             Some(event) => EventAction::Handle(event),
-            None => EventAction::Wait,
+            None => {
+                self.waiting = true;
+                EventAction::Wait
+            }
         }
     }
 
+    /// Returns whether the applet can make progress.
+    pub fn ready(&self) -> bool {
+        !self.stopped && (!self.waiting || self.done || !self.events.is_empty())
+    }
+
     pub fn done(&mut self) {
         self.done = true;
     }
@@ -151,6 +253,11 @@ impl<B: Board> Applet<B> {
         }
     }
 
+    /// Returns the keys of the enabled handlers.
+    pub fn keys(&self) -> impl Iterator<Item = Key<B>> + '_ {
+        self.handlers.iter().map(|x| x.key)
+    }
+
     pub fn get(&self, key: Key<B>) -> Option<&Handler<B>> {
         self.handlers.get(&key)
     }
@@ -172,3 +279,99 @@ pub enum EventAction<B: Board> {
     /// Should suspend execution until an event is available.
     Wait,
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec;
+
+    use wasefire_board_api::radio::Event as RadioEvent;
+    use wasefire_board_api::usb::serial::Event as SerialEvent;
+    use wasefire_board_api::usb::Event as UsbEvent;
+    use wasefire_interpreter::{InstId, Module};
+
+    use super::*;
+    use crate::event::{radio, usb};
+    use crate::tests::TestBoard;
+
+    type B = TestBoard;
+
+    fn applet() -> Applet<B> {
+        let memory = Box::leak(vec![0; 16].into_boxed_slice());
+        let linker = Box::leak(Box::default());
+        let manifest = Manifest { id: 0, version: 0, imports: Vec::new(), ranges: Vec::new() };
+        Applet::new(b"", memory, manifest, 0, linker, Vec::new())
+    }
+
+    fn inst() -> InstId {
+        const WASM: &[u8] = b"\0asm\x01\0\0\0";
+        let mut store = Store::default();
+        store.instantiate(Module::new(WASM).unwrap(), vec![]).unwrap()
+    }
+
+    fn handler(key: Key<B>, inst: InstId) -> Handler<B> {
+        Handler { key, inst, func: 0, data: 0 }
+    }
+
+    const RADIO: Event<B> = Event::Radio(RadioEvent::Received);
+    const SERIAL_READ: Event<B> = Event::Usb(UsbEvent::Serial(SerialEvent::Read));
+    const SERIAL_WRITE: Event<B> = Event::Usb(UsbEvent::Serial(SerialEvent::Write));
+
+    #[test]
+    fn push_without_handler_is_discarded() {
+        let mut applet = applet();
+        applet.push(RADIO);
+        assert_eq!(applet.len(), 0);
+        assert!(matches!(applet.pop(), EventAction::Wait));
+        assert!(!applet.ready());
+    }
+
+    #[test]
+    fn push_merges_and_pops_in_order() {
+        let mut applet = applet();
+        let inst = inst();
+        applet.enable(handler(Key::Radio(radio::Key::Received), inst)).unwrap();
+        applet.enable(handler(Key::Usb(usb::Key::Serial(usb::serial::Key::Read)), inst)).unwrap();
+        applet.push(SERIAL_READ);
+        applet.push(RADIO);
+        applet.push(SERIAL_READ);
+        applet.push(SERIAL_WRITE);
+        assert_eq!(applet.len(), 2);
+        assert!(matches!(applet.pop(), EventAction::Handle(x) if x == SERIAL_READ));
+        assert!(matches!(applet.pop(), EventAction::Handle(x) if x == RADIO));
+        assert!(matches!(applet.pop(), EventAction::Wait));
+    }
+
+    #[test]
+    fn enable_and_disable_handlers() {
+        let mut applet = applet();
+        let inst = inst();
+        let key = Key::Radio(radio::Key::Received);
+        assert!(applet.disable(key).is_err());
+        applet.enable(handler(key, inst)).unwrap();
+        assert!(applet.enable(handler(key, inst)).is_err());
+        assert_eq!(applet.keys().collect::<Vec<_>>(), [key]);
+        applet.push(RADIO);
+        applet.disable(key).unwrap();
+        assert_eq!(applet.len(), 0);
+        assert!(applet.get(key).is_none());
+    }
+
+    #[test]
+    fn ready_follows_events() {
+        let mut applet = applet();
+        applet.enable(handler(Key::Radio(radio::Key::Received), inst())).unwrap();
+        assert!(applet.ready());
+        assert!(matches!(applet.pop(), EventAction::Wait));
+        assert!(!applet.ready());
+        applet.push(RADIO);
+        assert!(applet.ready());
+        assert!(matches!(applet.pop(), EventAction::Handle(_)));
+        applet.done();
+        assert!(applet.ready());
+        assert!(matches!(applet.pop(), EventAction::Reply));
+        applet.set_stopped(true);
+        applet.push(RADIO);
+        assert!(!applet.ready());
+    }
+}
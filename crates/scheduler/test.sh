@@ -17,6 +17,7 @@ set -ex
 
 cargo check --features=std
 cargo check --features=std,log
+cargo check --features=std,log,profile
 cargo check --target=thumbv7em-none-eabi
 cargo check --target=thumbv7em-none-eabi --features=defmt
 cargo fmt -- --check
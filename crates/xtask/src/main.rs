@@ -147,6 +147,12 @@ struct RunnerOptions {
     #[clap(long)]
     features: Vec<String>,
 
+    /// Runs unsigned applets (insecure, only meant for development).
+    ///
+    /// Otherwise, the runner only runs applets signed with the key in `WASEFIRE_APPLET_KEY`.
+    #[clap(long)]
+    unsigned: bool,
+
     /// Optimization level (0, 1, 2, 3, s, z).
     #[clap(long, short = 'O', default_value_t)]
     opt_level: OptLevel,
@@ -439,6 +445,9 @@ impl RunnerOptions {
         for features in &self.features {
             cargo.arg(format!("--features={features}"));
         }
+        if self.unsigned {
+            cargo.arg("--features=unsigned");
+        }
         if let Some(log) = &self.log {
             cargo.env(self.log_env(), log);
         }
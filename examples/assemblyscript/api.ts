@@ -734,7 +734,7 @@
   export declare function store_insert(
     // Key of the entry.
     //
-    // This must be smaller than 4096.
+    // This must be smaller than 256.
     key: usize,
 
     // Value of the entry.
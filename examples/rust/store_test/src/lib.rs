@@ -67,17 +67,17 @@ fn test_find() {
     }
 }
 
-const INSERTED: &[usize] = &[0, 1, 2, 3, 100, 500, 1000, 2000];
-const REMOVED: &[usize] = &[2, 500];
+const INSERTED: &[usize] = &[0, 1, 2, 3, 10, 30, 60, 120];
+const REMOVED: &[usize] = &[2, 30];
 
 fn reverse(key: usize) -> usize {
-    4095 - key
+    255 - key
 }
 
 fn value(mut key: usize) -> Vec<u8> {
-    let reversed = key & 0x800 != 0;
+    let reversed = key & 0x80 != 0;
     if reversed {
-        key = 4095 - key;
+        key = 255 - key;
     }
     let val = key as u8;
     let mut len = val as usize;
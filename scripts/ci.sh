@@ -42,8 +42,8 @@ done
 for crate in $(ls crates); do
   name=${crate#runner-}
   [ $crate = $name ] && continue
-  x cargo xtask runner $name --log=trace
-  x cargo xtask --release runner $name
+  x cargo xtask runner $name --unsigned --log=trace
+  x cargo xtask --release runner $name --unsigned
 done
 
 for dir in $(find . -name test.sh -printf '%h\n' | sort); do
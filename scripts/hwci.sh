@@ -31,11 +31,11 @@ features() {
 }
 
 for name in $(list); do
-  x cargo xtask applet rust $name runner "$@"
+  x cargo xtask applet rust $name runner "$@" --unsigned
   for feature in $(cd examples/rust/$name && features); do
-    x cargo xtask applet rust $name --features=$feature runner "$@"
+    x cargo xtask applet rust $name --features=$feature runner "$@" --unsigned
   done
 done
 for name in $(list); do
-  x cargo xtask --release applet rust $name runner "$@"
+  x cargo xtask --release applet rust $name runner "$@" --unsigned
 done